@@ -41,7 +41,7 @@ async fn process_message(message: SqsMessage) -> Result<(), Error> {
 
     let message: MyMessage = match cloud_event_data {
         cloudevents::Data::Binary(items) => serde_json::from_slice(items)?,
-        cloudevents::Data::String(string_data) => serde_json::from_str(&string_data)?,
+        cloudevents::Data::String(string_data) => serde_json::from_str(string_data)?,
         cloudevents::Data::Json(value) => serde_json::from_value(value.clone())?,
     };
 
@@ -61,7 +61,7 @@ async fn publish_message(cloud_event: &cloudevents::Event) {
     let config = aws_config::load_defaults(BehaviorVersion::latest()).await;
     let sqs_client = aws_sdk_sqs::Client::new(&config);
 
-    tracing::Span::current().record("messaging.message.id", &cloud_event.id().to_string());
+    tracing::Span::current().record("messaging.message.id", cloud_event.id().to_string());
 
     // Here you would publish the message to your messaging system
     tracing::info!(
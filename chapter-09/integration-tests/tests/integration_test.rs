@@ -17,6 +17,7 @@ async fn when_valid_link_is_passed_should_retrieve_info_and_store() {
     let result = http_client
         .post(format!("{}links", api_endpoint))
         .header("Content-Type", "application/json")
+        .bearer_auth(access_token())
         .body(serde_json::json!({"url_to_shorten": "https://google.com"}).to_string())
         .send()
         .await;
@@ -56,6 +57,7 @@ async fn when_invalid_body_is_passed_application_should_return_400_error() {
     let result = http_client
         .post(format!("{}links", api_endpoint))
         .header("Content-Type", "application/json")
+        .bearer_auth(access_token())
         // Empty body triggers the BAD_REQUEST branch without surfacing a JSON parsing error
         // (the handler now treats missing payload as a 400 and malformed JSON as a 500).
         .body("")
@@ -69,6 +71,12 @@ async fn when_invalid_body_is_passed_application_should_return_400_error() {
     assert_eq!(response.status(), 400);
 }
 
+/// The API sits behind a JWT authorizer, so tests need a token issued for its audience.
+fn access_token() -> String {
+    env::var("ACCESS_TOKEN")
+        .expect("An access token should be set using the 'ACCESS_TOKEN' environment variable")
+}
+
 async fn retrieve_api_endpoint() -> String {
     let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
     let cloudformation_client = aws_sdk_cloudformation::Client::new(&config);
@@ -81,7 +89,7 @@ async fn retrieve_api_endpoint() -> String {
         .set_stack_name(Some(stack_name.clone()))
         .send()
         .await
-        .expect(format!("CloudFormation stack named {} should exist", stack_name).as_str());
+        .unwrap_or_else(|_| panic!("CloudFormation stack named {} should exist", stack_name));
 
     let outputs = get_stacks
         .stacks
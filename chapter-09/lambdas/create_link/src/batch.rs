@@ -0,0 +1,377 @@
+use crate::event_publisher::EventPublisher;
+use crate::http_handler::HandlerDeps;
+use futures::future::join_all;
+use futures::stream::{self, StreamExt};
+use lambda_http::{
+    http::header::CONTENT_TYPE, http::StatusCode, tracing, Body, Error, Request, Response,
+};
+use serde::{Deserialize, Serialize};
+use shared::configuration::Configuration;
+use shared::core::{IdGenerator, ShortUrl, UrlRepository};
+use shared::normalise::url_hash;
+use shared::rate_limit::RateLimiter;
+use shared::utils::{json_error_response, json_response, repository_error_response};
+use std::collections::HashSet;
+
+/// The most URLs a single `POST /links:batch` request may contain.
+pub const MAX_BATCH_SIZE: usize = 500;
+/// How many LinkCreated events are published at the same time.
+const PUBLISH_CONCURRENCY: usize = 10;
+
+/// A JSON batch entry: either the bare URL or the same shape as a single create.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BatchEntry {
+    Url(String),
+    Request { url_to_shorten: String },
+}
+
+/// The outcome for one URL of the batch, in the order they were sent.
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum BatchItemResult {
+    Created {
+        url_to_shorten: String,
+        short_url: Box<ShortUrl>,
+    },
+    Failed {
+        url_to_shorten: String,
+        error: String,
+    },
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchResponse {
+    pub created: usize,
+    pub failed: usize,
+    pub results: Vec<BatchItemResult>,
+}
+
+/// Reads the URLs from a JSON array, or from the first column of a CSV body
+/// when the content type is `text/csv`. A CSV header row is skipped.
+fn parse_urls(event: &Request) -> Result<Vec<String>, String> {
+    let is_csv = event
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with("text/csv"));
+    let body: &[u8] = event.body().as_ref();
+
+    if !is_csv {
+        let entries: Vec<BatchEntry> = serde_json::from_slice(body)
+            .map_err(|_| "body must be a JSON array of URLs".to_string())?;
+        return Ok(entries
+            .into_iter()
+            .map(|entry| match entry {
+                BatchEntry::Url(url)
+                | BatchEntry::Request {
+                    url_to_shorten: url,
+                } => url,
+            })
+            .collect());
+    }
+
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(body);
+    let mut urls = Vec::new();
+    for (row, record) in reader.records().enumerate() {
+        let record = record.map_err(|e| format!("invalid CSV: {}", e))?;
+        let Some(url) = record.get(0).map(str::trim).filter(|url| !url.is_empty()) else {
+            continue;
+        };
+        if row == 0 && ["url", "url_to_shorten"].contains(&url.to_ascii_lowercase().as_str()) {
+            continue;
+        }
+        urls.push(url.to_string());
+    }
+    Ok(urls)
+}
+
+/// Handles `POST /links:batch`: shortens every URL of the body with a generated
+/// id, writes them in bulk and publishes a LinkCreated event for each new link.
+pub(crate) async fn create_links<
+    I: IdGenerator,
+    R: UrlRepository,
+    E: EventPublisher,
+    L: RateLimiter,
+>(
+    deps: &HandlerDeps<I, R, E, L>,
+    configuration: &Configuration,
+    owner_id: &str,
+    event: &Request,
+) -> Result<Response<Body>, Error> {
+    let urls = match parse_urls(event) {
+        Ok(urls) => urls,
+        Err(e) => return json_error_response(&StatusCode::BAD_REQUEST, &e),
+    };
+    if urls.is_empty() {
+        return json_error_response(&StatusCode::BAD_REQUEST, "batch must contain a URL");
+    }
+    if urls.len() > MAX_BATCH_SIZE {
+        return json_error_response(
+            &StatusCode::BAD_REQUEST,
+            &format!("batch must contain at most {} URLs", MAX_BATCH_SIZE),
+        );
+    }
+
+    // Invalid URLs fail on their own, they don't stop the rest of the batch
+    let allowed = join_all(urls.iter().map(|url| async {
+        deps.url_policy
+            .validate(url)
+            .await
+            .map_err(|e| e.to_string())?;
+        configuration
+            .domain_policy
+            .check(url)
+            .map_err(|e| e.to_string())
+    }))
+    .await;
+    let candidates: Vec<Result<ShortUrl, String>> = urls
+        .iter()
+        .zip(allowed)
+        .map(|(url, allowed)| match (url_hash(url), allowed) {
+            (Err(_), _) => Err("url_to_shorten must be an absolute URL".to_string()),
+            (Ok(_), Err(e)) => Err(e),
+            (Ok(url_hash), Ok(())) => Ok(ShortUrl {
+                owner_id: Some(owner_id.to_string()),
+                url_hash: Some(url_hash),
+                ..ShortUrl::new(deps.id_generator.generate_id(), url.clone())
+            }),
+        })
+        .collect();
+    let to_store: Vec<ShortUrl> = candidates
+        .iter()
+        .filter_map(|candidate| candidate.as_ref().ok().cloned())
+        .collect();
+
+    let written: HashSet<String> = if to_store.is_empty() {
+        HashSet::new()
+    } else {
+        match deps.url_repo.store_short_urls(to_store).await {
+            Ok(written) => written
+                .into_iter()
+                .map(|short_url| short_url.link_id)
+                .collect(),
+            Err(e) => {
+                tracing::error!("Failed to store batch: {:?}", e);
+                return repository_error_response(&e);
+            }
+        }
+    };
+
+    let results: Vec<BatchItemResult> = urls
+        .into_iter()
+        .zip(candidates)
+        .map(|(url_to_shorten, candidate)| match candidate {
+            Ok(short_url) if written.contains(&short_url.link_id) => BatchItemResult::Created {
+                url_to_shorten,
+                short_url: Box::new(short_url),
+            },
+            Ok(_) => BatchItemResult::Failed {
+                url_to_shorten,
+                error: "link could not be stored".to_string(),
+            },
+            Err(error) => BatchItemResult::Failed {
+                url_to_shorten,
+                error,
+            },
+        })
+        .collect();
+
+    stream::iter(results.iter().filter_map(|result| match result {
+        BatchItemResult::Created { short_url, .. } => Some(short_url.as_ref()),
+        BatchItemResult::Failed { .. } => None,
+    }))
+    .for_each_concurrent(PUBLISH_CONCURRENCY, |short_url| async move {
+        if let Err(e) = deps.event_publisher.publish_link_created(short_url).await {
+            tracing::error!("Failed to publish link created event: {:?}", e);
+        }
+    })
+    .await;
+
+    let created = results
+        .iter()
+        .filter(|result| matches!(result, BatchItemResult::Created { .. }))
+        .count();
+    json_response(
+        &StatusCode::OK,
+        &BatchResponse {
+            created,
+            failed: results.len() - created,
+            results,
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::event_publisher::MockEventPublisher;
+    use crate::http_handler::tests::create_deps;
+    use crate::http_handler::{function_handler, HandlerDeps};
+    use lambda_http::http::Request;
+    use lambda_http::{Body, IntoResponse, RequestExt};
+    use mockall::predicate::function;
+    use serde_json::{json, Value};
+    use shared::auth::jwt_request_context;
+    use shared::core::{MockIdGenerator, MockUrlRepository, RepositoryError, ShortUrl};
+    use shared::rate_limit::InMemoryRateLimiter;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn create_request(content_type: &str, body: String) -> lambda_http::Request {
+        Request::builder()
+            .method("POST")
+            .uri("/links:batch")
+            .header("Content-Type", content_type)
+            .body(Body::from(body))
+            .unwrap()
+            .with_request_context(jwt_request_context("user-1", &[]))
+    }
+
+    fn sequential_ids() -> MockIdGenerator {
+        let next_id = AtomicUsize::new(0);
+        let mut id_generator = MockIdGenerator::new();
+        id_generator
+            .expect_generate_id()
+            .returning(move || format!("id{}", next_id.fetch_add(1, Ordering::SeqCst)));
+        id_generator
+    }
+
+    async fn send(
+        deps: &HandlerDeps<
+            MockIdGenerator,
+            MockUrlRepository,
+            MockEventPublisher,
+            InMemoryRateLimiter,
+        >,
+        request: lambda_http::Request,
+    ) -> (u16, Value) {
+        let data = function_handler(deps, request)
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+        let body = serde_json::from_slice(data.body()).unwrap_or(Value::Null);
+        (data.status().as_u16(), body)
+    }
+
+    #[tokio::test]
+    async fn when_json_batch_is_valid_should_store_all_and_publish_each() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo
+            .expect_store_short_urls()
+            .times(1)
+            .with(function(|short_urls: &Vec<ShortUrl>| {
+                short_urls.len() == 2
+                    && short_urls
+                        .iter()
+                        .all(|short_url| short_url.owner_id.as_deref() == Some("user-1"))
+            }))
+            .returning(Ok);
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher
+            .expect_publish_link_created()
+            .times(2)
+            .returning(|_| Ok(()));
+        let deps = create_deps(sequential_ids(), mock_url_repo, event_publisher);
+        let body = json!(["https://example.com/a", {"url_to_shorten": "https://example.com/b"}]);
+
+        let (status, body) =
+            send(&deps, create_request("application/json", body.to_string())).await;
+
+        assert_eq!(status, 200);
+        assert_eq!(body["created"], 2);
+        assert_eq!(body["results"][0]["status"], "created");
+        assert_eq!(body["results"][0]["short_url"]["link_id"], "id0");
+        assert_eq!(
+            body["results"][1]["short_url"]["original_link"],
+            "https://example.com/b"
+        );
+    }
+
+    #[tokio::test]
+    async fn when_csv_batch_has_invalid_and_unprocessed_rows_should_report_each() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo
+            .expect_store_short_urls()
+            .times(1)
+            .returning(|short_urls| {
+                // The second valid link stays unprocessed
+                Ok(short_urls.into_iter().take(1).collect())
+            });
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher
+            .expect_publish_link_created()
+            .times(1)
+            .returning(|_| Ok(()));
+        let deps = create_deps(sequential_ids(), mock_url_repo, event_publisher);
+        let csv = "url,campaign\nhttps://example.com/a,spring\nnot a url,\nhttp://internal.example/admin,\n\"https://example.com/b?x=1,2\",summer\n";
+
+        let (status, body) = send(&deps, create_request("text/csv", csv.to_string())).await;
+
+        assert_eq!(status, 200);
+        assert_eq!(body["created"], 1);
+        assert_eq!(body["failed"], 3);
+        let statuses: Vec<&str> = body["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|result| result["status"].as_str().unwrap())
+            .collect();
+        assert_eq!(statuses, ["created", "failed", "failed", "failed"]);
+        assert_eq!(
+            body["results"][2]["error"],
+            "host 'internal.example' points to the non-public address 10.0.0.1"
+        );
+        assert_eq!(
+            body["results"][3]["url_to_shorten"],
+            "https://example.com/b?x=1,2"
+        );
+    }
+
+    #[tokio::test]
+    async fn when_batch_is_empty_or_too_large_should_return_400() {
+        let too_large: Vec<String> = (0..=super::MAX_BATCH_SIZE)
+            .map(|i| format!("https://example.com/{}", i))
+            .collect();
+        let cases = [
+            create_request("application/json", "[]".to_string()),
+            create_request("application/json", "{\"url\": 1}".to_string()),
+            create_request("application/json", json!(too_large).to_string()),
+        ];
+
+        for request in cases {
+            let mut mock_url_repo = MockUrlRepository::default();
+            mock_url_repo.expect_store_short_urls().times(0);
+            let deps = create_deps(sequential_ids(), mock_url_repo, MockEventPublisher::new());
+
+            let (status, _) = send(&deps, request).await;
+
+            assert_eq!(status, 400);
+        }
+    }
+
+    #[tokio::test]
+    async fn when_nothing_could_be_stored_should_return_the_repository_error() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo
+            .expect_store_short_urls()
+            .times(1)
+            .returning(|_| Err(RepositoryError::Throttled("slow down".to_string())));
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher.expect_publish_link_created().times(0);
+        let deps = create_deps(sequential_ids(), mock_url_repo, event_publisher);
+
+        let (status, _) = send(
+            &deps,
+            create_request(
+                "application/json",
+                json!(["https://example.com"]).to_string(),
+            ),
+        )
+        .await;
+
+        assert_eq!(status, 429);
+    }
+}
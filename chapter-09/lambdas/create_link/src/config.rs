@@ -1,17 +1,130 @@
 use figment::providers::Env;
 use figment::Figment;
 use serde::{Deserialize, Serialize};
+use shared::jwt::JwtSettings;
+use shared::rate_limit::RateLimit;
+use shared::slug::SlugPolicy;
+use shared::url_policy::UrlPolicy;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub(crate) struct Config {
     pub table_name: String,
+    /// Keep links in memory rather than in DynamoDB, see `adapters::url_repository`.
+    #[serde(default)]
+    pub in_memory_repository: bool,
     pub queue_url: String,
+    pub rate_limits_table_name: String,
+    #[serde(default)]
+    pub rate_limits: RateLimits,
+    #[serde(default)]
+    pub slug_policy: SlugPolicy,
+    #[serde(default)]
+    pub url_policy: UrlPolicy,
+    /// Set when bearer tokens are checked here rather than only by API Gateway.
+    #[serde(default)]
+    pub jwt: Option<JwtSettings>,
+    /// Whether requests that don't say otherwise reuse an existing link.
+    #[serde(default)]
+    pub dedupe: bool,
+}
+
+/// How many links a client can create, per API key or caller and per IP address.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct RateLimits {
+    pub per_key: RateLimit,
+    pub per_ip: RateLimit,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self {
+            per_key: RateLimit {
+                capacity: 60,
+                refill_per_second: 1.0,
+            },
+            per_ip: RateLimit {
+                capacity: 30,
+                refill_per_second: 0.5,
+            },
+        }
+    }
 }
 
 impl Config {
-    pub fn load() -> Result<Self, figment::Error> {
+    pub fn load() -> Result<Self, Box<figment::Error>> {
         Figment::new()
-            .merge(Env::raw().only(&["TABLE_NAME", "QUEUE_URL"]))
+            .merge(Env::raw().only(&[
+                "TABLE_NAME",
+                "IN_MEMORY_REPOSITORY",
+                "QUEUE_URL",
+                "DEDUPE",
+                "RATE_LIMITS_TABLE_NAME",
+            ]))
+            // e.g. SLUG_MAX_LENGTH=32 or SLUG_RESERVED=[links,admin]
+            .merge(Env::prefixed("SLUG_").map(|key| format!("slug_policy.{}", key).into()))
+            // e.g. URL_MAX_LENGTH=1024
+            .merge(Env::prefixed("URL_").map(|key| format!("url_policy.{}", key).into()))
+            // e.g. RATE_LIMIT_PER_IP={capacity=10,refill_per_second=0.2}
+            .merge(Env::prefixed("RATE_LIMIT_").map(|key| format!("rate_limits.{}", key).into()))
+            // e.g. JWT_ISSUER, JWT_AUDIENCE and JWT_JWKS_URL or JWT_JWKS
+            .merge(Env::prefixed("JWT_").map(|key| format!("jwt.{}", key).into()))
             .extract()
+            .map_err(Box::new)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::result_large_err)] // figment::Jail returns a large figment::Error
+mod tests {
+    use super::Config;
+
+    #[test]
+    fn when_slug_variables_are_set_should_load_slug_policy() {
+        figment::Jail::expect_with(|jail| {
+            jail.set_env("TABLE_NAME", "links");
+            jail.set_env("QUEUE_URL", "https://sqs.local/queue");
+            jail.set_env("RATE_LIMITS_TABLE_NAME", "rate-limits");
+            jail.set_env("RATE_LIMIT_PER_IP", "{capacity=10,refill_per_second=0.2}");
+            jail.set_env("SLUG_MAX_LENGTH", "32");
+            jail.set_env("SLUG_RESERVED", "[links, admin]");
+
+            let config = Config::load().unwrap();
+
+            assert_eq!(config.slug_policy.max_length, 32);
+            assert_eq!(config.slug_policy.min_length, 3);
+            assert_eq!(config.slug_policy.reserved, vec!["links", "admin"]);
+            assert_eq!(config.url_policy.max_length, 2048);
+            assert_eq!(config.rate_limits.per_ip.capacity, 10);
+            assert_eq!(config.rate_limits.per_key.capacity, 60);
+
+            assert!(config.jwt.is_none());
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn when_jwt_variables_are_set_should_load_jwt_settings() {
+        figment::Jail::expect_with(|jail| {
+            jail.set_env("TABLE_NAME", "links");
+            jail.set_env("QUEUE_URL", "https://sqs.local/queue");
+            jail.set_env("RATE_LIMITS_TABLE_NAME", "rate-limits");
+            jail.set_env("JWT_ISSUER", "https://id.example.com");
+            jail.set_env("JWT_AUDIENCE", "links-api");
+            jail.set_env("JWT_JWKS", r#"{"keys": [{"kty": "EC", "crv": "P-256"}]}"#);
+
+            let jwt = Config::load().unwrap().jwt.unwrap();
+
+            assert_eq!(jwt.issuer, "https://id.example.com");
+            assert_eq!(jwt.audience, "links-api");
+            assert_eq!(
+                jwt.jwks.as_deref(),
+                Some(r#"{"keys": [{"kty": "EC", "crv": "P-256"}]}"#)
+            );
+            assert_eq!(jwt.jwks_url, None);
+
+            Ok(())
+        });
     }
 }
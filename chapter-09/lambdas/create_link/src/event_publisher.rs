@@ -73,7 +73,7 @@ impl EventPublisher for SqsEventBridgePublisher {
             .extension("traceparent", trace_parent)
             .build()
             .unwrap();
-        tracing::Span::current().record("messaging.message.id", &event.id().to_string());
+        tracing::Span::current().record("messaging.message.id", event.id().to_string());
 
         let data: String = serde_json::to_string(&event)?;
 
@@ -1,25 +1,133 @@
+use crate::batch;
+use crate::config::RateLimits;
 use crate::event_publisher::EventPublisher;
-use lambda_http::RequestPayloadExt;
+use lambda_http::request::RequestContext;
 use lambda_http::{http::StatusCode, tracing, Error, IntoResponse, Request};
+use lambda_http::{RequestExt, RequestPayloadExt};
 use serde::{Deserialize, Serialize};
-use shared::core::{IdGenerator, UrlRepository};
-use shared::utils::{empty_response, json_response};
+use shared::api_key::require_api_key;
+use shared::auth::{authenticate_caller, Caller};
+use shared::configuration::ConfigurationCache;
+use shared::core::{
+    epoch_seconds, IdGenerator, QueryMode, RedirectRule, RedirectType, RepositoryError, ShortUrl,
+    Target, UrlRepository,
+};
+use shared::jwt::JwtVerifier;
+use shared::normalise::url_hash;
+use shared::password::{hash_password, LinkPassword, PasswordError};
+use shared::query::validate_utm;
+use shared::rate_limit::{RateLimitDecision, RateLimiter};
+use shared::rules::normalise_rules;
+use shared::slug::SlugPolicy;
+use shared::targets::validate_targets;
+use shared::url_policy::UrlPolicy;
+use shared::utils::{
+    api_key_error_response, configuration_error_response, empty_response, json_error_response,
+    json_response, jwt_error_response, rate_limited_response, repository_error_response,
+};
+use std::collections::BTreeMap;
+
+/// How many generated ids to try before giving up on a run of id collisions.
+const MAX_ID_ATTEMPTS: usize = 3;
 
 #[derive(Serialize, Deserialize)]
 pub struct ShortenUrlRequest {
     pub url_to_shorten: String,
+    #[serde(default)]
+    pub custom_slug: Option<String>,
+    /// Seconds since the Unix epoch after which the link stops redirecting.
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+    #[serde(default)]
+    pub max_clicks: Option<u32>,
+    /// Return the caller's existing link for the same destination instead of
+    /// creating a new one. Defaults to the `DEDUPE` setting.
+    #[serde(default)]
+    pub dedupe: Option<bool>,
+    /// Visitors have to enter it before being redirected. Only its hash is stored.
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Weighted destinations to split the traffic between, in addition to `url_to_shorten`.
+    #[serde(default)]
+    pub targets: Vec<TargetRequest>,
+    /// Device, country and language rules, checked in order before the other destinations.
+    #[serde(default)]
+    pub rules: Vec<RedirectRule>,
+    /// Whether the query string visitors add to the short link is passed on.
+    #[serde(default)]
+    pub query_mode: QueryMode,
+    /// Let added parameters replace the ones already in the destination.
+    #[serde(default)]
+    pub override_query: bool,
+    /// UTM parameters added on every redirect.
+    #[serde(default)]
+    pub utm: BTreeMap<String, String>,
+    /// 301, 302, 307 or 308, defaults to 302.
+    #[serde(default)]
+    pub redirect_type: RedirectType,
+    /// Show a preview of the destination on every visit before redirecting.
+    #[serde(default)]
+    pub interstitial: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TargetRequest {
+    pub url: String,
+    pub weight: u32,
 }
-pub(crate) struct HandlerDeps<I: IdGenerator, R: UrlRepository, E: EventPublisher> {
+pub(crate) struct HandlerDeps<I: IdGenerator, R: UrlRepository, E: EventPublisher, L: RateLimiter> {
     pub id_generator: I,
     pub url_repo: R,
     pub event_publisher: E,
+    pub rate_limiter: L,
+    pub rate_limits: RateLimits,
+    pub slug_policy: SlugPolicy,
+    pub url_policy: UrlPolicy,
+    /// Verifies bearer tokens of requests API Gateway did not authenticate.
+    pub jwt_verifier: Option<JwtVerifier>,
+    /// Holds the domain policy and API keys, which change without a redeploy.
+    pub configuration: ConfigurationCache,
+    pub dedupe: bool,
 }
 
-#[tracing::instrument(skip(deps, event))]
-pub(crate) async fn function_handler<I: IdGenerator, R: UrlRepository, E: EventPublisher>(
-    deps: &HandlerDeps<I, R, E>,
+/// Handles `POST /links` and `POST /links:batch`.
+#[tracing::instrument(skip(deps, event), fields(api_key.label))]
+pub(crate) async fn function_handler<
+    I: IdGenerator,
+    R: UrlRepository,
+    E: EventPublisher,
+    L: RateLimiter,
+>(
+    deps: &HandlerDeps<I, R, E, L>,
     event: Request,
 ) -> Result<impl IntoResponse, Error> {
+    let caller = match authenticate_caller(&event, deps.jwt_verifier.as_ref()).await {
+        Ok(caller) => caller,
+        Err(e) => {
+            tracing::info!("Refusing request: {}", e);
+            return jwt_error_response(&e);
+        }
+    };
+    let configuration = match deps.configuration.current().await {
+        Ok(configuration) => configuration,
+        Err(e) => {
+            tracing::error!("Refusing request: {}", e);
+            return configuration_error_response(&e);
+        }
+    };
+    let api_key_label = match require_api_key(&event, &configuration.api_keys) {
+        Ok(label) => label,
+        Err(e) => {
+            tracing::info!("Refusing request: {}", e);
+            return api_key_error_response(&e);
+        }
+    };
+    if let Some(decision) = over_rate_limit(deps, &event, &caller, api_key_label).await {
+        return rate_limited_response(&decision);
+    }
+    if event.uri().path().ends_with("/links:batch") {
+        return batch::create_links(deps, &configuration, &caller.owner_id, &event).await;
+    }
     // Handle bad request in the case the body is not valid JSON or missing fields
     let shorten_url_request_body = match event.payload::<ShortenUrlRequest>() {
         Ok(body) => body,
@@ -29,31 +137,307 @@ pub(crate) async fn function_handler<I: IdGenerator, R: UrlRepository, E: EventP
     if shorten_url_request_body.is_none() {
         return empty_response(&StatusCode::BAD_REQUEST);
     }
-    let url_to_shorten = shorten_url_request_body.unwrap().url_to_shorten;
-    let id = deps.id_generator.generate_id();
-    let saved = deps.url_repo.store_short_url(url_to_shorten, id).await;
-    if let Err(e) = &saved {
-        tracing::error!("Failed to shorten URL: {:?}", e);
-        return empty_response(&StatusCode::INTERNAL_SERVER_ERROR);
+    let ShortenUrlRequest {
+        url_to_shorten,
+        custom_slug,
+        expires_at,
+        max_clicks,
+        dedupe,
+        password,
+        targets,
+        rules,
+        query_mode,
+        override_query,
+        utm,
+        redirect_type,
+        interstitial,
+    } = shorten_url_request_body.unwrap();
+
+    if expires_at.is_some_and(|expires_at| expires_at <= epoch_seconds()) {
+        return json_error_response(&StatusCode::BAD_REQUEST, "expires_at must be in the future");
+    }
+    if max_clicks == Some(0) {
+        return json_error_response(&StatusCode::BAD_REQUEST, "max_clicks must be at least 1");
+    }
+    let Ok(url_hash) = url_hash(&url_to_shorten) else {
+        return json_error_response(
+            &StatusCode::BAD_REQUEST,
+            "url_to_shorten must be an absolute URL",
+        );
+    };
+    // The link gets fetched from inside our network once it is created
+    if let Err(e) = deps.url_policy.validate(&url_to_shorten).await {
+        return json_error_response(&StatusCode::UNPROCESSABLE_ENTITY, &e.to_string());
+    }
+
+    let targets: Vec<Target> = targets
+        .into_iter()
+        .map(|target| Target {
+            url: target.url,
+            weight: target.weight,
+            clicks: 0,
+        })
+        .collect();
+    if let Err(e) = validate_targets(&targets) {
+        return json_error_response(&StatusCode::BAD_REQUEST, &e.to_string());
+    }
+    let rules = match normalise_rules(rules) {
+        Ok(rules) => rules,
+        Err(e) => return json_error_response(&StatusCode::BAD_REQUEST, &e.to_string()),
+    };
+    if let Err(e) = validate_utm(&utm) {
+        return json_error_response(&StatusCode::BAD_REQUEST, &e.to_string());
+    }
+    // Visitors are sent to targets and rule destinations like to the link itself
+    let destinations = targets
+        .iter()
+        .map(|target| target.url.as_str())
+        .chain(rules.iter().map(|rule| rule.destination.as_str()));
+    for destination in destinations {
+        if let Err(e) = deps.url_policy.validate(destination).await {
+            return json_error_response(&StatusCode::UNPROCESSABLE_ENTITY, &e.to_string());
+        }
+    }
+    // Rules and targets send visitors somewhere too
+    let blocked = std::iter::once(url_to_shorten.as_str())
+        .chain(targets.iter().map(|target| target.url.as_str()))
+        .chain(rules.iter().map(|rule| rule.destination.as_str()))
+        .find_map(|destination| configuration.domain_policy.check(destination).err());
+    if let Some(e) = blocked {
+        return json_error_response(&StatusCode::UNPROCESSABLE_ENTITY, &e.to_string());
+    }
+    let password = match password.as_deref().map(hash_password).transpose() {
+        Ok(hash) => hash.map(|hash| LinkPassword {
+            hash,
+            ..Default::default()
+        }),
+        Err(e @ PasswordError::InvalidLength) => {
+            return json_error_response(&StatusCode::BAD_REQUEST, &e.to_string())
+        }
+        Err(e) => {
+            tracing::error!("Failed to hash password: {:?}", e);
+            return empty_response(&StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    let short_url = ShortUrl {
+        owner_id: Some(caller.owner_id.clone()),
+        url_hash: Some(url_hash.clone()),
+        expires_at,
+        max_clicks,
+        password,
+        targets,
+        rules,
+        query_mode,
+        override_query,
+        utm,
+        redirect_type,
+        interstitial,
+        ..ShortUrl::new(String::new(), url_to_shorten)
+    };
+
+    // A custom slug, usage limits, a password or anything changing where visitors
+    // end up ask for a link of its own
+    let dedupe = dedupe.unwrap_or(deps.dedupe)
+        && custom_slug.is_none()
+        && expires_at.is_none()
+        && max_clicks.is_none()
+        && !short_url.has_options();
+    if dedupe {
+        match deps
+            .url_repo
+            .find_urls_by_hash(&caller.owner_id, &url_hash)
+            .await
+        {
+            Ok(existing) => {
+                let now = epoch_seconds();
+                if let Some(existing) = existing.into_iter().find(|existing| {
+                    // Nor is a link someone asked options for handed out as a plain one
+                    !existing.has_options() && !existing.is_expired(now) && !existing.is_exhausted()
+                }) {
+                    return json_response(&StatusCode::OK, &existing);
+                }
+            }
+            Err(e) => {
+                tracing::error!("Failed to look up existing links: {:?}", e);
+                return repository_error_response(&e);
+            }
+        }
     }
-    let short_url = saved.unwrap();
+
+    let saved = match custom_slug {
+        Some(slug) => {
+            if let Err(e) = deps.slug_policy.validate(&slug) {
+                return json_error_response(&StatusCode::BAD_REQUEST, &e.to_string());
+            }
+            let short_url = ShortUrl {
+                link_id: slug.clone(),
+                ..short_url
+            };
+            match deps.url_repo.store_short_url(short_url).await {
+                Err(RepositoryError::AlreadyExists(_)) => {
+                    return json_error_response(
+                        &StatusCode::CONFLICT,
+                        &format!("slug '{}' is already taken", slug),
+                    );
+                }
+                saved => saved,
+            }
+        }
+        None => store_with_generated_id(deps, short_url).await,
+    };
+    let short_url = match saved {
+        Ok(short_url) => short_url,
+        Err(e) => {
+            tracing::error!("Failed to shorten URL: {:?}", e);
+            return repository_error_response(&e);
+        }
+    };
     let publish_result = deps.event_publisher.publish_link_created(&short_url).await;
     if let Err(e) = &publish_result {
         tracing::error!("Failed to publish link created event: {:?}", e);
     }
-    json_response(&StatusCode::OK, &short_url)
+    // With dedupe, 201 tells a new link apart from an existing one (200)
+    let status = if dedupe {
+        StatusCode::CREATED
+    } else {
+        StatusCode::OK
+    };
+    json_response(&status, &short_url)
+}
+
+/// Takes a token from the bucket of the API key the request was authenticated
+/// with, or of the caller when there is none, and from the bucket of their IP
+/// address. Returns the
+/// decision of the first one found empty. Requests go through when the
+/// limiter itself fails, it is not worth refusing every link over.
+async fn over_rate_limit<I: IdGenerator, R: UrlRepository, E: EventPublisher, L: RateLimiter>(
+    deps: &HandlerDeps<I, R, E, L>,
+    event: &Request,
+    caller: &Caller,
+    api_key_label: Option<&str>,
+) -> Option<RateLimitDecision> {
+    let now = epoch_seconds();
+    // Never the presented key itself: any made up value would get a fresh bucket
+    let key_bucket = match api_key_label {
+        Some(label) => format!("key#{}", label),
+        None => format!("owner#{}", caller.owner_id),
+    };
+    let mut buckets = vec![(key_bucket, &deps.rate_limits.per_key)];
+    if let Some(ip) = client_ip(event) {
+        buckets.push((format!("ip#{}", ip), &deps.rate_limits.per_ip));
+    }
+
+    for (key, limit) in buckets {
+        match deps.rate_limiter.acquire(&key, limit, now).await {
+            Ok(decision) if !decision.allowed => {
+                tracing::info!("Rate limit reached for {}", key);
+                return Some(decision);
+            }
+            Ok(_) => {}
+            Err(e) => tracing::warn!("Failed to apply rate limit for {}: {:?}", key, e),
+        }
+    }
+    None
+}
+
+/// API Gateway appends the address it received the request from to
+/// `X-Forwarded-For`, anything before it was sent by the client and can be
+/// made up, so the last entry is the one to trust.
+fn client_ip(event: &Request) -> Option<String> {
+    let forwarded_for = event
+        .headers()
+        .get("x-forwarded-for")
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.rsplit(',').next())
+        .map(str::trim)
+        .filter(|ip| !ip.is_empty());
+    let source_ip = match event.request_context_ref() {
+        Some(RequestContext::ApiGatewayV2(context)) => context.http.source_ip.as_deref(),
+        _ => None,
+    };
+    forwarded_for.or(source_ip).map(str::to_string)
+}
+
+/// Generated ids can collide with existing ones: that is not the caller's fault,
+/// so retry with a fresh id instead of reporting a conflict.
+async fn store_with_generated_id<
+    I: IdGenerator,
+    R: UrlRepository,
+    E: EventPublisher,
+    L: RateLimiter,
+>(
+    deps: &HandlerDeps<I, R, E, L>,
+    short_url: ShortUrl,
+) -> Result<ShortUrl, RepositoryError> {
+    for _ in 0..MAX_ID_ATTEMPTS {
+        let candidate = ShortUrl {
+            link_id: deps.id_generator.generate_id(),
+            ..short_url.clone()
+        };
+        match deps.url_repo.store_short_url(candidate).await {
+            Err(RepositoryError::AlreadyExists(e)) => {
+                tracing::warn!("Generated id already exists, retrying: {:?}", e);
+            }
+            saved => return saved,
+        }
+    }
+    Err(RepositoryError::Fatal(format!(
+        "Failed to generate a unique id after {} attempts",
+        MAX_ID_ATTEMPTS
+    )))
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::function_handler;
+    use crate::config::RateLimits;
     use crate::event_publisher::MockEventPublisher;
     use crate::http_handler::HandlerDeps;
     use lambda_http::http::Request;
-    use lambda_http::{Body, IntoResponse};
+    use lambda_http::{Body, IntoResponse, RequestExt};
     use mockall::predicate::{eq, function};
     use serde_json::{json, Value};
-    use shared::core::{MockIdGenerator, MockUrlRepository, ShortUrl};
+    use shared::auth::jwt_request_context;
+    use shared::configuration::{Configuration, ConfigurationCache};
+    use shared::core::{
+        MockIdGenerator, MockUrlRepository, QueryMode, RedirectType, RepositoryError, ShortUrl,
+    };
+    use shared::normalise::url_hash;
+    use shared::rate_limit::{InMemoryRateLimiter, RateLimit};
+    use shared::slug::SlugPolicy;
+    use shared::url_policy::UrlPolicy;
+
+    /// Resolves the hosts the tests use without DNS.
+    pub(crate) fn test_url_policy() -> UrlPolicy {
+        let public = ["93.184.216.34".parse().unwrap()];
+        UrlPolicy::default()
+            .with_resolved("google.com", &public)
+            .with_resolved("example.com", &public)
+            .with_resolved("login.phish.example.net", &public)
+            .with_resolved("internal.example", &["10.0.0.1".parse().unwrap()])
+    }
+
+    /// The dependencies most tests use, for them to override what they test.
+    pub(crate) fn create_deps(
+        id_generator: MockIdGenerator,
+        url_repo: MockUrlRepository,
+        event_publisher: MockEventPublisher,
+    ) -> HandlerDeps<MockIdGenerator, MockUrlRepository, MockEventPublisher, InMemoryRateLimiter>
+    {
+        HandlerDeps {
+            id_generator,
+            url_repo,
+            event_publisher,
+            slug_policy: SlugPolicy::default(),
+            url_policy: test_url_policy(),
+            configuration: ConfigurationCache::fixed(Configuration::default()),
+            jwt_verifier: None,
+            rate_limiter: InMemoryRateLimiter::new(),
+            rate_limits: RateLimits::default(),
+            dedupe: false,
+        }
+    }
 
     #[tokio::test]
     async fn when_valid_link_is_passed_should_store_publish_and_return_details() {
@@ -65,12 +449,13 @@ mod tests {
             .return_const("12345689".to_string());
         mock_url_repo
             .expect_store_short_url()
-            .with(
-                eq("https://google.com".to_string()),
-                eq("12345689".to_string()),
-            )
+            .with(function(|short_url: &ShortUrl| {
+                short_url.original_link == "https://google.com"
+                    && short_url.link_id == "12345689"
+                    && short_url.owner_id.as_deref() == Some("user-1")
+            }))
             .times(1)
-            .returning(|url_to_shorten, short_link| Ok(ShortUrl::new(short_link, url_to_shorten)));
+            .returning(Ok);
         let mut event_publisher = MockEventPublisher::new();
         event_publisher
             .expect_publish_link_created()
@@ -79,11 +464,7 @@ mod tests {
                 short_url.link_id == "12345689"
             }))
             .returning(|_| Ok(()));
-        let deps = HandlerDeps {
-            id_generator: mock_id_generator,
-            url_repo: mock_url_repo,
-            event_publisher,
-        };
+        let deps = create_deps(mock_id_generator, mock_url_repo, event_publisher);
         let request = Request::builder()
             .header("Content-Type", "application/json")
             .body(
@@ -91,7 +472,8 @@ mod tests {
                     .to_string()
                     .into(),
             )
-            .unwrap();
+            .unwrap()
+            .with_request_context(jwt_request_context("user-1", &[]));
 
         let result = function_handler(&deps, request).await;
 
@@ -107,23 +489,50 @@ mod tests {
                 "clicks": 0,
                 "title": null,
                 "description": null,
-                "content_type": null
+                "content_type": null,
+                "owner_id": "user-1",
+                "url_hash": url_hash("https://google.com").unwrap(),
+                "version": 0
             })
         );
     }
 
+    #[tokio::test]
+    async fn when_request_is_not_authenticated_should_return_401() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo.expect_store_short_url().times(0);
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher.expect_publish_link_created().times(0);
+        let deps = create_deps(MockIdGenerator::new(), mock_url_repo, event_publisher);
+        let request = Request::builder()
+            .header("Content-Type", "application/json")
+            .body(
+                json!({"url_to_shorten": "https://google.com"})
+                    .to_string()
+                    .into(),
+            )
+            .unwrap();
+
+        let data = function_handler(&deps, request)
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+
+        assert_eq!(data.status(), 401);
+    }
+
     #[tokio::test]
     async fn when_invalid_body_is_passed_should_return_400() {
         let mock_url_repo = MockUrlRepository::default();
         let mock_id_generator = MockIdGenerator::new();
         let mut event_publisher = MockEventPublisher::new();
         event_publisher.expect_publish_link_created().times(0);
-        let deps = HandlerDeps {
-            id_generator: mock_id_generator,
-            url_repo: mock_url_repo,
-            event_publisher,
-        };
-        let request = Request::builder().body(Body::Empty).unwrap();
+        let deps = create_deps(mock_id_generator, mock_url_repo, event_publisher);
+        let request = Request::builder()
+            .body(Body::Empty)
+            .unwrap()
+            .with_request_context(jwt_request_context("user-1", &[]));
 
         let result = function_handler(&deps, request).await;
 
@@ -132,24 +541,341 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn when_valid_body_is_passed_and_storage_fails_should_return_500() {
+    async fn when_client_is_over_its_rate_limit_should_return_429_with_rate_limit_headers() {
         let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo
+            .expect_store_short_url()
+            .times(2)
+            .returning(Ok);
         let mut mock_id_generator = MockIdGenerator::new();
         mock_id_generator
             .expect_generate_id()
-            .times(1)
             .return_const("12345689".to_string());
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher
+            .expect_publish_link_created()
+            .times(2)
+            .returning(|_| Ok(()));
+        let one_a_minute = RateLimit {
+            capacity: 1,
+            refill_per_second: 1.0 / 60.0,
+        };
+        let configuration: Configuration = serde_json::from_value(json!({
+            "table_name": "links",
+            "api_keys": [
+                {"label": "ci-a", "key": "key-a"},
+                {"label": "ci-b", "key": "key-b"},
+                {"label": "ci-c", "key": "key-c"}
+            ]
+        }))
+        .unwrap();
+        let rate_limits = RateLimits {
+            per_key: one_a_minute,
+            per_ip: RateLimit {
+                capacity: 2,
+                ..one_a_minute
+            },
+        };
+        let deps = HandlerDeps {
+            configuration: ConfigurationCache::fixed(configuration),
+            rate_limits,
+            ..create_deps(mock_id_generator, mock_url_repo, event_publisher)
+        };
+        let request = |api_key: &str, forwarded_for: &str| {
+            Request::builder()
+                .header("Content-Type", "application/json")
+                .header("x-api-key", api_key)
+                .header("x-forwarded-for", forwarded_for)
+                .body(
+                    json!({"url_to_shorten": "https://google.com"})
+                        .to_string()
+                        .into(),
+                )
+                .unwrap()
+                .with_request_context(jwt_request_context("user-1", &[]))
+        };
+
+        // The first entry of X-Forwarded-For comes from the client and is ignored
+        let cases = [
+            ("key-a", "198.51.100.1, 203.0.113.9", 200),
+            ("key-a", "198.51.100.2, 203.0.113.8", 429),
+            ("key-b", "198.51.100.3, 203.0.113.9", 200),
+            ("key-c", "198.51.100.4, 203.0.113.9", 429),
+        ];
+        for (api_key, forwarded_for, status) in cases {
+            let data = function_handler(&deps, request(api_key, forwarded_for))
+                .await
+                .unwrap()
+                .into_response()
+                .await;
+
+            assert_eq!(data.status(), status, "{} {}", api_key, forwarded_for);
+            if status == 429 {
+                assert_eq!(data.headers()["retry-after"], "60");
+                assert_eq!(data.headers()["ratelimit-remaining"], "0");
+                assert!(data.headers().contains_key("ratelimit-limit"));
+                assert!(data.headers().contains_key("ratelimit-reset"));
+            }
+        }
+
+        // Without configured keys, made up ones all share the caller's bucket
+        let mut mock_url_repo = MockUrlRepository::default();
         mock_url_repo
             .expect_store_short_url()
             .times(1)
-            .returning(|_url_to_shorten, _short_link| Err("Error storing URL".to_string()));
+            .returning(Ok);
+        let mut mock_id_generator = MockIdGenerator::new();
+        mock_id_generator
+            .expect_generate_id()
+            .return_const("12345689".to_string());
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher
+            .expect_publish_link_created()
+            .times(1)
+            .returning(|_| Ok(()));
+        let deps = HandlerDeps {
+            rate_limits,
+            ..create_deps(mock_id_generator, mock_url_repo, event_publisher)
+        };
+        let cases = [
+            ("made-up-1", "203.0.113.1", 200),
+            ("made-up-2", "203.0.113.2", 429),
+        ];
+        for (api_key, forwarded_for, status) in cases {
+            let data = function_handler(&deps, request(api_key, forwarded_for))
+                .await
+                .unwrap()
+                .into_response()
+                .await;
+
+            assert_eq!(data.status(), status, "{} {}", api_key, forwarded_for);
+        }
+    }
+
+    #[tokio::test]
+    async fn when_api_keys_are_configured_should_refuse_requests_without_a_valid_one() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo
+            .expect_store_short_url()
+            .times(2)
+            .returning(Ok);
+        let mut mock_id_generator = MockIdGenerator::new();
+        mock_id_generator
+            .expect_generate_id()
+            .return_const("12345689".to_string());
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher
+            .expect_publish_link_created()
+            .times(2)
+            .returning(|_| Ok(()));
+        let configuration: Configuration = serde_json::from_value(json!({
+            "table_name": "links",
+            "api_keys": [
+                {"label": "ci-2026-09", "key": "old-secret"},
+                {"label": "ci-2026-10", "key": "new-secret"}
+            ]
+        }))
+        .unwrap();
+        let deps = HandlerDeps {
+            configuration: ConfigurationCache::fixed(configuration),
+            ..create_deps(mock_id_generator, mock_url_repo, event_publisher)
+        };
+        let request = |api_key: Option<&str>| {
+            let mut request = Request::builder().header("Content-Type", "application/json");
+            if let Some(api_key) = api_key {
+                request = request.header("x-api-key", api_key);
+            }
+            request
+                .body(
+                    json!({"url_to_shorten": "https://google.com"})
+                        .to_string()
+                        .into(),
+                )
+                .unwrap()
+                .with_request_context(jwt_request_context("user-1", &[]))
+        };
+
+        let cases = [
+            (None, 401),
+            (Some("wrong-secret"), 403),
+            (Some("old-secret"), 200),
+            (Some("new-secret"), 200),
+        ];
+        for (api_key, status) in cases {
+            let data = function_handler(&deps, request(api_key))
+                .await
+                .unwrap()
+                .into_response()
+                .await;
+
+            assert_eq!(data.status(), status, "{:?}", api_key);
+            if status == 401 {
+                assert_eq!(data.headers()["www-authenticate"], "Bearer");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn when_configuration_could_not_be_loaded_should_return_503_rather_than_skip_api_keys() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo.expect_store_short_url().times(0);
         let mut event_publisher = MockEventPublisher::new();
         event_publisher.expect_publish_link_created().times(0);
         let deps = HandlerDeps {
-            id_generator: mock_id_generator,
-            url_repo: mock_url_repo,
-            event_publisher,
+            configuration: ConfigurationCache::unavailable(),
+            ..create_deps(MockIdGenerator::new(), mock_url_repo, event_publisher)
+        };
+        let request = create_request(json!({"url_to_shorten": "https://google.com"}));
+
+        let data = function_handler(&deps, request)
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+
+        assert_eq!(data.status(), 503);
+        assert_eq!(data.headers()["retry-after"], "1");
+    }
+
+    #[tokio::test]
+    async fn when_a_destination_domain_is_blocked_should_return_422_with_the_reason() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo.expect_store_short_url().times(0);
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher.expect_publish_link_created().times(0);
+        let configuration = Configuration {
+            domain_policy: serde_json::from_value(json!({
+                "deny": ["example.com", "*.phish.example.net"]
+            }))
+            .unwrap(),
+            ..Default::default()
         };
+        let deps = HandlerDeps {
+            configuration: ConfigurationCache::fixed(configuration),
+            ..create_deps(MockIdGenerator::new(), mock_url_repo, event_publisher)
+        };
+
+        for (body, reason) in [
+            (
+                json!({"url_to_shorten": "https://example.com/offer"}),
+                "domain 'example.com' is blocked",
+            ),
+            (
+                json!({
+                    "url_to_shorten": "https://google.com",
+                    "targets": [
+                        {"url": "https://google.com/a", "weight": 1},
+                        {"url": "https://login.phish.example.net", "weight": 1}
+                    ]
+                }),
+                "domain 'login.phish.example.net' is blocked",
+            ),
+        ] {
+            let data = function_handler(&deps, create_request(body))
+                .await
+                .unwrap()
+                .into_response()
+                .await;
+
+            assert_eq!(data.status(), 422, "{}", reason);
+            let body: Value = serde_json::from_slice(data.body()).unwrap();
+            assert_eq!(body["error"], reason);
+        }
+    }
+
+    #[tokio::test]
+    async fn when_url_is_refused_by_the_url_policy_should_return_422_with_the_reason() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo.expect_store_short_url().times(0);
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher.expect_publish_link_created().times(0);
+        let deps = create_deps(MockIdGenerator::new(), mock_url_repo, event_publisher);
+
+        for (url, reason) in [
+            (
+                "http://169.254.169.254/latest/meta-data/",
+                "host '169.254.169.254' points to the non-public address 169.254.169.254",
+            ),
+            (
+                "http://internal.example/admin",
+                "host 'internal.example' points to the non-public address 10.0.0.1",
+            ),
+            (
+                "file:///etc/passwd",
+                "scheme 'file' is not allowed, only http and https are",
+            ),
+        ] {
+            let request = create_request(json!({ "url_to_shorten": url }));
+
+            let data = function_handler(&deps, request)
+                .await
+                .unwrap()
+                .into_response()
+                .await;
+
+            assert_eq!(data.status(), 422, "{}", url);
+            let body: Value = serde_json::from_slice(data.body()).unwrap();
+            assert_eq!(body["error"], reason);
+        }
+    }
+
+    #[tokio::test]
+    async fn when_a_target_or_rule_is_refused_by_the_url_policy_should_return_422_with_the_reason()
+    {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo.expect_store_short_url().times(0);
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher.expect_publish_link_created().times(0);
+        let deps = create_deps(MockIdGenerator::new(), mock_url_repo, event_publisher);
+
+        for (body, reason) in [
+            (
+                json!({
+                    "url_to_shorten": "https://google.com",
+                    "targets": [
+                        {"url": "https://google.com/a", "weight": 1},
+                        {"url": "javascript:alert(document.cookie)", "weight": 1}
+                    ]
+                }),
+                "scheme 'javascript' is not allowed, only http and https are",
+            ),
+            (
+                json!({
+                    "url_to_shorten": "https://google.com",
+                    "rules": [
+                        {"countries": ["BE"], "destination": "http://169.254.169.254/"}
+                    ]
+                }),
+                "host '169.254.169.254' points to the non-public address 169.254.169.254",
+            ),
+        ] {
+            let data = function_handler(&deps, create_request(body))
+                .await
+                .unwrap()
+                .into_response()
+                .await;
+
+            assert_eq!(data.status(), 422, "{}", reason);
+            let body: Value = serde_json::from_slice(data.body()).unwrap();
+            assert_eq!(body["error"], reason);
+        }
+    }
+
+    #[tokio::test]
+    async fn when_valid_body_is_passed_and_storage_fails_should_return_500() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        let mut mock_id_generator = MockIdGenerator::new();
+        mock_id_generator
+            .expect_generate_id()
+            .times(1)
+            .return_const("12345689".to_string());
+        mock_url_repo
+            .expect_store_short_url()
+            .times(1)
+            .returning(|_short_url| Err(RepositoryError::Fatal("Error storing URL".to_string())));
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher.expect_publish_link_created().times(0);
+        let deps = create_deps(mock_id_generator, mock_url_repo, event_publisher);
         let request = Request::builder()
             .header("Content-Type", "application/json")
             .body(
@@ -157,7 +883,8 @@ mod tests {
                     .to_string()
                     .into(),
             )
-            .unwrap();
+            .unwrap()
+            .with_request_context(jwt_request_context("user-1", &[]));
 
         let result = function_handler(&deps, request).await;
 
@@ -166,44 +893,583 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn when_event_publish_fails_should_still_return_200() {
+    async fn when_generated_id_collides_should_retry_with_new_id() {
         let mut mock_url_repo = MockUrlRepository::default();
         let mut mock_id_generator = MockIdGenerator::new();
+        let mut ids = vec!["second".to_string(), "first".to_string()];
         mock_id_generator
             .expect_generate_id()
+            .times(2)
+            .returning(move || ids.pop().unwrap());
+        mock_url_repo
+            .expect_store_short_url()
+            .with(function(|short_url: &ShortUrl| {
+                short_url.original_link == "https://google.com" && short_url.link_id == "first"
+            }))
             .times(1)
-            .return_const("short123".to_string());
+            .returning(|_| Err(RepositoryError::AlreadyExists("first".to_string())));
         mock_url_repo
             .expect_store_short_url()
-            .with(
-                eq("https://example.com".to_string()),
-                eq("short123".to_string()),
-            )
+            .with(function(|short_url: &ShortUrl| {
+                short_url.original_link == "https://google.com" && short_url.link_id == "second"
+            }))
             .times(1)
-            .returning(|url_to_shorten, short_link| Ok(ShortUrl::new(short_link, url_to_shorten)));
+            .returning(Ok);
         let mut event_publisher = MockEventPublisher::new();
         event_publisher
             .expect_publish_link_created()
             .times(1)
-            .returning(|_| {
-                Err(Box::new(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    "publish failed",
-                )))
-            });
-        let deps = HandlerDeps {
-            id_generator: mock_id_generator,
-            url_repo: mock_url_repo,
-            event_publisher,
-        };
+            .returning(|_| Ok(()));
+        let deps = create_deps(mock_id_generator, mock_url_repo, event_publisher);
         let request = Request::builder()
             .header("Content-Type", "application/json")
             .body(
-                json!({"url_to_shorten": "https://example.com"})
+                json!({"url_to_shorten": "https://google.com"})
                     .to_string()
                     .into(),
             )
-            .unwrap();
+            .unwrap()
+            .with_request_context(jwt_request_context("user-1", &[]));
+
+        let data = function_handler(&deps, request)
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+
+        assert_eq!(data.status(), 200);
+        let response_struct: Value = serde_json::from_slice(data.body()).unwrap();
+        assert_eq!(response_struct["link_id"], "second");
+    }
+
+    #[tokio::test]
+    async fn when_storage_is_throttled_should_return_429_with_retry_after() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        let mut mock_id_generator = MockIdGenerator::new();
+        mock_id_generator
+            .expect_generate_id()
+            .times(1)
+            .return_const("12345689".to_string());
+        mock_url_repo
+            .expect_store_short_url()
+            .times(1)
+            .returning(|_| Err(RepositoryError::Throttled("slow down".to_string())));
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher.expect_publish_link_created().times(0);
+        let deps = create_deps(mock_id_generator, mock_url_repo, event_publisher);
+        let request = Request::builder()
+            .header("Content-Type", "application/json")
+            .body(
+                json!({"url_to_shorten": "https://google.com"})
+                    .to_string()
+                    .into(),
+            )
+            .unwrap()
+            .with_request_context(jwt_request_context("user-1", &[]));
+
+        let data = function_handler(&deps, request)
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+
+        assert_eq!(data.status(), 429);
+        assert!(data.headers().contains_key("Retry-After"));
+    }
+
+    fn create_request(body: serde_json::Value) -> Request<Body> {
+        Request::builder()
+            .header("Content-Type", "application/json")
+            .body(body.to_string().into())
+            .unwrap()
+            .with_request_context(jwt_request_context("user-1", &[]))
+    }
+
+    #[tokio::test]
+    async fn when_custom_slug_is_passed_should_store_it_as_link_id() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        let mut mock_id_generator = MockIdGenerator::new();
+        mock_id_generator.expect_generate_id().times(0);
+        mock_url_repo
+            .expect_store_short_url()
+            .with(function(|short_url: &ShortUrl| {
+                short_url.original_link == "https://google.com"
+                    && short_url.link_id == "summer-sale"
+            }))
+            .times(1)
+            .returning(Ok);
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher
+            .expect_publish_link_created()
+            .times(1)
+            .returning(|_| Ok(()));
+        let deps = create_deps(mock_id_generator, mock_url_repo, event_publisher);
+        let request = create_request(
+            json!({"url_to_shorten": "https://google.com", "custom_slug": "summer-sale"}),
+        );
+
+        let data = function_handler(&deps, request)
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+
+        assert_eq!(data.status(), 200);
+        let response_struct: Value = serde_json::from_slice(data.body()).unwrap();
+        assert_eq!(response_struct["link_id"], "summer-sale");
+    }
+
+    #[tokio::test]
+    async fn when_custom_slug_is_invalid_should_return_400() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo.expect_store_short_url().times(0);
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher.expect_publish_link_created().times(0);
+        let deps = create_deps(MockIdGenerator::new(), mock_url_repo, event_publisher);
+
+        for slug in ["ab", "not/valid", "links"] {
+            let request = create_request(
+                json!({"url_to_shorten": "https://google.com", "custom_slug": slug}),
+            );
+
+            let data = function_handler(&deps, request)
+                .await
+                .unwrap()
+                .into_response()
+                .await;
+
+            assert_eq!(data.status(), 400, "{}", slug);
+            let response_struct: Value = serde_json::from_slice(data.body()).unwrap();
+            assert!(response_struct["error"].is_string());
+        }
+    }
+
+    #[tokio::test]
+    async fn when_custom_slug_is_taken_should_return_409() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo
+            .expect_store_short_url()
+            .times(1)
+            .returning(|short_url| Err(RepositoryError::AlreadyExists(short_url.link_id)));
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher.expect_publish_link_created().times(0);
+        let deps = create_deps(MockIdGenerator::new(), mock_url_repo, event_publisher);
+        let request = create_request(
+            json!({"url_to_shorten": "https://google.com", "custom_slug": "summer-sale"}),
+        );
+
+        let data = function_handler(&deps, request)
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+
+        assert_eq!(data.status(), 409);
+        let response_struct: Value = serde_json::from_slice(data.body()).unwrap();
+        assert_eq!(
+            response_struct,
+            json!({"error": "slug 'summer-sale' is already taken"})
+        );
+    }
+
+    #[tokio::test]
+    async fn when_expiry_and_max_clicks_are_passed_should_store_them() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        let mut mock_id_generator = MockIdGenerator::new();
+        mock_id_generator
+            .expect_generate_id()
+            .times(1)
+            .return_const("12345689".to_string());
+        mock_url_repo
+            .expect_store_short_url()
+            .with(function(|short_url: &ShortUrl| {
+                short_url.expires_at == Some(4_102_444_800) && short_url.max_clicks == Some(10)
+            }))
+            .times(1)
+            .returning(Ok);
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher
+            .expect_publish_link_created()
+            .times(1)
+            .returning(|_| Ok(()));
+        let deps = create_deps(mock_id_generator, mock_url_repo, event_publisher);
+        let request = create_request(json!({
+            "url_to_shorten": "https://google.com",
+            "expires_at": 4_102_444_800u64,
+            "max_clicks": 10
+        }));
+
+        let data = function_handler(&deps, request)
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+
+        assert_eq!(data.status(), 200);
+        let response_struct: Value = serde_json::from_slice(data.body()).unwrap();
+        assert_eq!(response_struct["expires_at"], 4_102_444_800u64);
+        assert_eq!(response_struct["max_clicks"], 10);
+    }
+
+    #[tokio::test]
+    async fn when_expiry_is_in_the_past_or_max_clicks_is_zero_should_return_400() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo.expect_store_short_url().times(0);
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher.expect_publish_link_created().times(0);
+        let deps = create_deps(MockIdGenerator::new(), mock_url_repo, event_publisher);
+
+        for body in [
+            json!({"url_to_shorten": "https://google.com", "expires_at": 1}),
+            json!({"url_to_shorten": "https://google.com", "max_clicks": 0}),
+        ] {
+            let data = function_handler(&deps, create_request(body.clone()))
+                .await
+                .unwrap()
+                .into_response()
+                .await;
+
+            assert_eq!(data.status(), 400, "{}", body);
+        }
+    }
+
+    #[tokio::test]
+    async fn when_password_is_passed_should_store_only_its_hash_and_skip_dedupe() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo.expect_find_urls_by_hash().times(0);
+        mock_url_repo
+            .expect_store_short_url()
+            .with(function(|short_url: &ShortUrl| {
+                short_url.password.as_ref().is_some_and(|password| {
+                    password.hash.starts_with("$argon2") && password.verify("open sesame")
+                })
+            }))
+            .times(1)
+            .returning(Ok);
+        let mut mock_id_generator = MockIdGenerator::new();
+        mock_id_generator
+            .expect_generate_id()
+            .times(1)
+            .return_const("12345689".to_string());
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher
+            .expect_publish_link_created()
+            .times(1)
+            .returning(|_| Ok(()));
+        let deps = HandlerDeps {
+            dedupe: true,
+            ..create_deps(mock_id_generator, mock_url_repo, event_publisher)
+        };
+        let request = create_request(json!({
+            "url_to_shorten": "https://google.com",
+            "password": "open sesame"
+        }));
+
+        let data = function_handler(&deps, request)
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+
+        assert_eq!(data.status(), 200);
+        let body = std::str::from_utf8(data.body()).unwrap();
+        assert!(!body.contains("open sesame"));
+        assert!(!body.contains("argon2"));
+    }
+
+    #[tokio::test]
+    async fn when_targets_are_passed_should_store_them_without_clicks() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo
+            .expect_store_short_url()
+            .with(function(|short_url: &ShortUrl| {
+                short_url.targets.len() == 2
+                    && short_url.targets[0].weight == 3
+                    && short_url.targets.iter().all(|target| target.clicks == 0)
+            }))
+            .times(1)
+            .returning(Ok);
+        let mut mock_id_generator = MockIdGenerator::new();
+        mock_id_generator
+            .expect_generate_id()
+            .times(1)
+            .return_const("12345689".to_string());
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher
+            .expect_publish_link_created()
+            .times(1)
+            .returning(|_| Ok(()));
+        let deps = create_deps(mock_id_generator, mock_url_repo, event_publisher);
+        let request = create_request(json!({
+            "url_to_shorten": "https://google.com",
+            "targets": [
+                {"url": "https://google.com/a", "weight": 3, "clicks": 100},
+                {"url": "https://google.com/b", "weight": 1}
+            ]
+        }));
+
+        let data = function_handler(&deps, request)
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+
+        assert_eq!(data.status(), 200);
+        let body: Value = serde_json::from_slice(data.body()).unwrap();
+        assert_eq!(body["targets"][1]["url"], "https://google.com/b");
+    }
+
+    #[tokio::test]
+    async fn when_link_options_are_invalid_should_return_400() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo.expect_store_short_url().times(0);
+        let deps = create_deps(
+            MockIdGenerator::new(),
+            mock_url_repo,
+            MockEventPublisher::new(),
+        );
+
+        for (field, value) in [
+            ("targets", json!([{"url": "not a url", "weight": 1}])),
+            (
+                "targets",
+                json!([{"url": "https://google.com/a", "weight": 0}]),
+            ),
+            (
+                "rules",
+                json!([{"countries": ["BEL"], "destination": "https://google.com/be"}]),
+            ),
+            ("rules", json!([{"destination": "https://google.com/all"}])),
+            ("utm", json!({"ref": "newsletter"})),
+            ("utm", json!({"utm_source": ""})),
+            ("redirect_type", json!(303)),
+        ] {
+            let request = create_request(json!({
+                "url_to_shorten": "https://google.com",
+                field: value
+            }));
+
+            let data = function_handler(&deps, request)
+                .await
+                .unwrap()
+                .into_response()
+                .await;
+
+            assert_eq!(data.status(), 400, "{}", value);
+        }
+    }
+
+    #[tokio::test]
+    async fn when_password_is_too_short_should_return_400() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo.expect_store_short_url().times(0);
+        let deps = create_deps(
+            MockIdGenerator::new(),
+            mock_url_repo,
+            MockEventPublisher::new(),
+        );
+        let request = create_request(json!({
+            "url_to_shorten": "https://google.com",
+            "password": "short"
+        }));
+
+        let data = function_handler(&deps, request)
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+
+        assert_eq!(data.status(), 400);
+    }
+
+    #[tokio::test]
+    async fn when_dedupe_requested_and_link_exists_should_return_it_with_200() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo
+            .expect_find_urls_by_hash()
+            .times(1)
+            .with(
+                eq("user-1".to_string()),
+                eq(url_hash("https://google.com").unwrap()),
+            )
+            .returning(|_owner_id, _url_hash| {
+                Ok(vec![ShortUrl::new(
+                    "existing".into(),
+                    "https://google.com".into(),
+                )])
+            });
+        mock_url_repo.expect_store_short_url().times(0);
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher.expect_publish_link_created().times(0);
+        let deps = create_deps(MockIdGenerator::new(), mock_url_repo, event_publisher);
+        let request = create_request(json!({
+            "url_to_shorten": "HTTPS://Google.com:443/#top",
+            "dedupe": true
+        }));
+
+        let data = function_handler(&deps, request)
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+
+        assert_eq!(data.status(), 200);
+        let response_struct: Value = serde_json::from_slice(data.body()).unwrap();
+        assert_eq!(response_struct["link_id"], "existing");
+    }
+
+    #[tokio::test]
+    async fn when_dedupe_configured_and_no_usable_link_exists_should_create_with_201() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo
+            .expect_find_urls_by_hash()
+            .times(1)
+            .returning(|_owner_id, _url_hash| {
+                Ok(vec![ShortUrl {
+                    expires_at: Some(1),
+                    ..ShortUrl::new("expired".into(), "https://google.com".into())
+                }])
+            });
+        mock_url_repo
+            .expect_store_short_url()
+            .times(1)
+            .with(function(|short_url: &ShortUrl| {
+                short_url.url_hash == url_hash("https://google.com").ok()
+            }))
+            .returning(Ok);
+        let mut mock_id_generator = MockIdGenerator::new();
+        mock_id_generator
+            .expect_generate_id()
+            .times(1)
+            .return_const("new".to_string());
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher
+            .expect_publish_link_created()
+            .times(1)
+            .returning(|_| Ok(()));
+        let deps = HandlerDeps {
+            dedupe: true,
+            ..create_deps(mock_id_generator, mock_url_repo, event_publisher)
+        };
+        let request = create_request(json!({"url_to_shorten": "https://google.com"}));
+
+        let data = function_handler(&deps, request)
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+
+        assert_eq!(data.status(), 201);
+    }
+
+    #[tokio::test]
+    async fn when_existing_links_have_options_should_not_reuse_them() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo
+            .expect_find_urls_by_hash()
+            .times(1)
+            .returning(|_owner_id, _url_hash| {
+                let existing = ShortUrl::new("existing".into(), "https://google.com".into());
+                Ok(vec![
+                    ShortUrl {
+                        interstitial: true,
+                        ..existing.clone()
+                    },
+                    ShortUrl {
+                        query_mode: QueryMode::Merge,
+                        ..existing.clone()
+                    },
+                    ShortUrl {
+                        redirect_type: RedirectType::MovedPermanently,
+                        ..existing.clone()
+                    },
+                    ShortUrl {
+                        utm: [("utm_source".to_string(), "mail".to_string())].into(),
+                        ..existing
+                    },
+                ])
+            });
+        mock_url_repo
+            .expect_store_short_url()
+            .times(1)
+            .returning(Ok);
+        let mut mock_id_generator = MockIdGenerator::new();
+        mock_id_generator
+            .expect_generate_id()
+            .times(1)
+            .return_const("new".to_string());
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher
+            .expect_publish_link_created()
+            .times(1)
+            .returning(|_| Ok(()));
+        let deps = HandlerDeps {
+            dedupe: true,
+            ..create_deps(mock_id_generator, mock_url_repo, event_publisher)
+        };
+        let request = create_request(json!({"url_to_shorten": "https://google.com"}));
+
+        let data = function_handler(&deps, request)
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+
+        assert_eq!(data.status(), 201);
+        let response_struct: Value = serde_json::from_slice(data.body()).unwrap();
+        assert_eq!(response_struct["link_id"], "new");
+    }
+
+    #[tokio::test]
+    async fn when_url_is_not_absolute_should_return_400() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo.expect_store_short_url().times(0);
+        let deps = create_deps(
+            MockIdGenerator::new(),
+            mock_url_repo,
+            MockEventPublisher::new(),
+        );
+        let request = create_request(json!({"url_to_shorten": "google.com"}));
+
+        let data = function_handler(&deps, request)
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+
+        assert_eq!(data.status(), 400);
+    }
+
+    #[tokio::test]
+    async fn when_event_publish_fails_should_still_return_200() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        let mut mock_id_generator = MockIdGenerator::new();
+        mock_id_generator
+            .expect_generate_id()
+            .times(1)
+            .return_const("short123".to_string());
+        mock_url_repo
+            .expect_store_short_url()
+            .with(function(|short_url: &ShortUrl| {
+                short_url.original_link == "https://example.com" && short_url.link_id == "short123"
+            }))
+            .times(1)
+            .returning(Ok);
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher
+            .expect_publish_link_created()
+            .times(1)
+            .returning(|_| Err(Box::new(std::io::Error::other("publish failed"))));
+        let deps = create_deps(mock_id_generator, mock_url_repo, event_publisher);
+        let request = Request::builder()
+            .header("Content-Type", "application/json")
+            .body(
+                json!({"url_to_shorten": "https://example.com"})
+                    .to_string()
+                    .into(),
+            )
+            .unwrap()
+            .with_request_context(jwt_request_context("user-1", &[]));
 
         let data = function_handler(&deps, request)
             .await
@@ -1,20 +1,27 @@
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
 use crate::config::Config;
 use crate::event_publisher::SqsEventBridgePublisher;
 use crate::http_handler::HandlerDeps;
 use ::tracing::Instrument;
 use http_handler::function_handler;
-use lambda_http::{Body, Error, http, run, service_fn, tracing};
-use shared::adapters::DynamoDbUrlRepository;
+use lambda_http::{http, run, service_fn, tracing, Body, Error};
+use shared::adapters::{url_repository, DynamoDbUrlRepository};
+use shared::configuration::ConfigurationCache;
 use shared::core::CuidGenerator;
+use shared::jwt::JwtVerifier;
+use shared::rate_limit::{DynamoDbRateLimiter, InMemoryRateLimiter, RateLimiter};
 
+mod batch;
 mod config;
 mod event_publisher;
 mod http_handler;
 
 static IS_COLD_START: AtomicBool = AtomicBool::new(true);
+/// How soon a change to the domain policy reaches a running function.
+const CONFIGURATION_MAX_AGE: Duration = Duration::from_secs(60);
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
@@ -24,26 +31,51 @@ async fn main() -> Result<(), Error> {
     let dynamodb_client = aws_sdk_dynamodb::Client::new(&aws_config);
     let config = Config::load()?;
     let id_generator = CuidGenerator::new();
-    let url_repo = DynamoDbUrlRepository::new(config.table_name, dynamodb_client);
+    let url_repo = url_repository(config.in_memory_repository, || {
+        DynamoDbUrlRepository::new(config.table_name, dynamodb_client.clone())
+    });
     let event_publisher = SqsEventBridgePublisher::new(
         aws_sdk_sqs::Client::new(&aws_config),
         config.queue_url,
         aws_sdk_eventbridge::Client::new(&aws_config),
     );
+    // Buckets in memory too, so nothing needs DynamoDB
+    let rate_limiter: Box<dyn RateLimiter + Send + Sync> = if config.in_memory_repository {
+        Box::new(InMemoryRateLimiter::new())
+    } else {
+        Box::new(DynamoDbRateLimiter::new(
+            config.rate_limits_table_name,
+            dynamodb_client,
+        ))
+    };
     let deps = HandlerDeps {
         id_generator,
         url_repo,
         event_publisher,
+        slug_policy: config.slug_policy,
+        url_policy: config.url_policy,
+        rate_limiter,
+        rate_limits: config.rate_limits,
+        jwt_verifier: config.jwt.map(JwtVerifier::new).transpose()?,
+        configuration: ConfigurationCache::load(
+            aws_sdk_ssm::Client::new(&aws_config),
+            aws_sdk_secretsmanager::Client::new(&aws_config),
+            CONFIGURATION_MAX_AGE,
+        )
+        .await,
+        dedupe: config.dedupe,
     };
 
     run(service_fn(|event: http::Request<Body>| async {
         let was_cold_start = IS_COLD_START.swap(false, Ordering::SeqCst);
 
-        let handler_span = tracing::info_span!("aws.lambda", 
+        let handler_span = tracing::info_span!(
+            "aws.lambda",
             operation_name = "aws.lambda",
             faas.coldstart = was_cold_start,
             cloud.provider = "aws",
-            event_type = "http");
+            event_type = "http"
+        );
 
         let res = function_handler(&deps, event)
             .instrument(handler_span)
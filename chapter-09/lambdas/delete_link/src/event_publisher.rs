@@ -0,0 +1,70 @@
+use aws_sdk_eventbridge::types::PutEventsRequestEntry;
+use cloudevents::{AttributesReader, EventBuilder, EventBuilderV10};
+use shared::core::{CuidGenerator, IdGenerator, LinkStatus, ShortUrl};
+
+#[cfg(test)]
+use mockall::automock;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+#[cfg_attr(test, automock)]
+pub(crate) trait EventPublisher {
+    async fn publish_link_status_changed(&self, short_url: &ShortUrl) -> Result<(), Error>;
+}
+
+pub(crate) struct EventBridgePublisher {
+    pub eventbridge_client: aws_sdk_eventbridge::Client,
+}
+
+impl EventBridgePublisher {
+    pub fn new(eventbridge_client: aws_sdk_eventbridge::Client) -> Self {
+        Self { eventbridge_client }
+    }
+}
+
+fn detail_type(status: LinkStatus) -> &'static str {
+    match status {
+        LinkStatus::Active => "LinkEnabled",
+        LinkStatus::Disabled => "LinkDisabled",
+        LinkStatus::Deleted => "LinkDeleted",
+    }
+}
+
+impl EventPublisher for EventBridgePublisher {
+    #[tracing::instrument("publish link_status_changed.v1", skip(self, short_url), fields(
+    messaging.message.id = tracing::field::Empty,
+    messaging.operation.name = "publish",
+    messaging.destination = "aws_eventbridge",
+    messaging.client.id = "delete_link",
+))]
+    async fn publish_link_status_changed(&self, short_url: &ShortUrl) -> Result<(), Error> {
+        let current_span = tracing::Span::current();
+        let trace_parent = shared::observability::get_traceparent_extension_value(&current_span);
+
+        let event: cloudevents::Event = EventBuilderV10::new()
+            .id(CuidGenerator::new().generate_id().to_string())
+            .ty("rust-link-shortener")
+            .source("http://rust-link-shortener.com")
+            .data("application/json", serde_json::to_value(short_url)?)
+            .extension("traceparent", trace_parent)
+            .build()
+            .unwrap();
+        tracing::Span::current().record("messaging.message.id", event.id().to_string());
+
+        let data: String = serde_json::to_string(&event)?;
+
+        self.eventbridge_client
+            .put_events()
+            .entries(
+                PutEventsRequestEntry::builder()
+                    .source("custom.link_shortener")
+                    .detail_type(detail_type(short_url.status))
+                    .detail(data)
+                    .build(),
+            )
+            .send()
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,388 @@
+use crate::event_publisher::EventPublisher;
+use lambda_http::RequestExt;
+use lambda_http::{http::Method, http::StatusCode, tracing, Error, IntoResponse, Request};
+use shared::api_key::require_api_key;
+use shared::auth::authenticate_caller;
+use shared::configuration::ConfigurationCache;
+use shared::core::{LinkStatus, UrlRepository};
+use shared::jwt::JwtVerifier;
+use shared::utils::{
+    api_key_error_response, configuration_error_response, empty_response, json_response,
+    jwt_error_response, repository_error_response,
+};
+
+pub(crate) struct HandlerDeps<R: UrlRepository, E: EventPublisher> {
+    pub url_repo: R,
+    pub event_publisher: E,
+    /// Verifies bearer tokens of requests API Gateway did not authenticate.
+    pub jwt_verifier: Option<JwtVerifier>,
+    /// Holds the API keys, which change without a redeploy.
+    pub configuration: ConfigurationCache,
+}
+
+/// Maps the route to the status it sets:
+/// `DELETE /links/{linkId}`, `POST /links/{linkId}/disable` and `POST /links/{linkId}/enable`.
+fn requested_status(event: &Request) -> Option<LinkStatus> {
+    let path = event.uri().path();
+    match *event.method() {
+        Method::DELETE => Some(LinkStatus::Deleted),
+        Method::POST if path.ends_with("/disable") => Some(LinkStatus::Disabled),
+        Method::POST if path.ends_with("/enable") => Some(LinkStatus::Active),
+        _ => None,
+    }
+}
+
+/// Callers only change their own links, admins any link. The links of other
+/// owners are reported as missing, like in update_link. Configured API keys
+/// are required as on every mutating endpoint, see `require_api_key`.
+#[tracing::instrument(skip(deps, event), fields(api_key.label))]
+pub(crate) async fn function_handler<R: UrlRepository, E: EventPublisher>(
+    deps: &HandlerDeps<R, E>,
+    event: Request,
+) -> Result<impl IntoResponse, Error> {
+    tracing::info!("Received {} {}", event.method(), event.uri().path());
+
+    let caller = match authenticate_caller(&event, deps.jwt_verifier.as_ref()).await {
+        Ok(caller) => caller,
+        Err(e) => {
+            tracing::info!("Refusing request: {}", e);
+            return jwt_error_response(&e);
+        }
+    };
+    let configuration = match deps.configuration.current().await {
+        Ok(configuration) => configuration,
+        Err(e) => {
+            tracing::error!("Refusing request: {}", e);
+            return configuration_error_response(&e);
+        }
+    };
+    if let Err(e) = require_api_key(&event, &configuration.api_keys) {
+        tracing::info!("Refusing request: {}", e);
+        return api_key_error_response(&e);
+    }
+
+    let link_id = event
+        .path_parameters_ref()
+        .and_then(|params| params.first("linkId"))
+        .unwrap_or("");
+
+    if link_id.is_empty() {
+        return empty_response(&StatusCode::NOT_FOUND);
+    }
+    let Some(status) = requested_status(&event) else {
+        return empty_response(&StatusCode::METHOD_NOT_ALLOWED);
+    };
+
+    let short_url = match deps
+        .url_repo
+        .set_link_status(link_id, caller.owner_filter(), status)
+        .await
+    {
+        Ok(short_url) => short_url,
+        Err(e) => {
+            tracing::error!("Failed to update link status: {:?}", e);
+            return repository_error_response(&e);
+        }
+    };
+
+    let publish_result = deps
+        .event_publisher
+        .publish_link_status_changed(&short_url)
+        .await;
+    if let Err(e) = &publish_result {
+        tracing::error!("Failed to publish link status changed event: {:?}", e);
+    }
+
+    match status {
+        LinkStatus::Deleted => empty_response(&StatusCode::NO_CONTENT),
+        _ => json_response(&StatusCode::OK, &short_url),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{function_handler, HandlerDeps};
+    use crate::event_publisher::MockEventPublisher;
+    use lambda_http::http::Request;
+    use lambda_http::{Body, IntoResponse, RequestExt};
+    use mockall::predicate::{eq, function};
+    use shared::auth::{jwt_request_context, ADMIN_SCOPE};
+    use shared::configuration::{Configuration, ConfigurationCache};
+    use shared::core::{LinkStatus, MockUrlRepository, RepositoryError, ShortUrl};
+    use std::collections::HashMap;
+
+    fn create_deps(
+        url_repo: MockUrlRepository,
+        event_publisher: MockEventPublisher,
+    ) -> HandlerDeps<MockUrlRepository, MockEventPublisher> {
+        HandlerDeps {
+            url_repo,
+            event_publisher,
+            jwt_verifier: None,
+            configuration: ConfigurationCache::fixed(Configuration::default()),
+        }
+    }
+
+    fn create_request(method: &str, uri: &str, link_id: &str) -> lambda_http::Request {
+        let mut path_params = HashMap::new();
+        path_params.insert("linkId".to_string(), link_id.to_string());
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::Empty)
+            .unwrap()
+            .with_path_parameters(path_params)
+            .with_request_context(jwt_request_context("user-1", &[]))
+    }
+
+    #[tokio::test]
+    async fn when_route_matches_should_set_status_and_publish() {
+        let cases = [
+            ("DELETE", "/links/abc123", LinkStatus::Deleted, 204),
+            ("POST", "/links/abc123/disable", LinkStatus::Disabled, 200),
+            ("POST", "/links/abc123/enable", LinkStatus::Active, 200),
+        ];
+
+        for (method, uri, status, expected_status) in cases {
+            let mut mock_url_repo = MockUrlRepository::default();
+            mock_url_repo
+                .expect_set_link_status()
+                .times(1)
+                .with(
+                    eq("abc123".to_string()),
+                    eq(Some("user-1".to_string())),
+                    eq(status),
+                )
+                .returning(|link_id, _owner_id, status| {
+                    Ok(ShortUrl {
+                        status,
+                        ..ShortUrl::new(link_id.to_string(), "https://example.com".into())
+                    })
+                });
+            let mut event_publisher = MockEventPublisher::new();
+            event_publisher
+                .expect_publish_link_status_changed()
+                .times(1)
+                .with(function(move |short_url: &ShortUrl| {
+                    short_url.status == status
+                }))
+                .returning(|_| Ok(()));
+            let deps = create_deps(mock_url_repo, event_publisher);
+
+            let data = function_handler(&deps, create_request(method, uri, "abc123"))
+                .await
+                .unwrap()
+                .into_response()
+                .await;
+
+            assert_eq!(data.status(), expected_status, "{} {}", method, uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn when_link_is_missing_or_deleted_should_return_404_without_publishing() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo.expect_set_link_status().times(1).returning(
+            |_link_id, _owner_id, _status| Err(RepositoryError::NotFound("abc123".to_string())),
+        );
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher
+            .expect_publish_link_status_changed()
+            .times(0);
+        let deps = create_deps(mock_url_repo, event_publisher);
+
+        let data = function_handler(
+            &deps,
+            create_request("POST", "/links/abc123/enable", "abc123"),
+        )
+        .await
+        .unwrap()
+        .into_response()
+        .await;
+
+        assert_eq!(data.status(), 404);
+    }
+
+    #[tokio::test]
+    async fn when_route_is_unknown_should_return_405() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo.expect_set_link_status().times(0);
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher
+            .expect_publish_link_status_changed()
+            .times(0);
+        let deps = create_deps(mock_url_repo, event_publisher);
+
+        let data = function_handler(
+            &deps,
+            create_request("POST", "/links/abc123/archive", "abc123"),
+        )
+        .await
+        .unwrap()
+        .into_response()
+        .await;
+
+        assert_eq!(data.status(), 405);
+    }
+
+    #[tokio::test]
+    async fn when_publish_fails_should_still_delete() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo
+            .expect_set_link_status()
+            .times(1)
+            .returning(|link_id, _owner_id, status| {
+                Ok(ShortUrl {
+                    status,
+                    ..ShortUrl::new(link_id.to_string(), "https://example.com".into())
+                })
+            });
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher
+            .expect_publish_link_status_changed()
+            .times(1)
+            .returning(|_| Err(Box::new(std::io::Error::other("publish failed"))));
+        let deps = create_deps(mock_url_repo, event_publisher);
+
+        let data = function_handler(&deps, create_request("DELETE", "/links/abc123", "abc123"))
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+
+        assert_eq!(data.status(), 204);
+    }
+
+    #[tokio::test]
+    async fn when_api_keys_are_configured_should_require_one_for_every_route() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo
+            .expect_set_link_status()
+            .times(1)
+            .with(
+                eq("abc123".to_string()),
+                eq(Some("user-1".to_string())),
+                eq(LinkStatus::Deleted),
+            )
+            .returning(|link_id, _owner_id, status| {
+                Ok(ShortUrl {
+                    status,
+                    ..ShortUrl::new(link_id.to_string(), "https://example.com".into())
+                })
+            });
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher
+            .expect_publish_link_status_changed()
+            .times(1)
+            .returning(|_| Ok(()));
+        let configuration: Configuration = serde_json::from_value(serde_json::json!({
+            "table_name": "links",
+            "api_keys": [{"label": "ci-2026-10", "key": "new-secret"}]
+        }))
+        .unwrap();
+        let deps = HandlerDeps {
+            configuration: ConfigurationCache::fixed(configuration),
+            ..create_deps(mock_url_repo, event_publisher)
+        };
+        let request = |method: &str, uri: &str, api_key: Option<&str>| {
+            let mut request = create_request(method, uri, "abc123");
+            if let Some(api_key) = api_key {
+                request
+                    .headers_mut()
+                    .insert("x-api-key", api_key.parse().unwrap());
+            }
+            request
+        };
+
+        let cases = [
+            ("DELETE", "/links/abc123", None, 401),
+            ("POST", "/links/abc123/disable", None, 401),
+            ("POST", "/links/abc123/enable", None, 401),
+            ("DELETE", "/links/abc123", Some("old-secret"), 403),
+            ("POST", "/links/abc123/disable", Some("old-secret"), 403),
+            ("POST", "/links/abc123/enable", Some("old-secret"), 403),
+            ("DELETE", "/links/abc123", Some("new-secret"), 204),
+        ];
+        for (method, uri, api_key, status) in cases {
+            let data = function_handler(&deps, request(method, uri, api_key))
+                .await
+                .unwrap()
+                .into_response()
+                .await;
+
+            assert_eq!(data.status(), status, "{} {} {:?}", method, uri, api_key);
+        }
+    }
+
+    #[tokio::test]
+    async fn when_configuration_could_not_be_loaded_should_return_503() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo.expect_set_link_status().times(0);
+        let deps = HandlerDeps {
+            configuration: ConfigurationCache::unavailable(),
+            ..create_deps(mock_url_repo, MockEventPublisher::new())
+        };
+
+        let data = function_handler(&deps, create_request("DELETE", "/links/abc123", "abc123"))
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+
+        assert_eq!(data.status(), 503);
+    }
+
+    #[tokio::test]
+    async fn when_request_is_not_authenticated_should_return_401() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo.expect_set_link_status().times(0);
+        let deps = create_deps(mock_url_repo, MockEventPublisher::new());
+        let request = Request::builder()
+            .method("DELETE")
+            .uri("/links/abc123")
+            .body(Body::Empty)
+            .unwrap()
+            .with_path_parameters(HashMap::from([(
+                "linkId".to_string(),
+                "abc123".to_string(),
+            )]));
+
+        let data = function_handler(&deps, request)
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+
+        assert_eq!(data.status(), 401);
+    }
+
+    #[tokio::test]
+    async fn when_caller_is_admin_should_change_links_of_any_owner() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo
+            .expect_set_link_status()
+            .times(1)
+            .with(eq("abc123".to_string()), eq(None), eq(LinkStatus::Disabled))
+            .returning(|link_id, _owner_id, status| {
+                Ok(ShortUrl {
+                    status,
+                    ..ShortUrl::new(link_id.to_string(), "https://example.com".into())
+                })
+            });
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher
+            .expect_publish_link_status_changed()
+            .returning(|_| Ok(()));
+        let deps = create_deps(mock_url_repo, event_publisher);
+        let request = create_request("POST", "/links/abc123/disable", "abc123")
+            .with_request_context(jwt_request_context("admin-1", &[ADMIN_SCOPE]));
+
+        let data = function_handler(&deps, request)
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+
+        assert_eq!(data.status(), 200);
+    }
+}
@@ -0,0 +1,279 @@
+use bytes::Bytes;
+use lambda_http::Error;
+use serde::Serialize;
+use shared::core::ShortUrl;
+use std::borrow::Cow;
+use std::str::FromStr;
+
+/// CSV columns, in the order of the `ShortUrl` fields. Password hashes are not
+/// exported, only whether a link has one, so a restore has to set them again.
+pub const CSV_COLUMNS: [&str; 22] = [
+    "link_id",
+    "original_link",
+    "owner_id",
+    "url_hash",
+    "clicks",
+    "title",
+    "description",
+    "content_type",
+    "expires_at",
+    "max_clicks",
+    "status",
+    "version",
+    "history",
+    "tags",
+    "password_protected",
+    "targets",
+    "rules",
+    "query_mode",
+    "override_query",
+    "utm",
+    "redirect_type",
+    "interstitial",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Csv,
+    Ndjson,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(ExportFormat::Csv),
+            "ndjson" => Ok(ExportFormat::Ndjson),
+            other => Err(format!("unknown export format '{}'", other)),
+        }
+    }
+}
+
+/// A `ShortUrl` flattened into CSV cells. The history, targets and rules become
+/// JSON arrays and the UTM parameters a JSON object, tags are separated by
+/// spaces (they cannot contain any). The scraped details come from pages anyone
+/// controls, so they are made safe to open in a spreadsheet.
+#[derive(Serialize)]
+struct CsvRow<'a> {
+    link_id: &'a str,
+    original_link: &'a str,
+    owner_id: Option<&'a str>,
+    url_hash: Option<&'a str>,
+    clicks: u32,
+    title: Option<Cow<'a, str>>,
+    description: Option<Cow<'a, str>>,
+    content_type: Option<Cow<'a, str>>,
+    expires_at: Option<u64>,
+    max_clicks: Option<u32>,
+    status: &'static str,
+    version: u64,
+    history: String,
+    tags: String,
+    password_protected: bool,
+    targets: String,
+    rules: String,
+    query_mode: &'static str,
+    override_query: bool,
+    utm: String,
+    redirect_type: u16,
+    interstitial: bool,
+}
+
+impl<'a> TryFrom<&'a ShortUrl> for CsvRow<'a> {
+    type Error = serde_json::Error;
+
+    fn try_from(short_url: &'a ShortUrl) -> Result<Self, Self::Error> {
+        Ok(CsvRow {
+            link_id: &short_url.link_id,
+            original_link: &short_url.original_link,
+            owner_id: short_url.owner_id.as_deref(),
+            url_hash: short_url.url_hash.as_deref(),
+            clicks: short_url.clicks,
+            title: short_url.title.as_deref().map(spreadsheet_safe),
+            description: short_url.description.as_deref().map(spreadsheet_safe),
+            content_type: short_url.content_type.as_deref().map(spreadsheet_safe),
+            expires_at: short_url.expires_at,
+            max_clicks: short_url.max_clicks,
+            status: short_url.status.as_str(),
+            version: short_url.version,
+            history: serde_json::to_string(&short_url.history)?,
+            tags: short_url.tags.join(" "),
+            password_protected: short_url.password.is_some(),
+            targets: serde_json::to_string(&short_url.targets)?,
+            rules: serde_json::to_string(&short_url.rules)?,
+            query_mode: short_url.query_mode.as_str(),
+            override_query: short_url.override_query,
+            utm: serde_json::to_string(&short_url.utm)?,
+            redirect_type: short_url.redirect_type.status_code(),
+            interstitial: short_url.interstitial,
+        })
+    }
+}
+
+/// Spreadsheets run cells starting with one of these as formulas.
+const FORMULA_PREFIXES: [char; 4] = ['=', '+', '-', '@'];
+
+/// Quotes a cell that would otherwise be run as a formula, the way
+/// spreadsheets mark text themselves.
+fn spreadsheet_safe(cell: &str) -> Cow<'_, str> {
+    if cell.starts_with(FORMULA_PREFIXES) {
+        Cow::Owned(format!("'{}", cell))
+    } else {
+        Cow::Borrowed(cell)
+    }
+}
+
+/// A `ShortUrl` as one NDJSON line. Its password is never serialised, so the
+/// line says whether it had one.
+#[derive(Serialize)]
+struct NdjsonLine<'a> {
+    #[serde(flatten)]
+    short_url: &'a ShortUrl,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    password_protected: bool,
+}
+
+impl ExportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    /// What comes before the first row, if anything.
+    pub fn preamble(&self) -> Result<Option<Bytes>, Error> {
+        match self {
+            ExportFormat::Csv => {
+                let mut writer = csv::Writer::from_writer(Vec::new());
+                writer.write_record(CSV_COLUMNS)?;
+                Ok(Some(writer.into_inner()?.into()))
+            }
+            ExportFormat::Ndjson => Ok(None),
+        }
+    }
+
+    /// Encodes one page of links, one line per link.
+    pub fn encode(&self, short_urls: &[ShortUrl]) -> Result<Bytes, Error> {
+        match self {
+            ExportFormat::Csv => {
+                let mut writer = csv::WriterBuilder::new()
+                    .has_headers(false)
+                    .from_writer(Vec::new());
+                for short_url in short_urls {
+                    writer.serialize(CsvRow::try_from(short_url)?)?;
+                }
+                Ok(writer.into_inner()?.into())
+            }
+            ExportFormat::Ndjson => {
+                let mut lines = Vec::new();
+                for short_url in short_urls {
+                    let line = NdjsonLine {
+                        short_url,
+                        password_protected: short_url.password.is_some(),
+                    };
+                    serde_json::to_writer(&mut lines, &line)?;
+                    lines.push(b'\n');
+                }
+                Ok(lines.into())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ExportFormat, CSV_COLUMNS};
+    use serde_json::Value;
+    use shared::core::{DestinationChange, ShortUrl};
+    use shared::password::LinkPassword;
+
+    #[test]
+    fn when_link_is_encoded_as_csv_should_fill_every_column() {
+        let short_url = ShortUrl {
+            owner_id: Some("user-1".into()),
+            title: Some("Example, with a comma".into()),
+            description: Some("An example".into()),
+            content_type: Some("text/html".into()),
+            max_clicks: Some(10),
+            version: 1,
+            history: vec![DestinationChange {
+                original_link: "https://old.example.com".into(),
+                replaced_at: 1_700_000_000,
+            }],
+            tags: vec!["sale".into(), "spring".into()],
+            ..ShortUrl::new("abc123".into(), "https://example.com".into())
+        };
+
+        let encoded = ExportFormat::Csv.encode(&[short_url]).unwrap();
+
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(encoded.as_ref());
+        let record = reader.records().next().unwrap().unwrap();
+        assert_eq!(record.len(), CSV_COLUMNS.len());
+        assert_eq!(&record[0], "abc123");
+        assert_eq!(&record[5], "Example, with a comma");
+        assert_eq!(&record[7], "text/html");
+        assert_eq!(&record[8], "");
+        assert_eq!(&record[10], "active");
+        assert_eq!(
+            &record[12],
+            r#"[{"original_link":"https://old.example.com","replaced_at":1700000000}]"#
+        );
+        assert_eq!(&record[13], "sale spring");
+        assert_eq!(&record[14], "false");
+        assert_eq!(&record[15], "[]");
+        assert_eq!(&record[17], "ignore");
+        assert_eq!(&record[19], "{}");
+        assert_eq!(&record[20], "302");
+        assert_eq!(&record[21], "false");
+    }
+
+    #[test]
+    fn when_link_is_password_protected_should_say_so_without_the_hash() {
+        let short_url = ShortUrl {
+            password: Some(LinkPassword {
+                hash: "$argon2id$secret-hash".into(),
+                ..Default::default()
+            }),
+            ..ShortUrl::new("abc123".into(), "https://example.com".into())
+        };
+
+        let csv = ExportFormat::Csv
+            .encode(std::slice::from_ref(&short_url))
+            .unwrap();
+        let ndjson = ExportFormat::Ndjson.encode(&[short_url]).unwrap();
+
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(csv.as_ref());
+        let record = reader.records().next().unwrap().unwrap();
+        assert_eq!(&record[14], "true");
+        let line: Value = serde_json::from_slice(&ndjson).unwrap();
+        assert_eq!(line["password_protected"], true);
+        assert!(!String::from_utf8_lossy(&csv).contains("secret-hash"));
+        assert!(!String::from_utf8_lossy(&ndjson).contains("secret-hash"));
+    }
+
+    #[test]
+    fn when_scraped_details_start_like_a_formula_should_quote_them() {
+        let short_url = ShortUrl {
+            title: Some("=HYPERLINK(\"https://evil.example\")".into()),
+            description: Some("-2+3".into()),
+            content_type: Some("@SUM(A1)".into()),
+            ..ShortUrl::new("abc123".into(), "https://example.com".into())
+        };
+
+        let encoded = ExportFormat::Csv.encode(&[short_url]).unwrap();
+
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(encoded.as_ref());
+        let record = reader.records().next().unwrap().unwrap();
+        assert_eq!(&record[5], "'=HYPERLINK(\"https://evil.example\")");
+        assert_eq!(&record[6], "'-2+3");
+        assert_eq!(&record[7], "'@SUM(A1)");
+    }
+}
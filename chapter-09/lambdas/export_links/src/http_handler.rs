@@ -0,0 +1,226 @@
+use crate::format::ExportFormat;
+use bytes::Bytes;
+use futures::stream::{self, BoxStream, StreamExt};
+use http_body::Frame;
+use http_body_util::StreamBody;
+use lambda_http::http::header::CONTENT_TYPE;
+use lambda_http::{http::StatusCode, tracing, Error, Request, RequestExt, Response};
+use shared::core::{ListOptions, PageKey, UrlRepository, MAX_PAGE_SIZE};
+use std::sync::Arc;
+
+/// A body written one page of links at a time.
+pub(crate) type ExportBody = StreamBody<BoxStream<'static, Result<Frame<Bytes>, Error>>>;
+
+pub(crate) struct HandlerDeps<R: UrlRepository> {
+    pub url_repo: R,
+}
+
+/// Where the scan is at between two pages.
+enum ScanState {
+    Start,
+    Next(PageKey),
+    Done,
+}
+
+/// Handles `GET /links/export?format=csv|ndjson`.
+///
+/// Every link is exported, whatever its owner or status, so the function is only
+/// reachable through an IAM-authenticated function URL: HTTP APIs cannot stream.
+#[tracing::instrument(skip(deps, event))]
+pub(crate) async fn function_handler<R: UrlRepository + Send + Sync + 'static>(
+    deps: Arc<HandlerDeps<R>>,
+    event: Request,
+) -> Result<Response<ExportBody>, Error> {
+    tracing::info!("Received {} {}", event.method(), event.uri().path());
+    let format = match event
+        .query_string_parameters_ref()
+        .and_then(|params| params.first("format"))
+        .unwrap_or("csv")
+        .parse::<ExportFormat>()
+    {
+        Ok(format) => format,
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, &e),
+    };
+
+    let preamble = stream::iter(format.preamble().transpose());
+    let pages = stream::unfold(ScanState::Start, move |state| {
+        let deps = deps.clone();
+        async move {
+            let start_key = match state {
+                ScanState::Start => None,
+                ScanState::Next(start_key) => Some(start_key),
+                ScanState::Done => return None,
+            };
+            let options = ListOptions {
+                start_key,
+                limit: MAX_PAGE_SIZE,
+                include_inactive: true,
+                ..Default::default()
+            };
+            match deps.url_repo.list_urls(options).await {
+                Ok(page) => {
+                    let next_state = page.next_key.map_or(ScanState::Done, ScanState::Next);
+                    Some((format.encode(&page.short_urls), next_state))
+                }
+                Err(e) => {
+                    // The status is already sent, all we can do is cut the body short
+                    tracing::error!("Failed to list URLs during export: {:?}", e);
+                    Some((Err(e.into()), ScanState::Done))
+                }
+            }
+        }
+    });
+    let body = preamble
+        .chain(pages)
+        .map(|chunk| chunk.map(Frame::data))
+        .boxed();
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, format.content_type())
+        .body(StreamBody::new(body))?)
+}
+
+fn error_response(status: StatusCode, message: &str) -> Result<Response<ExportBody>, Error> {
+    let body = Bytes::from(serde_json::json!({ "message": message }).to_string());
+    Ok(Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(StreamBody::new(
+            stream::once(async { Ok(Frame::data(body)) }).boxed(),
+        ))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{function_handler, HandlerDeps};
+    use http_body_util::BodyExt;
+    use lambda_http::http::Request;
+    use lambda_http::{Body, RequestExt};
+    use mockall::predicate::function;
+    use serde_json::Value;
+    use shared::core::{ListOptions, MockUrlRepository, Page, PageKey, RepositoryError, ShortUrl};
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    fn create_request(format: &str) -> lambda_http::Request {
+        Request::builder()
+            .uri("/links/export")
+            .body(Body::Empty)
+            .unwrap()
+            .with_query_string_parameters(HashMap::from([(
+                "format".to_string(),
+                format.to_string(),
+            )]))
+    }
+
+    fn link_key(link_id: &str) -> PageKey {
+        PageKey::from([("LinkId".to_string(), link_id.to_string())])
+    }
+
+    /// Two pages, `a` then `b`, the second found through the first's key.
+    fn two_page_repo() -> MockUrlRepository {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo
+            .expect_list_urls()
+            .times(1)
+            .with(function(|options: &ListOptions| {
+                options.start_key.is_none() && options.include_inactive
+            }))
+            .returning(|_options| {
+                Ok(Page {
+                    short_urls: vec![ShortUrl {
+                        title: Some("Example A".into()),
+                        ..ShortUrl::new("a".into(), "https://example.com/a".into())
+                    }],
+                    next_key: Some(link_key("a")),
+                })
+            });
+        mock_url_repo
+            .expect_list_urls()
+            .times(1)
+            .with(function(|options: &ListOptions| {
+                options.start_key == Some(link_key("a"))
+            }))
+            .returning(|_options| {
+                Ok(Page {
+                    short_urls: vec![ShortUrl::new("b".into(), "https://example.com/b".into())],
+                    next_key: None,
+                })
+            });
+        mock_url_repo
+    }
+
+    #[tokio::test]
+    async fn when_csv_requested_should_stream_a_header_and_every_page() {
+        let deps = Arc::new(HandlerDeps {
+            url_repo: two_page_repo(),
+        });
+
+        let response = function_handler(deps, create_request("csv")).await.unwrap();
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            response.headers()["content-type"],
+            "text/csv; charset=utf-8"
+        );
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let lines: Vec<&str> = std::str::from_utf8(&body).unwrap().lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("link_id,original_link,owner_id"));
+        assert!(lines[1].starts_with("a,https://example.com/a,,,0,Example A,"));
+        assert!(lines[2].starts_with("b,"));
+    }
+
+    #[tokio::test]
+    async fn when_ndjson_requested_should_stream_one_link_per_line() {
+        let deps = Arc::new(HandlerDeps {
+            url_repo: two_page_repo(),
+        });
+
+        let response = function_handler(deps, create_request("ndjson"))
+            .await
+            .unwrap();
+
+        assert_eq!(response.headers()["content-type"], "application/x-ndjson");
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let links: Vec<Value> = std::str::from_utf8(&body)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(links.len(), 2);
+        assert_eq!(links[0]["title"], "Example A");
+        assert_eq!(links[1]["link_id"], "b");
+    }
+
+    #[tokio::test]
+    async fn when_format_is_unknown_should_return_400() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo.expect_list_urls().times(0);
+        let deps = Arc::new(HandlerDeps {
+            url_repo: mock_url_repo,
+        });
+
+        let response = function_handler(deps, create_request("xml")).await.unwrap();
+
+        assert_eq!(response.status(), 400);
+    }
+
+    #[tokio::test]
+    async fn when_listing_fails_mid_export_should_end_the_stream_with_an_error() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo
+            .expect_list_urls()
+            .times(1)
+            .returning(|_options| Err(RepositoryError::Transient("timeout".to_string())));
+        let deps = Arc::new(HandlerDeps {
+            url_repo: mock_url_repo,
+        });
+
+        let response = function_handler(deps, create_request("csv")).await.unwrap();
+
+        assert_eq!(response.status(), 200);
+        assert!(response.into_body().collect().await.is_err());
+    }
+}
@@ -1,16 +1,41 @@
 use figment::providers::Env;
 use figment::Figment;
 use serde::{Deserialize, Serialize};
+use shared::jwt::JwtSettings;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub(crate) struct Config {
     pub table_name: String,
+    /// Keep links in memory rather than in DynamoDB, see `adapters::url_repository`.
+    #[serde(default)]
+    pub in_memory_repository: bool,
+    /// The tag index, see `DynamoDbUrlRepository::with_tags_table`.
+    pub tags_table_name: String,
+    /// The search index, see `DynamoDbUrlRepository::with_search_table`.
+    pub search_table_name: String,
+    /// HMAC key that signs pagination cursors.
+    pub cursor_secret: String,
+    /// Where short links are served, e.g. `https://sho.rt`, encoded in QR codes.
+    pub public_base_url: String,
+    /// Set when bearer tokens are checked here rather than only by API Gateway.
+    #[serde(default)]
+    pub jwt: Option<JwtSettings>,
 }
 
 impl Config {
-    pub fn load() -> Result<Self, figment::Error> {
+    pub fn load() -> Result<Self, Box<figment::Error>> {
         Figment::new()
-            .merge(Env::raw().only(&["TABLE_NAME"]))
+            .merge(Env::raw().only(&[
+                "TABLE_NAME",
+                "IN_MEMORY_REPOSITORY",
+                "CURSOR_SECRET",
+                "TAGS_TABLE_NAME",
+                "SEARCH_TABLE_NAME",
+                "PUBLIC_BASE_URL",
+            ]))
+            // e.g. JWT_ISSUER, JWT_AUDIENCE and JWT_JWKS_URL or JWT_JWKS
+            .merge(Env::prefixed("JWT_").map(|key| format!("jwt.{}", key).into()))
             .extract()
+            .map_err(Box::new)
     }
 }
@@ -1,29 +1,156 @@
+use crate::qr;
 use lambda_http::RequestExt;
-use lambda_http::{http::StatusCode, tracing, Error, IntoResponse, Request};
-use shared::core::UrlRepository;
-use shared::utils::{empty_response, json_response};
+use lambda_http::{http::StatusCode, tracing, Body, Error, IntoResponse, Request, Response};
+use shared::auth::authenticate_caller;
+use shared::core::{
+    ListOptions, ListShortUrlsResponse, Page, RepositoryError, SortOrder, UrlRepository,
+    MAX_PAGE_SIZE,
+};
+use shared::cursor::{CursorQuery, CursorSigner};
+use shared::jwt::JwtVerifier;
+use shared::search::query_terms;
+use shared::tags::validate_tag;
+use shared::utils::{
+    empty_response, json_error_response, json_response, jwt_error_response,
+    repository_error_response,
+};
 
 pub(crate) struct HandlerDeps<R: UrlRepository> {
     pub url_repo: R,
+    pub cursor_signer: CursorSigner,
+    /// Verifies bearer tokens of requests API Gateway did not authenticate.
+    pub jwt_verifier: Option<JwtVerifier>,
+    /// Where short links are served, for QR codes.
+    pub public_base_url: String,
 }
 
+/// Handles `GET /links`, `GET /links/search` and `GET /links/{linkId}/qr`.
 #[tracing::instrument(skip(deps, event))]
 pub(crate) async fn function_handler<R: UrlRepository>(
     deps: &HandlerDeps<R>,
     event: Request,
 ) -> Result<impl IntoResponse, Error> {
-    tracing::info!("Received event: {:?}", event);
+    tracing::info!("Received {} {}", event.method(), event.uri().path());
+    let caller = match authenticate_caller(&event, deps.jwt_verifier.as_ref()).await {
+        Ok(caller) => caller,
+        Err(e) => {
+            tracing::info!("Refusing request: {}", e);
+            return jwt_error_response(&e);
+        }
+    };
+    if let Some(link_id) = event
+        .path_parameters_ref()
+        .and_then(|params| params.first("linkId"))
+        .filter(|_| event.uri().path().ends_with("/qr"))
+    {
+        return qr::qr_code(deps, &event, link_id).await;
+    }
     let query_params = event.query_string_parameters();
-    let last_evaluated_id = query_params
-        .first("last_evaluated_id")
-        .map(|s| s.to_string());
+    let scan_all = query_params.first("scope") == Some("all");
+
+    let mut options = ListOptions {
+        include_inactive: query_params.first("include") == Some("disabled"),
+        ..Default::default()
+    };
+    if let Some(limit) = query_params.first("limit") {
+        match limit.parse::<usize>() {
+            Ok(limit) if (1..=MAX_PAGE_SIZE).contains(&limit) => options.limit = limit,
+            _ => {
+                return json_error_response(
+                    &StatusCode::BAD_REQUEST,
+                    &format!("limit must be between 1 and {}", MAX_PAGE_SIZE),
+                )
+            }
+        }
+    }
+    // `GET /links/search?q=` pages through the caller's links by relevance
+    let search = event
+        .uri()
+        .path()
+        .ends_with("/links/search")
+        .then(|| query_params.first("q").unwrap_or_default().to_string());
+    if let Some(query) = &search {
+        if query_terms(query).is_empty() {
+            return json_error_response(
+                &StatusCode::BAD_REQUEST,
+                "q must contain a word to search for",
+            );
+        }
+    } else {
+        if let Some(tag) = query_params.first("tag") {
+            if let Err(e) = validate_tag(tag) {
+                return json_error_response(&StatusCode::BAD_REQUEST, &e.to_string());
+            }
+            options.tag = Some(tag.to_string());
+        }
+        match query_params.first("sort") {
+            None | Some("asc") => {}
+            // A scan has no order to reverse
+            Some("desc") if !scan_all => options.sort = SortOrder::Descending,
+            Some("desc") => {
+                return json_error_response(
+                    &StatusCode::BAD_REQUEST,
+                    "sort is not supported with scope=all",
+                )
+            }
+            Some(_) => {
+                return json_error_response(
+                    &StatusCode::BAD_REQUEST,
+                    "sort must be 'asc' or 'desc'",
+                )
+            }
+        }
+    }
+    let cursor_query = CursorQuery {
+        owner_id: (search.is_some() || !scan_all).then(|| caller.owner_id.clone()),
+        sort: options.sort,
+        tag: options.tag.clone(),
+        search: search.clone(),
+    };
+    if let Some(cursor) = query_params.first("cursor") {
+        match deps.cursor_signer.decode(cursor, &cursor_query) {
+            Ok(start_key) => options.start_key = Some(start_key),
+            Err(e) => return json_error_response(&StatusCode::BAD_REQUEST, &e.to_string()),
+        }
+    }
 
-    let links = deps.url_repo.list_urls(last_evaluated_id).await;
-    match links {
-        Ok(links) => json_response(&StatusCode::OK, &links),
+    let page = if let Some(query) = &search {
+        deps.url_repo
+            .search_urls(&caller.owner_id, query, options)
+            .await
+    } else if scan_all {
+        // Scanning every owner's links is an admin-only mode
+        if !caller.is_admin {
+            return empty_response(&StatusCode::FORBIDDEN);
+        }
+        deps.url_repo.list_urls(options).await
+    } else {
+        deps.url_repo
+            .list_urls_for_owner(&caller.owner_id, options)
+            .await
+    };
+    page_response(deps, &cursor_query, page)
+}
+
+/// Responds with a page of links and the cursor of the next one.
+fn page_response<R: UrlRepository>(
+    deps: &HandlerDeps<R>,
+    cursor_query: &CursorQuery,
+    page: Result<Page, RepositoryError>,
+) -> Result<Response<Body>, Error> {
+    match page {
+        Ok(page) => json_response(
+            &StatusCode::OK,
+            &ListShortUrlsResponse {
+                short_urls: page.short_urls,
+                next_cursor: page
+                    .next_key
+                    .map(|next_key| deps.cursor_signer.encode(&next_key, cursor_query)),
+            },
+        ),
         Err(e) => {
             tracing::error!("Failed to list URLs: {:?}", e);
-            empty_response(&StatusCode::INTERNAL_SERVER_ERROR)
+            repository_error_response(&e)
         }
     }
 }
@@ -33,67 +160,212 @@ mod tests {
     use super::{function_handler, HandlerDeps};
     use lambda_http::http::Request;
     use lambda_http::{Body, IntoResponse, RequestExt};
-    use mockall::predicate::eq;
-    use shared::core::{MockUrlRepository, ShortUrl};
+    use mockall::predicate::{eq, function};
+    use serde_json::Value;
+    use shared::auth::{jwt_request_context, ADMIN_SCOPE};
+    use shared::core::{
+        LinkStatus, ListOptions, MockUrlRepository, Page, PageKey, RepositoryError, ShortUrl,
+        SortOrder,
+    };
+    use shared::cursor::{CursorQuery, CursorSigner};
+    use shared::jwt;
     use std::collections::HashMap;
 
+    fn create_request(query: &[(&str, &str)], scopes: &[&str]) -> lambda_http::Request {
+        let query_string: HashMap<String, String> = query
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        Request::builder()
+            .header("Content-Type", "application/json")
+            .body(Body::Empty)
+            .unwrap()
+            .with_query_string_parameters(query_string)
+            .with_request_context(jwt_request_context("user-1", scopes))
+    }
+
+    fn create_deps(url_repo: MockUrlRepository) -> HandlerDeps<MockUrlRepository> {
+        HandlerDeps {
+            url_repo,
+            cursor_signer: CursorSigner::new("test-secret"),
+            jwt_verifier: None,
+            public_base_url: "https://sho.rt".into(),
+        }
+    }
+
+    fn owner_key(link_id: &str) -> PageKey {
+        PageKey::from([
+            ("LinkId".to_string(), link_id.to_string()),
+            ("OwnerId".to_string(), "user-1".to_string()),
+        ])
+    }
+
     #[tokio::test]
     async fn when_valid_request_made_should_return() {
         let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo.expect_list_urls().times(0);
         mock_url_repo
-            .expect_list_urls()
+            .expect_list_urls_for_owner()
             .times(1)
-            .with(eq(None))
-            .returning(|_last_evaluated_id| {
-                Ok((
-                    vec![ShortUrl::new(
+            .with(eq("user-1".to_string()), eq(ListOptions::default()))
+            .returning(|_owner_id, _options| {
+                Ok(Page {
+                    short_urls: vec![ShortUrl::new(
                         "12345689".into(),
                         "https://google.com".into(),
                     )],
-                    None,
-                ))
+                    next_key: None,
+                })
             });
-        let deps = HandlerDeps {
-            url_repo: mock_url_repo,
-        };
-        let request = Request::builder()
-            .header("Content-Type", "application/json")
-            .body(Body::Empty)
-            .unwrap();
+        let deps = create_deps(mock_url_repo);
+        let request = create_request(&[], &[]);
 
         let result = function_handler(&deps, request).await;
 
         assert!(result.is_ok());
         let data = result.unwrap().into_response().await;
         assert_eq!(data.status(), 200);
+        let body: Value = serde_json::from_slice(data.body()).unwrap();
+        assert_eq!(body["short_urls"][0]["link_id"], "12345689");
+        assert_eq!(body["next_cursor"], Value::Null);
+    }
+
+    #[tokio::test]
+    async fn when_page_is_full_should_return_a_cursor_that_resumes_it() {
+        let mut first_repo = MockUrlRepository::default();
+        first_repo
+            .expect_list_urls_for_owner()
+            .times(1)
+            .returning(|_owner_id, _options| {
+                Ok(Page {
+                    short_urls: vec![ShortUrl::new("abc".into(), "https://google.com".into())],
+                    next_key: Some(owner_key("abc")),
+                })
+            });
+        let data = function_handler(
+            &create_deps(first_repo),
+            create_request(&[("sort", "desc")], &[]),
+        )
+        .await
+        .unwrap()
+        .into_response()
+        .await;
+        let body: Value = serde_json::from_slice(data.body()).unwrap();
+        let cursor = body["next_cursor"].as_str().unwrap().to_string();
+        assert!(!cursor.contains("abc"));
+
+        let mut second_repo = MockUrlRepository::default();
+        second_repo
+            .expect_list_urls_for_owner()
+            .times(1)
+            .with(
+                eq("user-1".to_string()),
+                function(|options: &ListOptions| {
+                    options.start_key == Some(owner_key("abc"))
+                        && options.limit == 10
+                        && options.sort == SortOrder::Descending
+                }),
+            )
+            .returning(|_owner_id, _options| Ok(Page::default()));
+        let request = create_request(
+            &[("cursor", &cursor), ("limit", "10"), ("sort", "desc")],
+            &[],
+        );
+
+        let data = function_handler(&create_deps(second_repo), request)
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+
+        assert_eq!(data.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn when_cursor_comes_from_another_query_should_return_400() {
+        let query = CursorQuery {
+            owner_id: Some("user-1".into()),
+            ..Default::default()
+        };
+        let cursor = CursorSigner::new("test-secret").encode(&owner_key("abc"), &query);
+        let cases: [&[(&str, &str)]; 3] = [
+            &[("cursor", &cursor), ("sort", "desc")],
+            &[("cursor", &cursor), ("tag", "spring")],
+            &[("cursor", &cursor), ("scope", "all")],
+        ];
+
+        for query in cases {
+            let mut mock_url_repo = MockUrlRepository::default();
+            mock_url_repo.expect_list_urls().times(0);
+            mock_url_repo.expect_list_urls_for_owner().times(0);
+            let request = create_request(query, &[ADMIN_SCOPE]);
+
+            let data = function_handler(&create_deps(mock_url_repo), request)
+                .await
+                .unwrap()
+                .into_response()
+                .await;
+
+            assert_eq!(data.status(), 400, "{:?}", query);
+            let body: Value = serde_json::from_slice(data.body()).unwrap();
+            assert_eq!(body["error"], "cursor belongs to another query");
+        }
     }
 
     #[tokio::test]
-    async fn when_valid_request_made_with_path_parameter_should_return() {
+    async fn when_query_parameters_are_invalid_should_return_400() {
+        let query = CursorQuery {
+            owner_id: Some("user-1".into()),
+            ..Default::default()
+        };
+        let forged_cursor = CursorSigner::new("another-secret").encode(&owner_key("abc"), &query);
+        let cases: [&[(&str, &str)]; 7] = [
+            &[("cursor", "not-a-cursor")],
+            &[("cursor", &forged_cursor)],
+            &[("limit", "0")],
+            &[("limit", "1000")],
+            &[("sort", "sideways")],
+            &[("scope", "all"), ("sort", "desc")],
+            &[("tag", "not#a-tag")],
+        ];
+
+        for query in cases {
+            let mut mock_url_repo = MockUrlRepository::default();
+            mock_url_repo.expect_list_urls().times(0);
+            mock_url_repo.expect_list_urls_for_owner().times(0);
+            let request = create_request(query, &[ADMIN_SCOPE]);
+
+            let data = function_handler(&create_deps(mock_url_repo), request)
+                .await
+                .unwrap()
+                .into_response()
+                .await;
+
+            assert_eq!(data.status(), 400, "{:?}", query);
+        }
+    }
+
+    #[tokio::test]
+    async fn when_include_disabled_passed_should_list_inactive_links() {
         let mut mock_url_repo = MockUrlRepository::default();
         mock_url_repo
-            .expect_list_urls()
+            .expect_list_urls_for_owner()
             .times(1)
-            .with(eq(Some("an-id".to_string()))) // make sure the correct id is propagated
-            .returning(|_last_evaluated_id| {
-                Ok((
-                    vec![ShortUrl::new(
-                        "12345689".into(),
-                        "https://google.com".into(),
-                    )],
-                    None,
-                ))
+            .with(
+                eq("user-1".to_string()),
+                function(|options: &ListOptions| options.include_inactive),
+            )
+            .returning(|_owner_id, _options| {
+                Ok(Page {
+                    short_urls: vec![ShortUrl {
+                        status: LinkStatus::Disabled,
+                        ..ShortUrl::new("12345689".into(), "https://google.com".into())
+                    }],
+                    next_key: None,
+                })
             });
-        let deps = HandlerDeps {
-            url_repo: mock_url_repo,
-        };
-        let mut query_string = HashMap::new();
-        query_string.insert("last_evaluated_id".to_string(), "an-id".to_string());
-        let request = Request::builder()
-            .header("Content-Type", "application/json")
-            .body(Body::Empty)
-            .unwrap()
-            .with_query_string_parameters(query_string);
+        let deps = create_deps(mock_url_repo);
+        let request = create_request(&[("include", "disabled")], &[]);
 
         let result = function_handler(&deps, request).await;
 
@@ -103,22 +375,195 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn when_error_in_database_return_500() {
+    async fn when_tag_passed_should_list_that_tag_only() {
         let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo
+            .expect_list_urls_for_owner()
+            .times(1)
+            .with(
+                eq("user-1".to_string()),
+                function(|options: &ListOptions| options.tag.as_deref() == Some("spring")),
+            )
+            .returning(|_owner_id, _options| Ok(Page::default()));
+        let deps = create_deps(mock_url_repo);
+        let request = create_request(&[("tag", "spring")], &[]);
+
+        let data = function_handler(&deps, request)
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+
+        assert_eq!(data.status(), 200);
+    }
+
+    fn search_request(query: &str) -> lambda_http::Request {
+        Request::builder()
+            .uri("/links/search")
+            .body(Body::Empty)
+            .unwrap()
+            .with_query_string_parameters(HashMap::from([("q".to_string(), query.to_string())]))
+            .with_request_context(jwt_request_context("user-1", &[]))
+    }
+
+    #[tokio::test]
+    async fn when_search_requested_should_search_the_callers_links() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo.expect_list_urls_for_owner().times(0);
+        mock_url_repo
+            .expect_search_urls()
+            .times(1)
+            .with(
+                eq("user-1".to_string()),
+                eq("rust lambda".to_string()),
+                eq(ListOptions::default()),
+            )
+            .returning(|_owner_id, _query, _options| {
+                Ok(Page {
+                    short_urls: vec![ShortUrl::new("abc".into(), "https://google.com".into())],
+                    next_key: Some(PageKey::from([("Offset".to_string(), "1".to_string())])),
+                })
+            });
+        let deps = create_deps(mock_url_repo);
+
+        let data = function_handler(&deps, search_request("rust lambda"))
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+
+        assert_eq!(data.status(), 200);
+        let body: Value = serde_json::from_slice(data.body()).unwrap();
+        assert_eq!(body["short_urls"][0]["link_id"], "abc");
+        assert!(body["next_cursor"].is_string());
+    }
+
+    #[tokio::test]
+    async fn when_search_has_no_words_should_return_400() {
+        for query in ["", "the a", "!!"] {
+            let mut mock_url_repo = MockUrlRepository::default();
+            mock_url_repo.expect_search_urls().times(0);
+            let deps = create_deps(mock_url_repo);
+
+            let data = function_handler(&deps, search_request(query))
+                .await
+                .unwrap()
+                .into_response()
+                .await;
+
+            assert_eq!(data.status(), 400);
+        }
+    }
+
+    #[tokio::test]
+    async fn when_admin_asks_for_all_links_should_scan() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo.expect_list_urls_for_owner().times(0);
         mock_url_repo
             .expect_list_urls()
             .times(1)
-            .returning(|_last_evaluated_id| Err("Error reading from DB".to_string()));
+            .with(eq(ListOptions::default()))
+            .returning(|_options| Ok(Page::default()));
+        let deps = create_deps(mock_url_repo);
+        let request = create_request(&[("scope", "all")], &[ADMIN_SCOPE]);
+
+        let data = function_handler(&deps, request)
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+
+        assert_eq!(data.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn when_non_admin_asks_for_all_links_should_return_403() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo.expect_list_urls().times(0);
+        mock_url_repo.expect_list_urls_for_owner().times(0);
+        let deps = create_deps(mock_url_repo);
+        let request = create_request(&[("scope", "all")], &[]);
+
+        let data = function_handler(&deps, request)
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+
+        assert_eq!(data.status(), 403);
+    }
+
+    #[tokio::test]
+    async fn when_request_is_not_authenticated_should_return_401() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo.expect_list_urls_for_owner().times(0);
+        let deps = create_deps(mock_url_repo);
+        let request = Request::builder().body(Body::Empty).unwrap();
+
+        let data = function_handler(&deps, request)
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+
+        assert_eq!(data.status(), 401);
+    }
+
+    #[tokio::test]
+    async fn when_bearer_token_is_verified_here_should_list_the_links_of_its_subject() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo
+            .expect_list_urls_for_owner()
+            .times(1)
+            .with(eq("user-2".to_string()), eq(ListOptions::default()))
+            .returning(|_owner_id, _options| {
+                Ok(Page {
+                    short_urls: vec![],
+                    next_key: None,
+                })
+            });
         let deps = HandlerDeps {
-            url_repo: mock_url_repo,
+            jwt_verifier: Some(jwt::testing::verifier()),
+            ..create_deps(mock_url_repo)
         };
-        let mut query_string = HashMap::new();
-        query_string.insert("last_evaluated_id".to_string(), "an-id".to_string());
-        let request = Request::builder()
-            .header("Content-Type", "application/json")
-            .body(Body::Empty)
+        // No authorizer context: API Gateway did not check these tokens
+        let request = |token: &str| {
+            Request::builder()
+                .header("Authorization", format!("Bearer {}", token))
+                .body(Body::Empty)
+                .unwrap()
+        };
+
+        let data = function_handler(&deps, request(&jwt::testing::token("user-2", false)))
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+        assert_eq!(data.status(), 200);
+
+        let data = function_handler(&deps, request("not-a-token"))
+            .await
             .unwrap()
-            .with_query_string_parameters(query_string);
+            .into_response()
+            .await;
+        assert_eq!(data.status(), 401);
+        assert_eq!(
+            data.headers()["www-authenticate"],
+            "Bearer error=\"invalid_token\""
+        );
+    }
+
+    #[tokio::test]
+    async fn when_error_in_database_return_500() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo
+            .expect_list_urls_for_owner()
+            .times(1)
+            .returning(|_owner_id, _options| {
+                Err(RepositoryError::Fatal("Error reading from DB".to_string()))
+            });
+        let deps = create_deps(mock_url_repo);
+        let request = create_request(&[], &[]);
 
         let result = function_handler(&deps, request).await;
 
@@ -2,7 +2,9 @@ use crate::config::Config;
 use crate::http_handler::{function_handler, HandlerDeps};
 use ::tracing::Instrument;
 use lambda_http::{run, service_fn, tracing, Error};
-use shared::adapters::DynamoDbUrlRepository;
+use shared::adapters::{url_repository, DynamoDbUrlRepository};
+use shared::cursor::CursorSigner;
+use shared::jwt::JwtVerifier;
 use std::sync::{
     atomic::{AtomicBool, Ordering},
     Arc,
@@ -10,6 +12,7 @@ use std::sync::{
 
 mod config;
 mod http_handler;
+mod qr;
 
 static IS_COLD_START: AtomicBool = AtomicBool::new(true);
 
@@ -21,8 +24,17 @@ async fn main() -> Result<(), Error> {
     let dynamodb_client = aws_sdk_dynamodb::Client::new(&config);
 
     let env = Config::load()?;
-    let url_repo = DynamoDbUrlRepository::new(env.table_name, dynamodb_client);
-    let deps = HandlerDeps { url_repo };
+    let url_repo = url_repository(env.in_memory_repository, || {
+        DynamoDbUrlRepository::new(env.table_name, dynamodb_client)
+            .with_tags_table(env.tags_table_name)
+            .with_search_table(env.search_table_name)
+    });
+    let deps = HandlerDeps {
+        url_repo,
+        cursor_signer: CursorSigner::new(env.cursor_secret),
+        jwt_verifier: env.jwt.map(JwtVerifier::new).transpose()?,
+        public_base_url: env.public_base_url,
+    };
 
     run(service_fn(|event| async {
         let was_cold_start = IS_COLD_START.swap(false, Ordering::SeqCst);
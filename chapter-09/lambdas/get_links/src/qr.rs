@@ -0,0 +1,348 @@
+use crate::http_handler::HandlerDeps;
+use lambda_http::http::header::{CACHE_CONTROL, CONTENT_TYPE, ETAG, IF_NONE_MATCH};
+use lambda_http::http::StatusCode;
+use lambda_http::{tracing, Body, Error, Request, RequestExt, Response};
+use qrcode::{Color, EcLevel, QrCode};
+use sha2::{Digest, Sha256};
+use shared::core::{LinkStatus, UrlRepository};
+use shared::utils::{empty_response, json_error_response, repository_error_response};
+
+const DEFAULT_SIZE: usize = 512;
+const MIN_SIZE: usize = 64;
+const MAX_SIZE: usize = 4096;
+/// The quiet zone the QR code specification asks for, in modules.
+const DEFAULT_MARGIN: usize = 4;
+const MAX_MARGIN: usize = 32;
+/// The image only depends on the link id and the options, so it can be cached
+/// for as long as the link exists, but only by the caller: the endpoint is
+/// authenticated and shared caches must not hand it out to anyone else.
+const QR_CACHE_CONTROL: &str = "private, max-age=86400";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum QrFormat {
+    Png,
+    Svg,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct QrOptions {
+    pub format: QrFormat,
+    /// The largest width and height wanted, in pixels.
+    pub size: usize,
+    /// The blank border, in modules.
+    pub margin: usize,
+    pub ecc: EcLevel,
+}
+
+impl Default for QrOptions {
+    fn default() -> Self {
+        Self {
+            format: QrFormat::Png,
+            size: DEFAULT_SIZE,
+            margin: DEFAULT_MARGIN,
+            ecc: EcLevel::M,
+        }
+    }
+}
+
+impl QrOptions {
+    fn from_request(event: &Request) -> Result<Self, String> {
+        let params = event.query_string_parameters();
+        let mut options = QrOptions::default();
+        match params.first("format") {
+            None | Some("png") => {}
+            Some("svg") => options.format = QrFormat::Svg,
+            Some(_) => return Err("format must be 'png' or 'svg'".to_string()),
+        }
+        if let Some(size) = params.first("size") {
+            options.size = size
+                .parse()
+                .ok()
+                .filter(|size| (MIN_SIZE..=MAX_SIZE).contains(size))
+                .ok_or_else(|| format!("size must be between {} and {}", MIN_SIZE, MAX_SIZE))?;
+        }
+        if let Some(margin) = params.first("margin") {
+            options.margin = margin
+                .parse()
+                .ok()
+                .filter(|margin| *margin <= MAX_MARGIN)
+                .ok_or_else(|| format!("margin must be between 0 and {}", MAX_MARGIN))?;
+        }
+        options.ecc = match params.first("ecc") {
+            None => EcLevel::M,
+            Some("L") => EcLevel::L,
+            Some("M") => EcLevel::M,
+            Some("Q") => EcLevel::Q,
+            Some("H") => EcLevel::H,
+            Some(_) => return Err("ecc must be one of L, M, Q or H".to_string()),
+        };
+        Ok(options)
+    }
+}
+
+/// Handles `GET /links/{linkId}/qr`: a QR code of the public short URL.
+pub(crate) async fn qr_code<R: UrlRepository>(
+    deps: &HandlerDeps<R>,
+    event: &Request,
+    link_id: &str,
+) -> Result<Response<Body>, Error> {
+    let options = match QrOptions::from_request(event) {
+        Ok(options) => options,
+        Err(message) => return json_error_response(&StatusCode::BAD_REQUEST, &message),
+    };
+    match deps.url_repo.get_url_from_short_link(link_id).await {
+        Ok(Some(short_url)) if short_url.status != LinkStatus::Deleted => {}
+        Ok(_) => return empty_response(&StatusCode::NOT_FOUND),
+        Err(e) => {
+            tracing::error!("Failed to retrieve URL: {:?}", e);
+            return repository_error_response(&e);
+        }
+    }
+    // Never from the Host header, the client could point the code anywhere
+    let short_link = format!("{}/{}", deps.public_base_url.trim_end_matches('/'), link_id);
+
+    let code = QrCode::with_error_correction_level(short_link.as_bytes(), options.ecc)?;
+    let (content_type, body) = match options.format {
+        QrFormat::Png => ("image/png", render_png(&code, &options)?),
+        QrFormat::Svg => ("image/svg+xml", render_svg(&code, &options).into_bytes()),
+    };
+    let etag = format!("\"{:x}\"", Sha256::digest(&body));
+
+    let not_modified = event
+        .headers()
+        .get_all(IF_NONE_MATCH)
+        .iter()
+        .filter_map(|header| header.to_str().ok())
+        .flat_map(|header| header.split(','))
+        .any(|tag| {
+            let tag = tag.trim();
+            tag == "*" || tag.trim_start_matches("W/") == etag
+        });
+    let response = Response::builder()
+        .header(ETAG, &etag)
+        .header(CACHE_CONTROL, QR_CACHE_CONTROL);
+    let response = if not_modified {
+        response.status(StatusCode::NOT_MODIFIED).body(Body::Empty)
+    } else {
+        response
+            .status(StatusCode::OK)
+            .header(CONTENT_TYPE, content_type)
+            .body(Body::Binary(body))
+    };
+    Ok(response.map_err(Box::new)?)
+}
+
+/// Each module is drawn as a square of whole pixels, as many as fit in
+/// `options.size` but at least one, so the image can come out a little
+/// smaller than asked.
+fn module_pixels(code: &QrCode, options: &QrOptions) -> usize {
+    (options.size / (code.width() + 2 * options.margin)).max(1)
+}
+
+fn render_png(code: &QrCode, options: &QrOptions) -> Result<Vec<u8>, png::EncodingError> {
+    let scale = module_pixels(code, options);
+    let modules = code.width() + 2 * options.margin;
+    let dimension = modules * scale;
+
+    let mut pixels = vec![u8::MAX; dimension * dimension];
+    let colors = code.to_colors();
+    for (index, _) in colors
+        .iter()
+        .enumerate()
+        .filter(|(_, color)| **color == Color::Dark)
+    {
+        let x = (index % code.width() + options.margin) * scale;
+        let y = (index / code.width() + options.margin) * scale;
+        for row in y..y + scale {
+            pixels[row * dimension + x..row * dimension + x + scale].fill(0);
+        }
+    }
+
+    let mut png = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut png, dimension as u32, dimension as u32);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&pixels)?;
+    }
+    Ok(png)
+}
+
+/// The SVG is drawn in module units and scaled to the same size as the PNG.
+fn render_svg(code: &QrCode, options: &QrOptions) -> String {
+    let modules = code.width() + 2 * options.margin;
+    let dimension = modules * module_pixels(code, options);
+    let path: String = code
+        .to_colors()
+        .iter()
+        .enumerate()
+        .filter(|(_, color)| **color == Color::Dark)
+        .map(|(index, _)| {
+            format!(
+                "M{},{}h1v1h-1z",
+                index % code.width() + options.margin,
+                index / code.width() + options.margin
+            )
+        })
+        .collect();
+    format!(
+        r##"<?xml version="1.0" encoding="UTF-8"?>
+<svg xmlns="http://www.w3.org/2000/svg" width="{dimension}" height="{dimension}" viewBox="0 0 {modules} {modules}" shape-rendering="crispEdges"><rect width="{modules}" height="{modules}" fill="#fff"/><path fill="#000" d="{path}"/></svg>
+"##
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render_png, render_svg, QrFormat, QrOptions};
+    use crate::http_handler::{function_handler, HandlerDeps};
+    use lambda_http::http::Request;
+    use lambda_http::{Body, IntoResponse, RequestExt, Response};
+    use qrcode::{EcLevel, QrCode};
+    use shared::auth::jwt_request_context;
+    use shared::core::{MockUrlRepository, ShortUrl};
+    use shared::cursor::CursorSigner;
+    use std::collections::HashMap;
+
+    fn create_deps(exists: bool) -> HandlerDeps<MockUrlRepository> {
+        let mut url_repo = MockUrlRepository::default();
+        url_repo
+            .expect_get_url_from_short_link()
+            .returning(move |link_id| {
+                Ok(
+                    exists
+                        .then(|| ShortUrl::new(link_id.to_string(), "https://example.com".into())),
+                )
+            });
+        HandlerDeps {
+            url_repo,
+            cursor_signer: CursorSigner::new("test-secret"),
+            jwt_verifier: None,
+            public_base_url: "https://sho.rt/".into(),
+        }
+    }
+
+    fn create_request(query: &[(&str, &str)], if_none_match: Option<&str>) -> lambda_http::Request {
+        let mut request = Request::builder().uri("https://api.example.com/links/abc123/qr");
+        if let Some(etag) = if_none_match {
+            request = request.header("If-None-Match", etag);
+        }
+        request
+            .body(Body::Empty)
+            .unwrap()
+            .with_path_parameters(HashMap::from([(
+                "linkId".to_string(),
+                "abc123".to_string(),
+            )]))
+            .with_query_string_parameters(
+                query
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+                    .collect::<HashMap<_, _>>(),
+            )
+            .with_request_context(jwt_request_context("user-1", &[]))
+    }
+
+    async fn send(
+        deps: &HandlerDeps<MockUrlRepository>,
+        request: lambda_http::Request,
+    ) -> Response<Body> {
+        function_handler(deps, request)
+            .await
+            .unwrap()
+            .into_response()
+            .await
+    }
+
+    #[tokio::test]
+    async fn when_qr_code_is_requested_should_render_it_with_an_etag_for_revalidation() {
+        let deps = create_deps(true);
+
+        let response = send(&deps, create_request(&[], None)).await;
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()["content-type"], "image/png");
+        let Body::Binary(png) = response.body() else {
+            panic!("expected a binary body");
+        };
+        assert!(png.starts_with(b"\x89PNG"));
+        let etag = response.headers()["etag"].to_str().unwrap().to_string();
+        assert_eq!(
+            response.headers()["cache-control"],
+            "private, max-age=86400"
+        );
+
+        let response = send(&deps, create_request(&[], Some(&etag))).await;
+
+        assert_eq!(response.status(), 304);
+        assert_eq!(response.headers()["etag"], etag.as_str());
+
+        let response = send(&deps, create_request(&[("format", "svg")], Some(&etag))).await;
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()["content-type"], "image/svg+xml");
+        assert_ne!(response.headers()["etag"], etag.as_str());
+    }
+
+    #[tokio::test]
+    async fn when_host_header_is_forged_should_still_encode_the_configured_base_url() {
+        let deps = create_deps(true);
+        let mut forged = create_request(&[("format", "svg")], None);
+        forged
+            .headers_mut()
+            .insert("Host", "evil.example".parse().unwrap());
+
+        let expected = send(&deps, create_request(&[("format", "svg")], None)).await;
+        let response = send(&deps, forged).await;
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()["etag"], expected.headers()["etag"]);
+    }
+
+    #[tokio::test]
+    async fn when_link_does_not_exist_should_return_404() {
+        let deps = create_deps(false);
+
+        let response = send(&deps, create_request(&[], None)).await;
+
+        assert_eq!(response.status(), 404);
+    }
+
+    #[tokio::test]
+    async fn when_options_are_invalid_should_return_400() {
+        let deps = create_deps(true);
+
+        for query in [
+            [("format", "gif")],
+            [("size", "10")],
+            [("margin", "-1")],
+            [("ecc", "X")],
+        ] {
+            let response = send(&deps, create_request(&query, None)).await;
+
+            assert_eq!(response.status(), 400, "{:?}", query);
+        }
+    }
+
+    #[test]
+    fn when_rendering_should_fit_whole_pixel_modules_in_the_size() {
+        let code =
+            QrCode::with_error_correction_level(b"https://sho.rt/abc123", EcLevel::M).unwrap();
+        let options = QrOptions {
+            format: QrFormat::Svg,
+            size: 200,
+            margin: 2,
+            ecc: EcLevel::M,
+        };
+        // 25 modules and a margin of 2 on each side fit 6 pixels per module in 200
+        assert_eq!(code.width(), 25);
+
+        let svg = render_svg(&code, &options);
+        assert!(svg.contains(r#"width="174" height="174" viewBox="0 0 29 29""#));
+
+        let png = render_png(&code, &options).unwrap();
+        let decoder = png::Decoder::new(png.as_slice());
+        let info = decoder.read_info().unwrap();
+        assert_eq!((info.info().width, info.info().height), (174, 174));
+    }
+}
@@ -5,12 +5,16 @@ use serde::{Deserialize, Serialize};
 #[derive(Debug, Serialize, Deserialize)]
 pub(crate) struct Config {
     pub table_name: String,
+    /// Keep links in memory rather than in DynamoDB, see `adapters::url_repository`.
+    #[serde(default)]
+    pub in_memory_repository: bool,
 }
 
 impl Config {
-    pub fn load() -> Result<Self, figment::Error> {
+    pub fn load() -> Result<Self, Box<figment::Error>> {
         Figment::new()
-            .merge(Env::raw().only(&["TABLE_NAME"]))
+            .merge(Env::raw().only(&["TABLE_NAME", "IN_MEMORY_REPOSITORY"]))
             .extract()
+            .map_err(Box::new)
     }
 }
@@ -3,7 +3,7 @@ use cloudevents::AttributesReader;
 use lambda_runtime::{tracing, Error, LambdaEvent};
 use opentelemetry::global;
 use shared::{
-    core::{ShortUrl, UrlRepository},
+    core::{LinkClicked, UrlRepository},
     observability::add_span_link_from,
 };
 use std::collections::HashMap;
@@ -23,14 +23,20 @@ pub(crate) async fn function_handler<R: UrlRepository>(
     // Extract some useful information from the request
     let payload = event.payload;
 
-    // Aggregate clicks by link ID
+    // Aggregate clicks by link ID, and by variant for A/B links
     let mut clicks_by_id: HashMap<String, u64> = HashMap::new();
+    let mut clicks_by_variant: HashMap<(String, usize), u64> = HashMap::new();
     for record in payload.records {
         let process_result = process_message(record).await;
 
         match process_result {
-            Ok(link_id) => {
-                let counter = clicks_by_id.entry(link_id).or_insert(0);
+            Ok(LinkClicked { short_url, variant }) => {
+                if let Some(variant) = variant {
+                    *clicks_by_variant
+                        .entry((short_url.link_id.clone(), variant))
+                        .or_insert(0) += 1;
+                }
+                let counter = clicks_by_id.entry(short_url.link_id).or_insert(0);
                 *counter += 1;
             }
             Err(e) => {
@@ -46,7 +52,7 @@ pub(crate) async fn function_handler<R: UrlRepository>(
             click_count,
             &[opentelemetry::KeyValue::new("link_id", link_id.clone())],
         );
-        
+
         let repo = &deps.url_repo;
         update_futures.push(async move {
             match repo.increment_clicks(&link_id, click_count).await {
@@ -69,6 +75,26 @@ pub(crate) async fn function_handler<R: UrlRepository>(
     }
     futures::future::join_all(update_futures).await;
 
+    let variant_futures = clicks_by_variant
+        .into_iter()
+        .map(|((link_id, variant), click_count)| {
+            let repo = &deps.url_repo;
+            async move {
+                if let Err(e) = repo
+                    .increment_target_clicks(&link_id, variant, click_count)
+                    .await
+                {
+                    tracing::error!(
+                        "Failed to update click count for variant {} of link ID {}: {:?}",
+                        variant,
+                        link_id,
+                        e
+                    );
+                }
+            }
+        });
+    futures::future::join_all(variant_futures).await;
+
     Ok(())
 }
 
@@ -80,7 +106,7 @@ pub(crate) async fn function_handler<R: UrlRepository>(
 ))]
 async fn process_message(
     record: KinesisEventRecord,
-) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+) -> Result<LinkClicked, Box<dyn std::error::Error + Send + Sync>> {
     let kinesis_record = record.kinesis;
     let data = kinesis_record.data.as_ref();
 
@@ -93,29 +119,30 @@ async fn process_message(
         }
     };
 
-    tracing::Span::current().record("messaging.message.id", &cloud_event.id().to_string());
+    tracing::Span::current().record("messaging.message.id", cloud_event.id().to_string());
 
     add_span_link_from(&current_span, &cloud_event);
 
     let cloud_event_data = cloud_event.data().ok_or("CloudEvent has no data")?;
 
-    let link_click_event: ShortUrl = match cloud_event_data {
+    let link_click_event: LinkClicked = match cloud_event_data {
         cloudevents::Data::Binary(items) => serde_json::from_slice(items)?,
-        cloudevents::Data::String(string_data) => serde_json::from_str(&string_data)?,
+        cloudevents::Data::String(string_data) => serde_json::from_str(string_data)?,
         cloudevents::Data::Json(value) => serde_json::from_value(value.clone())?,
     };
 
-    Ok(link_click_event.link_id)
+    Ok(link_click_event)
 }
 
 #[cfg(test)]
 mod tests {
     use super::{function_handler, HandlerDeps};
     use aws_lambda_events::event::kinesis::{KinesisEvent, KinesisEventRecord};
+    use cloudevents::{EventBuilder, EventBuilderV10};
     use lambda_runtime::{Context, LambdaEvent};
     use mockall::predicate::eq;
-    use serde_json::json;
-    use shared::core::MockUrlRepository;
+    use serde_json::{json, Value};
+    use shared::core::{MockUrlRepository, RepositoryError};
 
     fn create_kinesis_record(data: &str) -> KinesisEventRecord {
         use base64::{engine::general_purpose::STANDARD, Engine};
@@ -138,6 +165,17 @@ mod tests {
         serde_json::from_value(record_json).expect("Failed to create KinesisEventRecord")
     }
 
+    fn create_cloud_event(data: Value) -> String {
+        let event = EventBuilderV10::new()
+            .id("test-event-id")
+            .ty("rust-link-shortener")
+            .source("http://rust-link-shortener.com")
+            .data("application/json", data)
+            .build()
+            .unwrap();
+        serde_json::to_string(&event).unwrap()
+    }
+
     fn create_lambda_event(records: Vec<KinesisEventRecord>) -> LambdaEvent<KinesisEvent> {
         let mut kinesis_event = KinesisEvent::default();
         kinesis_event.records = records;
@@ -265,6 +303,50 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[tokio::test]
+    async fn when_records_have_variants_should_count_each_variant() {
+        let mut mock_url_repo = MockUrlRepository::default();
+
+        mock_url_repo
+            .expect_increment_clicks()
+            .times(1)
+            .with(eq("abc123"), eq(3u64))
+            .returning(|_, _| Ok(()));
+        mock_url_repo
+            .expect_increment_target_clicks()
+            .times(1)
+            .with(eq("abc123"), eq(0usize), eq(2u64))
+            .returning(|_, _, _| Ok(()));
+        mock_url_repo
+            .expect_increment_target_clicks()
+            .times(1)
+            .with(eq("abc123"), eq(1usize), eq(1u64))
+            .returning(|_, _, _| Ok(()));
+
+        let deps = HandlerDeps {
+            url_repo: mock_url_repo,
+        };
+
+        let click = |variant: usize| {
+            create_cloud_event(json!({
+                "link_id": "abc123",
+                "original_link": "https://example.com",
+                "clicks": 0,
+                "variant": variant
+            }))
+        };
+
+        let event = create_lambda_event(vec![
+            create_kinesis_record(&click(0)),
+            create_kinesis_record(&click(1)),
+            create_kinesis_record(&click(0)),
+        ]);
+
+        let result = function_handler(&deps, event).await;
+
+        assert!(result.is_ok());
+    }
+
     #[tokio::test]
     async fn when_repository_error_should_log_and_succeed() {
         let mut mock_url_repo = MockUrlRepository::default();
@@ -272,7 +354,7 @@ mod tests {
         mock_url_repo
             .expect_increment_clicks()
             .times(1)
-            .returning(|_, _| Err("DB error".to_string()));
+            .returning(|_, _| Err(RepositoryError::Fatal("DB error".to_string())));
 
         let deps = HandlerDeps {
             url_repo: mock_url_repo,
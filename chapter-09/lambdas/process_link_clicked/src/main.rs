@@ -7,7 +7,7 @@ use crate::event_handler::HandlerDeps;
 use ::tracing::Instrument;
 use event_handler::function_handler;
 use lambda_runtime::{run, service_fn, tracing, Error};
-use shared::adapters::DynamoDbUrlRepository;
+use shared::adapters::{url_repository, DynamoDbUrlRepository};
 
 mod config;
 mod event_handler;
@@ -21,7 +21,9 @@ async fn main() -> Result<(), Error> {
     let aws_config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
     let dynamodb_client = aws_sdk_dynamodb::Client::new(&aws_config);
     let config = config::Config::load()?;
-    let url_repo = DynamoDbUrlRepository::new(config.table_name, dynamodb_client);
+    let url_repo = url_repository(config.in_memory_repository, || {
+        DynamoDbUrlRepository::new(config.table_name, dynamodb_client)
+    });
     let handler_deps = HandlerDeps { url_repo };
 
     run(service_fn(|event| async {
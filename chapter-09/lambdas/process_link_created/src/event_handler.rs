@@ -6,7 +6,7 @@ use cloudevents::AttributesReader;
 use lambda_runtime::{tracing, Error, LambdaEvent};
 use opentelemetry::global;
 use shared::{
-    core::{ShortUrl, UrlInfo, UrlRepository},
+    core::{RepositoryError, ShortUrl, UrlInfo, UrlRepository},
     observability::add_span_link_from,
 };
 
@@ -84,7 +84,7 @@ async fn process_message<R: UrlRepository, I: UrlInfo>(
             }
         };
 
-    tracing::Span::current().record("messaging.message.id", &cloud_event.id().to_string());
+    tracing::Span::current().record("messaging.message.id", cloud_event.id().to_string());
 
     add_span_link_from(&current_span, &cloud_event);
 
@@ -92,31 +92,47 @@ async fn process_message<R: UrlRepository, I: UrlInfo>(
 
     let short_url: ShortUrl = match cloud_event_data {
         cloudevents::Data::Binary(items) => serde_json::from_slice(items)?,
-        cloudevents::Data::String(string_data) => serde_json::from_str(&string_data)?,
+        cloudevents::Data::String(string_data) => serde_json::from_str(string_data)?,
         cloudevents::Data::Json(value) => serde_json::from_value(value.clone())?,
     };
 
-    let info = url_info.fetch_details(&short_url.original_link).await?;
+    let info = match url_info.fetch_details(&short_url.original_link).await {
+        Ok(info) => info,
+        Err(RepositoryError::Validation(reason)) => {
+            // NOTE: the URL policy would refuse a retry too, so we don't report a failure
+            tracing::warn!("Not adding details to {}: {}", short_url.link_id, reason);
+            return Ok(());
+        }
+        Err(e) => return Err(Box::new(e)),
+    };
     tracing::debug!(
         "Fetched info for URL {}: {:?}",
         short_url.original_link,
         info
     );
-    url_repo
-        .add_details_to_short_url(short_url.link_id, info)
-        .await?;
-    Ok(())
+    match url_repo
+        .add_details_to_short_url(short_url.link_id.clone(), &short_url.original_link, info)
+        .await
+    {
+        Err(RepositoryError::Conflict(reason)) => {
+            // NOTE: the new destination has an event of its own, so we don't report a failure
+            tracing::warn!("Not adding details to {}: {}", short_url.link_id, reason);
+            Ok(())
+        }
+        result => Ok(result?),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::{function_handler, HandlerDeps};
     use aws_lambda_events::{event::sqs::SqsEvent, sqs::SqsMessage};
+    use cloudevents::{EventBuilder, EventBuilderV10};
     use lambda_runtime::{Context, LambdaEvent};
     use mockall::predicate::eq;
-    use serde_json::json;
+    use serde_json::{json, Value};
     use shared::{
-        core::{MockUrlInfo, MockUrlRepository},
+        core::{MockUrlInfo, MockUrlRepository, RepositoryError},
         url_info::UrlDetails,
     };
 
@@ -133,6 +149,17 @@ mod tests {
         LambdaEvent::new(sqs_event, Context::default())
     }
 
+    fn create_cloud_event(data: Value) -> String {
+        let event = EventBuilderV10::new()
+            .id("test-event-id")
+            .ty("rust-link-shortener")
+            .source("http://rust-link-shortener.com")
+            .data("application/json", data)
+            .build()
+            .unwrap();
+        serde_json::to_string(&event).unwrap()
+    }
+
     #[tokio::test]
     async fn when_valid_message_should_fetch_details_and_update_repository() {
         let mut mock_url_repo = MockUrlRepository::default();
@@ -155,9 +182,10 @@ mod tests {
             .times(1)
             .with(
                 eq("abc123".to_string()),
+                eq("https://example.com"),
                 mockall::predicate::always(), // UrlDetails doesn't implement PartialEq
             )
-            .returning(|_, _| Ok(()));
+            .returning(|_, _, _| Ok(()));
 
         let deps = HandlerDeps {
             url_repo: mock_url_repo,
@@ -236,7 +264,11 @@ mod tests {
         mock_url_info
             .expect_fetch_details()
             .times(1)
-            .returning(|_| Err("Failed to fetch URL".to_string()));
+            .returning(|_| {
+                Err(RepositoryError::Transient(
+                    "Failed to fetch URL".to_string(),
+                ))
+            });
 
         mock_url_repo.expect_add_details_to_short_url().times(0);
 
@@ -262,6 +294,87 @@ mod tests {
         assert_eq!(response.batch_item_failures[0].item_identifier, "msg-1");
     }
 
+    #[tokio::test]
+    async fn when_url_is_refused_by_the_url_policy_should_acknowledge_the_message() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        let mut mock_url_info = MockUrlInfo::default();
+
+        mock_url_info
+            .expect_fetch_details()
+            .times(1)
+            .with(eq("http://169.254.169.254/"))
+            .returning(|_| {
+                Err(RepositoryError::Validation(
+                    "Cannot scrape 'http://169.254.169.254/'".to_string(),
+                ))
+            });
+
+        mock_url_repo.expect_add_details_to_short_url().times(0);
+
+        let deps = HandlerDeps {
+            url_repo: mock_url_repo,
+            url_info: mock_url_info,
+        };
+
+        let body = create_cloud_event(json!({
+            "link_id": "abc123",
+            "original_link": "http://169.254.169.254/",
+            "clicks": 0
+        }));
+
+        let event = create_lambda_event(vec![create_sqs_message("msg-1", Some(body))]);
+
+        let result = function_handler(&deps, event).await;
+
+        assert!(result.is_ok());
+        let response = result.unwrap();
+        assert!(response.batch_item_failures.is_empty());
+    }
+
+    #[tokio::test]
+    async fn when_destination_changed_while_scraping_should_acknowledge_the_message() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        let mut mock_url_info = MockUrlInfo::default();
+
+        mock_url_info
+            .expect_fetch_details()
+            .times(1)
+            .returning(|_| Ok(UrlDetails::default()));
+
+        mock_url_repo
+            .expect_add_details_to_short_url()
+            .times(1)
+            .with(
+                eq("abc123".to_string()),
+                eq("https://example.com/old"),
+                mockall::predicate::always(),
+            )
+            .returning(|_, _, _| {
+                Err(RepositoryError::Conflict(
+                    "LinkId abc123 no longer points to https://example.com/old".to_string(),
+                ))
+            });
+
+        let deps = HandlerDeps {
+            url_repo: mock_url_repo,
+            url_info: mock_url_info,
+        };
+
+        let body = create_cloud_event(json!({
+            "link_id": "abc123",
+            "original_link": "https://example.com/old",
+            "clicks": 0
+        }));
+
+        let event = create_lambda_event(vec![create_sqs_message("msg-1", Some(body))]);
+
+        let result = function_handler(&deps, event).await;
+
+        assert!(result.is_ok());
+        let response = result.unwrap();
+        assert!(response.batch_item_failures.is_empty());
+    }
+
     #[tokio::test]
     async fn when_repository_update_fails_should_report_failure() {
         let mut mock_url_repo = MockUrlRepository::default();
@@ -275,7 +388,7 @@ mod tests {
         mock_url_repo
             .expect_add_details_to_short_url()
             .times(1)
-            .returning(|_, _| Err("DB error".to_string()));
+            .returning(|_, _, _| Err(RepositoryError::Fatal("DB error".to_string())));
 
         let deps = HandlerDeps {
             url_repo: mock_url_repo,
@@ -315,12 +428,12 @@ mod tests {
             .expect_fetch_details()
             .times(1)
             .with(eq("https://fail.com"))
-            .returning(|_| Err("Network error".to_string()));
+            .returning(|_| Err(RepositoryError::Transient("Network error".to_string())));
 
         mock_url_repo
             .expect_add_details_to_short_url()
             .times(1)
-            .returning(|_, _| Ok(()));
+            .returning(|_, _, _| Ok(()));
 
         let deps = HandlerDeps {
             url_repo: mock_url_repo,
@@ -7,7 +7,11 @@ use lambda_runtime::{run, service_fn, tracing, Error};
 mod event_handler;
 use ::tracing::Instrument;
 use event_handler::function_handler;
-use shared::{adapters::DynamoDbUrlRepository, url_info::HttpUrlInfo};
+use shared::{
+    adapters::{url_repository, DynamoDbUrlRepository},
+    url_info::HttpUrlInfo,
+    url_policy::UrlPolicy,
+};
 
 use crate::event_handler::HandlerDeps;
 
@@ -23,11 +27,11 @@ async fn main() -> Result<(), Error> {
     let dynamodb_client = aws_sdk_dynamodb::Client::new(&aws_config);
     let config = config::Config::load()?;
 
-    let url_repo = DynamoDbUrlRepository::new(config.table_name, dynamodb_client);
-    let http_client = shared::Client::builder()
-        .timeout(std::time::Duration::from_secs(2))
-        .build()?;
-    let url_info = HttpUrlInfo::new(http_client.clone());
+    let url_repo = url_repository(config.in_memory_repository, || {
+        DynamoDbUrlRepository::new(config.table_name, dynamodb_client)
+            .with_search_table(config.search_table_name)
+    });
+    let url_info = HttpUrlInfo::new(UrlPolicy::default(), std::time::Duration::from_secs(2))?;
 
     let handler_deps = HandlerDeps { url_repo, url_info };
 
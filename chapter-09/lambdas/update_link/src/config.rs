@@ -0,0 +1,39 @@
+use figment::providers::Env;
+use figment::Figment;
+use serde::{Deserialize, Serialize};
+use shared::jwt::JwtSettings;
+use shared::url_policy::UrlPolicy;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Config {
+    pub table_name: String,
+    /// Keep links in memory rather than in DynamoDB, see `adapters::url_repository`.
+    #[serde(default)]
+    pub in_memory_repository: bool,
+    /// The tag index, see `DynamoDbUrlRepository::with_tags_table`.
+    pub tags_table_name: String,
+    pub queue_url: String,
+    #[serde(default)]
+    pub url_policy: UrlPolicy,
+    /// Set when bearer tokens are checked here rather than only by API Gateway.
+    #[serde(default)]
+    pub jwt: Option<JwtSettings>,
+}
+
+impl Config {
+    pub fn load() -> Result<Self, Box<figment::Error>> {
+        Figment::new()
+            .merge(Env::raw().only(&[
+                "TABLE_NAME",
+                "IN_MEMORY_REPOSITORY",
+                "QUEUE_URL",
+                "TAGS_TABLE_NAME",
+            ]))
+            // e.g. URL_MAX_LENGTH=1024
+            .merge(Env::prefixed("URL_").map(|key| format!("url_policy.{}", key).into()))
+            // e.g. JWT_ISSUER, JWT_AUDIENCE and JWT_JWKS_URL or JWT_JWKS
+            .merge(Env::prefixed("JWT_").map(|key| format!("jwt.{}", key).into()))
+            .extract()
+            .map_err(Box::new)
+    }
+}
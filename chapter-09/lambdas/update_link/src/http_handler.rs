@@ -0,0 +1,770 @@
+use crate::event_publisher::EventPublisher;
+use lambda_http::{
+    http::Method, http::StatusCode, tracing, Body, Error, IntoResponse, Request, Response,
+};
+use lambda_http::{RequestExt, RequestPayloadExt};
+use serde::{Deserialize, Serialize};
+use shared::api_key::require_api_key;
+use shared::auth::authenticate_caller;
+use shared::configuration::ConfigurationCache;
+use shared::core::{LinkStatus, UrlRepository};
+use shared::jwt::JwtVerifier;
+use shared::tags::validate_tag;
+use shared::url_policy::UrlPolicy;
+use shared::utils::{
+    api_key_error_response, configuration_error_response, empty_response, json_error_response,
+    json_response, jwt_error_response, repository_error_response,
+};
+use url::Url;
+
+#[derive(Serialize, Deserialize)]
+pub struct UpdateDestinationRequest {
+    pub original_link: String,
+    /// The `version` of the link the caller last read.
+    pub version: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AddTagsRequest {
+    pub tags: Vec<String>,
+}
+
+pub(crate) struct HandlerDeps<R: UrlRepository, E: EventPublisher> {
+    pub url_repo: R,
+    pub event_publisher: E,
+    pub url_policy: UrlPolicy,
+    /// Verifies bearer tokens of requests API Gateway did not authenticate.
+    pub jwt_verifier: Option<JwtVerifier>,
+    /// Holds the domain policy and the API keys, which change without a redeploy.
+    pub configuration: ConfigurationCache,
+}
+
+/// Handles `PATCH /links/{linkId}`, `GET /links/{linkId}/history`,
+/// `POST /links/{linkId}/tags` and `DELETE /links/{linkId}/tags/{tag}`.
+///
+/// Callers only reach their own links, admins any link. The links of other
+/// owners are reported as missing rather than forbidden, so their ids are not
+/// given away. Everything but reading the history changes the link, so asks
+/// for an API key too, see `require_api_key`.
+#[tracing::instrument(skip(deps, event), fields(api_key.label))]
+pub(crate) async fn function_handler<R: UrlRepository, E: EventPublisher>(
+    deps: &HandlerDeps<R, E>,
+    event: Request,
+) -> Result<impl IntoResponse, Error> {
+    tracing::info!("Received {} {}", event.method(), event.uri().path());
+
+    let caller = match authenticate_caller(&event, deps.jwt_verifier.as_ref()).await {
+        Ok(caller) => caller,
+        Err(e) => {
+            tracing::info!("Refusing request: {}", e);
+            return jwt_error_response(&e);
+        }
+    };
+    if event.method() != Method::GET {
+        let configuration = match deps.configuration.current().await {
+            Ok(configuration) => configuration,
+            Err(e) => {
+                tracing::error!("Refusing request: {}", e);
+                return configuration_error_response(&e);
+            }
+        };
+        if let Err(e) = require_api_key(&event, &configuration.api_keys) {
+            tracing::info!("Refusing request: {}", e);
+            return api_key_error_response(&e);
+        }
+    }
+    let owner_id = caller.owner_filter();
+
+    let link_id = event
+        .path_parameters_ref()
+        .and_then(|params| params.first("linkId"))
+        .unwrap_or("");
+
+    if link_id.is_empty() {
+        return empty_response(&StatusCode::NOT_FOUND);
+    }
+
+    match *event.method() {
+        Method::PATCH => update_destination(deps, link_id, owner_id, &event).await,
+        Method::GET if event.uri().path().ends_with("/history") => {
+            get_history(deps, link_id, owner_id.as_deref()).await
+        }
+        Method::POST if event.uri().path().ends_with("/tags") => {
+            add_tags(deps, link_id, owner_id, &event).await
+        }
+        Method::DELETE => match event
+            .path_parameters_ref()
+            .and_then(|params| params.first("tag"))
+        {
+            Some(tag) => remove_tag(deps, link_id, owner_id, tag).await,
+            None => empty_response(&StatusCode::METHOD_NOT_ALLOWED),
+        },
+        _ => empty_response(&StatusCode::METHOD_NOT_ALLOWED),
+    }
+}
+
+async fn update_destination<R: UrlRepository, E: EventPublisher>(
+    deps: &HandlerDeps<R, E>,
+    link_id: &str,
+    owner_id: Option<String>,
+    event: &Request,
+) -> Result<Response<Body>, Error> {
+    let UpdateDestinationRequest {
+        original_link,
+        version,
+    } = match event.payload::<UpdateDestinationRequest>() {
+        Ok(Some(body)) => body,
+        _ => return empty_response(&StatusCode::BAD_REQUEST),
+    };
+    if !Url::parse(&original_link).is_ok_and(|url| matches!(url.scheme(), "http" | "https")) {
+        return json_error_response(
+            &StatusCode::BAD_REQUEST,
+            "original_link must be an absolute http or https URL",
+        );
+    }
+    // The new destination gets fetched from inside our network, as on create
+    if let Err(e) = deps.url_policy.validate(&original_link).await {
+        return json_error_response(&StatusCode::UNPROCESSABLE_ENTITY, &e.to_string());
+    }
+    let configuration = match deps.configuration.current().await {
+        Ok(configuration) => configuration,
+        Err(e) => {
+            tracing::error!("Refusing request: {}", e);
+            return configuration_error_response(&e);
+        }
+    };
+    if let Err(e) = configuration.domain_policy.check(&original_link) {
+        return json_error_response(&StatusCode::UNPROCESSABLE_ENTITY, &e.to_string());
+    }
+
+    let short_url = match deps
+        .url_repo
+        .update_destination(link_id, owner_id, original_link, version)
+        .await
+    {
+        Ok(short_url) => short_url,
+        Err(e) => {
+            tracing::error!("Failed to update destination: {:?}", e);
+            return repository_error_response(&e);
+        }
+    };
+
+    let publish_result = deps.event_publisher.publish_link_updated(&short_url).await;
+    if let Err(e) = &publish_result {
+        tracing::error!("Failed to publish link updated event: {:?}", e);
+    }
+    json_response(&StatusCode::OK, &short_url)
+}
+
+async fn add_tags<R: UrlRepository, E: EventPublisher>(
+    deps: &HandlerDeps<R, E>,
+    link_id: &str,
+    owner_id: Option<String>,
+    event: &Request,
+) -> Result<Response<Body>, Error> {
+    let tags = match event.payload::<AddTagsRequest>() {
+        Ok(Some(AddTagsRequest { tags })) if !tags.is_empty() => tags,
+        _ => return empty_response(&StatusCode::BAD_REQUEST),
+    };
+    if let Some(e) = tags.iter().find_map(|tag| validate_tag(tag).err()) {
+        return json_error_response(&StatusCode::BAD_REQUEST, &e.to_string());
+    }
+
+    match deps.url_repo.add_tags(link_id, owner_id, tags).await {
+        Ok(short_url) => json_response(&StatusCode::OK, &short_url),
+        Err(e) => {
+            tracing::error!("Failed to add tags: {:?}", e);
+            repository_error_response(&e)
+        }
+    }
+}
+
+async fn remove_tag<R: UrlRepository, E: EventPublisher>(
+    deps: &HandlerDeps<R, E>,
+    link_id: &str,
+    owner_id: Option<String>,
+    tag: &str,
+) -> Result<Response<Body>, Error> {
+    match deps
+        .url_repo
+        .remove_tags(link_id, owner_id, vec![tag.to_string()])
+        .await
+    {
+        Ok(short_url) => json_response(&StatusCode::OK, &short_url),
+        Err(e) => {
+            tracing::error!("Failed to remove tag: {:?}", e);
+            repository_error_response(&e)
+        }
+    }
+}
+
+async fn get_history<R: UrlRepository, E: EventPublisher>(
+    deps: &HandlerDeps<R, E>,
+    link_id: &str,
+    owner_id: Option<&str>,
+) -> Result<Response<Body>, Error> {
+    match deps.url_repo.get_url_from_short_link(link_id).await {
+        Ok(Some(short_url))
+            if short_url.status != LinkStatus::Deleted && short_url.belongs_to(owner_id) =>
+        {
+            json_response(&StatusCode::OK, &short_url.history)
+        }
+        Ok(_) => empty_response(&StatusCode::NOT_FOUND),
+        Err(e) => {
+            tracing::error!("Failed to retrieve URL: {:?}", e);
+            repository_error_response(&e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{function_handler, HandlerDeps};
+    use crate::event_publisher::MockEventPublisher;
+    use lambda_http::http::Request;
+    use lambda_http::{Body, IntoResponse, RequestExt};
+    use mockall::predicate::{eq, function};
+    use serde_json::{json, Value};
+    use shared::auth::{jwt_request_context, ADMIN_SCOPE};
+    use shared::configuration::{Configuration, ConfigurationCache};
+    use shared::core::{DestinationChange, MockUrlRepository, RepositoryError, ShortUrl};
+    use shared::url_policy::UrlPolicy;
+    use std::collections::HashMap;
+
+    /// Resolves the hosts the tests use without DNS.
+    fn test_url_policy() -> UrlPolicy {
+        UrlPolicy::default()
+            .with_resolved("example.com", &["93.184.216.34".parse().unwrap()])
+            .with_resolved("internal.example", &["10.0.0.1".parse().unwrap()])
+    }
+
+    fn create_deps(
+        url_repo: MockUrlRepository,
+        event_publisher: MockEventPublisher,
+    ) -> HandlerDeps<MockUrlRepository, MockEventPublisher> {
+        HandlerDeps {
+            url_repo,
+            event_publisher,
+            url_policy: test_url_policy(),
+            jwt_verifier: None,
+            configuration: ConfigurationCache::fixed(Configuration::default()),
+        }
+    }
+
+    fn create_request(method: &str, uri: &str, body: Body) -> lambda_http::Request {
+        let mut path_params = HashMap::new();
+        path_params.insert("linkId".to_string(), "abc123".to_string());
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("Content-Type", "application/json")
+            .body(body)
+            .unwrap()
+            .with_path_parameters(path_params)
+            .with_request_context(jwt_request_context("user-1", &[]))
+    }
+
+    fn patch_request(body: Value) -> lambda_http::Request {
+        create_request("PATCH", "/links/abc123", Body::from(body.to_string()))
+    }
+
+    #[tokio::test]
+    async fn when_version_matches_should_update_and_publish() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo
+            .expect_update_destination()
+            .times(1)
+            .with(
+                eq("abc123".to_string()),
+                eq(Some("user-1".to_string())),
+                eq("https://example.com".to_string()),
+                eq(3),
+            )
+            .returning(|link_id, _owner_id, original_link, version| {
+                Ok(ShortUrl {
+                    version: version + 1,
+                    ..ShortUrl::new(link_id.to_string(), original_link)
+                })
+            });
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher
+            .expect_publish_link_updated()
+            .times(1)
+            .with(function(|short_url: &ShortUrl| {
+                short_url.original_link == "https://example.com"
+            }))
+            .returning(|_| Ok(()));
+        let deps = create_deps(mock_url_repo, event_publisher);
+        let request = patch_request(json!({"original_link": "https://example.com", "version": 3}));
+
+        let data = function_handler(&deps, request)
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+
+        assert_eq!(data.status(), 200);
+        let body: Value = serde_json::from_slice(data.body()).unwrap();
+        assert_eq!(body["version"], 4);
+    }
+
+    #[tokio::test]
+    async fn when_version_is_stale_should_return_409_without_publishing() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo
+            .expect_update_destination()
+            .times(1)
+            .returning(|_link_id, _owner_id, _original_link, _version| {
+                Err(RepositoryError::Conflict("stale".to_string()))
+            });
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher.expect_publish_link_updated().times(0);
+        let deps = create_deps(mock_url_repo, event_publisher);
+        let request = patch_request(json!({"original_link": "https://example.com", "version": 1}));
+
+        let data = function_handler(&deps, request)
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+
+        assert_eq!(data.status(), 409);
+    }
+
+    #[tokio::test]
+    async fn when_version_is_missing_should_return_400() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo.expect_update_destination().times(0);
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher.expect_publish_link_updated().times(0);
+        let deps = create_deps(mock_url_repo, event_publisher);
+        let request = patch_request(json!({"original_link": "https://example.com"}));
+
+        let data = function_handler(&deps, request)
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+
+        assert_eq!(data.status(), 400);
+    }
+
+    #[tokio::test]
+    async fn when_destination_is_not_an_absolute_http_url_should_return_400() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo.expect_update_destination().times(0);
+        let deps = create_deps(mock_url_repo, MockEventPublisher::new());
+
+        for original_link in [
+            "",
+            "example.com/path",
+            "/links/other",
+            "ftp://example.com/file",
+        ] {
+            let request = patch_request(json!({"original_link": original_link, "version": 1}));
+
+            let data = function_handler(&deps, request)
+                .await
+                .unwrap()
+                .into_response()
+                .await;
+
+            assert_eq!(data.status(), 400, "{}", original_link);
+        }
+    }
+
+    #[tokio::test]
+    async fn when_destination_is_refused_by_the_url_policy_should_return_422_with_the_reason() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo.expect_update_destination().times(0);
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher.expect_publish_link_updated().times(0);
+        let deps = create_deps(mock_url_repo, event_publisher);
+
+        for (original_link, reason) in [
+            (
+                "http://169.254.169.254/latest/meta-data/",
+                "host '169.254.169.254' points to the non-public address 169.254.169.254",
+            ),
+            (
+                "http://internal.example/admin",
+                "host 'internal.example' points to the non-public address 10.0.0.1",
+            ),
+        ] {
+            let request = patch_request(json!({"original_link": original_link, "version": 1}));
+
+            let data = function_handler(&deps, request)
+                .await
+                .unwrap()
+                .into_response()
+                .await;
+
+            assert_eq!(data.status(), 422, "{}", original_link);
+            let body: Value = serde_json::from_slice(data.body()).unwrap();
+            assert_eq!(body["error"], reason);
+        }
+    }
+
+    #[tokio::test]
+    async fn when_destination_domain_is_blocked_should_return_422_with_the_reason() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo.expect_update_destination().times(0);
+        let configuration = Configuration {
+            domain_policy: serde_json::from_value(json!({"deny": ["example.com"]})).unwrap(),
+            ..Default::default()
+        };
+        let deps = HandlerDeps {
+            configuration: ConfigurationCache::fixed(configuration),
+            ..create_deps(mock_url_repo, MockEventPublisher::new())
+        };
+        let request =
+            patch_request(json!({"original_link": "https://example.com/offer", "version": 1}));
+
+        let data = function_handler(&deps, request)
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+
+        assert_eq!(data.status(), 422);
+        let body: Value = serde_json::from_slice(data.body()).unwrap();
+        assert_eq!(body["error"], "domain 'example.com' is blocked");
+    }
+
+    #[tokio::test]
+    async fn when_history_requested_should_return_previous_destinations() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo
+            .expect_get_url_from_short_link()
+            .times(1)
+            .with(eq("abc123".to_string()))
+            .returning(|link_id| {
+                Ok(Some(ShortUrl {
+                    owner_id: Some("user-1".into()),
+                    version: 1,
+                    history: vec![DestinationChange {
+                        original_link: "https://exmaple.com".into(),
+                        replaced_at: 1_700_000_000,
+                    }],
+                    ..ShortUrl::new(link_id.to_string(), "https://example.com".into())
+                }))
+            });
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher.expect_publish_link_updated().times(0);
+        let deps = create_deps(mock_url_repo, event_publisher);
+        let request = create_request("GET", "/links/abc123/history", Body::Empty);
+
+        let data = function_handler(&deps, request)
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+
+        assert_eq!(data.status(), 200);
+        let body: Value = serde_json::from_slice(data.body()).unwrap();
+        assert_eq!(
+            body,
+            json!([{"original_link": "https://exmaple.com", "replaced_at": 1_700_000_000}])
+        );
+    }
+
+    #[tokio::test]
+    async fn when_tags_are_added_should_return_the_tagged_link() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo
+            .expect_add_tags()
+            .times(1)
+            .with(
+                eq("abc123".to_string()),
+                eq(Some("user-1".to_string())),
+                eq(vec!["spring".to_string(), "sale".to_string()]),
+            )
+            .returning(|link_id, _owner_id, mut tags| {
+                tags.sort();
+                Ok(ShortUrl {
+                    tags,
+                    ..ShortUrl::new(link_id.to_string(), "https://example.com".into())
+                })
+            });
+        let deps = create_deps(mock_url_repo, MockEventPublisher::new());
+        let request = create_request(
+            "POST",
+            "/links/abc123/tags",
+            Body::from(json!({"tags": ["spring", "sale"]}).to_string()),
+        );
+
+        let data = function_handler(&deps, request)
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+
+        assert_eq!(data.status(), 200);
+        let body: Value = serde_json::from_slice(data.body()).unwrap();
+        assert_eq!(body["tags"], json!(["sale", "spring"]));
+    }
+
+    #[tokio::test]
+    async fn when_tags_are_invalid_should_return_400() {
+        let cases = [
+            json!({"tags": []}),
+            json!({"tags": ["no spaces"]}),
+            json!({}),
+        ];
+
+        for body in cases {
+            let mut mock_url_repo = MockUrlRepository::default();
+            mock_url_repo.expect_add_tags().times(0);
+            let deps = create_deps(mock_url_repo, MockEventPublisher::new());
+            let request =
+                create_request("POST", "/links/abc123/tags", Body::from(body.to_string()));
+
+            let data = function_handler(&deps, request)
+                .await
+                .unwrap()
+                .into_response()
+                .await;
+
+            assert_eq!(data.status(), 400, "{}", body);
+        }
+    }
+
+    #[tokio::test]
+    async fn when_tag_is_removed_should_return_the_link() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo
+            .expect_remove_tags()
+            .times(1)
+            .with(
+                eq("abc123".to_string()),
+                eq(Some("user-1".to_string())),
+                eq(vec!["spring".to_string()]),
+            )
+            .returning(|link_id, _owner_id, _tags| {
+                Ok(ShortUrl::new(
+                    link_id.to_string(),
+                    "https://example.com".into(),
+                ))
+            });
+        let deps = create_deps(mock_url_repo, MockEventPublisher::new());
+        let request = create_request("DELETE", "/links/abc123/tags/spring", Body::Empty)
+            .with_path_parameters(HashMap::from([
+                ("linkId".to_string(), "abc123".to_string()),
+                ("tag".to_string(), "spring".to_string()),
+            ]));
+
+        let data = function_handler(&deps, request)
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+
+        assert_eq!(data.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn when_history_requested_for_missing_link_should_return_404() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo
+            .expect_get_url_from_short_link()
+            .times(1)
+            .returning(|_link_id| Ok(None));
+        let deps = create_deps(mock_url_repo, MockEventPublisher::new());
+        let request = create_request("GET", "/links/abc123/history", Body::Empty);
+
+        let data = function_handler(&deps, request)
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+
+        assert_eq!(data.status(), 404);
+    }
+
+    #[tokio::test]
+    async fn when_api_keys_are_configured_should_require_one_for_every_change() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo.expect_add_tags().times(0);
+        mock_url_repo.expect_remove_tags().times(0);
+        mock_url_repo
+            .expect_update_destination()
+            .times(1)
+            .returning(|link_id, _owner_id, original_link, version| {
+                Ok(ShortUrl {
+                    version: version + 1,
+                    ..ShortUrl::new(link_id.to_string(), original_link)
+                })
+            });
+        // Reading the history changes nothing, it goes without a key
+        mock_url_repo
+            .expect_get_url_from_short_link()
+            .times(1)
+            .returning(|link_id| {
+                Ok(Some(ShortUrl {
+                    owner_id: Some("user-1".into()),
+                    ..ShortUrl::new(link_id.to_string(), "https://example.com".into())
+                }))
+            });
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher
+            .expect_publish_link_updated()
+            .times(1)
+            .returning(|_| Ok(()));
+        let configuration: Configuration = serde_json::from_value(json!({
+            "table_name": "links",
+            "api_keys": [{"label": "ci-2026-10", "key": "new-secret"}]
+        }))
+        .unwrap();
+        let deps = HandlerDeps {
+            configuration: ConfigurationCache::fixed(configuration),
+            ..create_deps(mock_url_repo, event_publisher)
+        };
+        let patch = || patch_request(json!({"original_link": "https://example.com", "version": 3}));
+        let add_tags = || {
+            create_request(
+                "POST",
+                "/links/abc123/tags",
+                Body::from(json!({"tags": ["spring"]}).to_string()),
+            )
+        };
+        let remove_tag = || {
+            create_request("DELETE", "/links/abc123/tags/spring", Body::Empty).with_path_parameters(
+                HashMap::from([
+                    ("linkId".to_string(), "abc123".to_string()),
+                    ("tag".to_string(), "spring".to_string()),
+                ]),
+            )
+        };
+        let with_key = |mut request: lambda_http::Request, api_key: &str| {
+            request
+                .headers_mut()
+                .insert("x-api-key", api_key.parse().unwrap());
+            request
+        };
+
+        let cases = [
+            (patch(), 401),
+            (add_tags(), 401),
+            (remove_tag(), 401),
+            (with_key(patch(), "old-secret"), 403),
+            (with_key(add_tags(), "old-secret"), 403),
+            (with_key(remove_tag(), "old-secret"), 403),
+            (
+                create_request("GET", "/links/abc123/history", Body::Empty),
+                200,
+            ),
+            (with_key(patch(), "new-secret"), 200),
+        ];
+        for (request, status) in cases {
+            let route = format!("{} {}", request.method(), request.uri());
+            let data = function_handler(&deps, request)
+                .await
+                .unwrap()
+                .into_response()
+                .await;
+
+            assert_eq!(data.status(), status, "{}", route);
+        }
+    }
+
+    #[tokio::test]
+    async fn when_configuration_could_not_be_loaded_should_return_503() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo.expect_update_destination().times(0);
+        let deps = HandlerDeps {
+            configuration: ConfigurationCache::unavailable(),
+            ..create_deps(mock_url_repo, MockEventPublisher::new())
+        };
+        let request = patch_request(json!({"original_link": "https://example.com", "version": 3}));
+
+        let data = function_handler(&deps, request)
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+
+        assert_eq!(data.status(), 503);
+    }
+
+    #[tokio::test]
+    async fn when_request_is_not_authenticated_should_return_401() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo.expect_update_destination().times(0);
+        let deps = create_deps(mock_url_repo, MockEventPublisher::new());
+        let request = Request::builder()
+            .method("PATCH")
+            .uri("/links/abc123")
+            .body(Body::from(
+                json!({"original_link": "https://example.com", "version": 1}).to_string(),
+            ))
+            .unwrap()
+            .with_path_parameters(HashMap::from([(
+                "linkId".to_string(),
+                "abc123".to_string(),
+            )]));
+
+        let data = function_handler(&deps, request)
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+
+        assert_eq!(data.status(), 401);
+    }
+
+    #[tokio::test]
+    async fn when_link_belongs_to_another_owner_history_should_return_404() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo
+            .expect_get_url_from_short_link()
+            .times(1)
+            .returning(|link_id| {
+                Ok(Some(ShortUrl {
+                    owner_id: Some("user-2".into()),
+                    ..ShortUrl::new(link_id.to_string(), "https://example.com".into())
+                }))
+            });
+        let deps = create_deps(mock_url_repo, MockEventPublisher::new());
+        let request = create_request("GET", "/links/abc123/history", Body::Empty);
+
+        let data = function_handler(&deps, request)
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+
+        assert_eq!(data.status(), 404);
+    }
+
+    #[tokio::test]
+    async fn when_caller_is_admin_should_update_links_of_any_owner() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo
+            .expect_update_destination()
+            .times(1)
+            .with(
+                eq("abc123".to_string()),
+                eq(None),
+                eq("https://example.com".to_string()),
+                eq(1),
+            )
+            .returning(|link_id, _owner_id, original_link, _version| {
+                Ok(ShortUrl::new(link_id.to_string(), original_link))
+            });
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher
+            .expect_publish_link_updated()
+            .returning(|_| Ok(()));
+        let deps = create_deps(mock_url_repo, event_publisher);
+        let request = patch_request(json!({"original_link": "https://example.com", "version": 1}))
+            .with_request_context(jwt_request_context("admin-1", &[ADMIN_SCOPE]));
+
+        let data = function_handler(&deps, request)
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+
+        assert_eq!(data.status(), 200);
+    }
+}
@@ -0,0 +1,72 @@
+use crate::config::Config;
+use crate::event_publisher::SqsEventBridgePublisher;
+use crate::http_handler::{function_handler, HandlerDeps};
+use ::tracing::Instrument;
+use lambda_http::{run, service_fn, tracing, Error};
+use shared::adapters::{url_repository, DynamoDbUrlRepository};
+use shared::configuration::ConfigurationCache;
+use shared::jwt::JwtVerifier;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use std::time::Duration;
+
+mod config;
+mod event_publisher;
+mod http_handler;
+
+static IS_COLD_START: AtomicBool = AtomicBool::new(true);
+/// How soon a change to the domain policy or the API keys reaches a running function.
+const CONFIGURATION_MAX_AGE: Duration = Duration::from_secs(60);
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    let otel_guard =
+        Arc::new(shared::observability::init_otel().expect("Failed to initialize telemetry"));
+    let aws_config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+    let dynamodb_client = aws_sdk_dynamodb::Client::new(&aws_config);
+    let config = Config::load()?;
+    let url_repo = url_repository(config.in_memory_repository, || {
+        DynamoDbUrlRepository::new(config.table_name, dynamodb_client)
+            .with_tags_table(config.tags_table_name)
+    });
+    let event_publisher = SqsEventBridgePublisher::new(
+        aws_sdk_sqs::Client::new(&aws_config),
+        config.queue_url,
+        aws_sdk_eventbridge::Client::new(&aws_config),
+    );
+    let deps = HandlerDeps {
+        url_repo,
+        event_publisher,
+        url_policy: config.url_policy,
+        jwt_verifier: config.jwt.map(JwtVerifier::new).transpose()?,
+        configuration: ConfigurationCache::load(
+            aws_sdk_ssm::Client::new(&aws_config),
+            aws_sdk_secretsmanager::Client::new(&aws_config),
+            CONFIGURATION_MAX_AGE,
+        )
+        .await,
+    };
+
+    run(service_fn(|event| async {
+        let was_cold_start = IS_COLD_START.swap(false, Ordering::SeqCst);
+
+        let handler_span = tracing::info_span!(
+            "aws.lambda",
+            operation_name = "aws.lambda",
+            faas.coldstart = was_cold_start,
+            cloud.provider = "aws",
+            event_type = "http"
+        );
+
+        let res = function_handler(&deps, event)
+            .instrument(handler_span)
+            .await;
+
+        otel_guard.flush();
+
+        res
+    }))
+    .await
+}
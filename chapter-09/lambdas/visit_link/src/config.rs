@@ -5,13 +5,24 @@ use serde::{Deserialize, Serialize};
 #[derive(Debug, Serialize, Deserialize)]
 pub(crate) struct Config {
     pub table_name: String,
+    /// Keep links in memory rather than in DynamoDB, see `adapters::url_repository`.
+    #[serde(default)]
+    pub in_memory_repository: bool,
     pub stream_name: String,
+    /// HMAC key that signs the access cookie of password-protected links.
+    pub access_cookie_secret: String,
 }
 
 impl Config {
-    pub fn load() -> Result<Self, figment::Error> {
+    pub fn load() -> Result<Self, Box<figment::Error>> {
         Figment::new()
-            .merge(Env::raw().only(&["TABLE_NAME", "STREAM_NAME"]))
+            .merge(Env::raw().only(&[
+                "TABLE_NAME",
+                "IN_MEMORY_REPOSITORY",
+                "STREAM_NAME",
+                "ACCESS_COOKIE_SECRET",
+            ]))
             .extract()
+            .map_err(Box::new)
     }
 }
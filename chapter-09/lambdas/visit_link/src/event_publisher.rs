@@ -1,13 +1,18 @@
 use cloudevents::{AttributesReader, EventBuilder, EventBuilderV10};
 #[cfg(test)]
 use mockall::automock;
-use shared::core::{CuidGenerator, IdGenerator, ShortUrl};
+use shared::core::{CuidGenerator, IdGenerator, LinkClicked, ShortUrl};
 
 type Error = Box<dyn std::error::Error + Send + Sync>;
 
 #[cfg_attr(test, automock)]
 pub(crate) trait EventPublisher {
-    async fn publish_link_clicked(&self, short_url: &ShortUrl) -> Result<(), Error>;
+    /// `variant` is the index of the target the visitor was sent to, for A/B links.
+    async fn publish_link_clicked(
+        &self,
+        short_url: &ShortUrl,
+        variant: Option<usize>,
+    ) -> Result<(), Error>;
 }
 
 pub(crate) struct KinesisEventPublisher {
@@ -31,9 +36,16 @@ impl EventPublisher for KinesisEventPublisher {
     messaging.destination = "aws_kinesis",
     messaging.client.id = "visit_link",
 ))]
-    async fn publish_link_clicked(&self, short_url: &ShortUrl) -> Result<(), Error> {
+    async fn publish_link_clicked(
+        &self,
+        short_url: &ShortUrl,
+        variant: Option<usize>,
+    ) -> Result<(), Error> {
         let current_span = tracing::Span::current();
-        let data = serde_json::to_vec(short_url)?;
+        let data = serde_json::to_vec(&LinkClicked {
+            short_url: short_url.clone(),
+            variant,
+        })?;
         let trace_parent = shared::observability::get_traceparent_extension_value(&current_span);
 
         let event: cloudevents::Event = EventBuilderV10::new()
@@ -44,7 +56,7 @@ impl EventPublisher for KinesisEventPublisher {
             .extension("traceparent", trace_parent)
             .build()
             .unwrap();
-        tracing::Span::current().record("messaging.message.id", &event.id().to_string());
+        tracing::Span::current().record("messaging.message.id", event.id().to_string());
 
         let data = serde_json::to_vec(&event)?;
 
@@ -1,12 +1,28 @@
 use crate::event_publisher::EventPublisher;
+use crate::password_gate;
+use crate::preview;
+use lambda_http::http::header::{ACCEPT_LANGUAGE, USER_AGENT};
+use lambda_http::http::Method;
+use lambda_http::request::RequestContext;
 use lambda_http::RequestExt;
-use lambda_http::{http::StatusCode, tracing, Error, IntoResponse, Request};
-use shared::core::UrlRepository;
-use shared::utils::{empty_response, redirect_response};
+use lambda_http::{http::StatusCode, tracing, Body, Error, IntoResponse, Request, Response};
+use shared::configuration::ConfigurationCache;
+use shared::core::{epoch_seconds, LinkStatus, ShortUrl, UrlRepository};
+use shared::password::AccessSigner;
+use shared::query::redirect_location;
+use shared::rules::{first_match, preferred_language, Device, Visitor};
+use shared::targets::choose_target;
+use shared::utils::{
+    configuration_error_response, empty_response, redirect_response, repository_error_response,
+};
 
 pub(crate) struct HandlerDeps<R: UrlRepository, E: EventPublisher> {
     pub url_repo: R,
     pub event_publisher: E,
+    /// Signs the cookie that lets visitors back into password-protected links.
+    pub access_signer: AccessSigner,
+    /// Holds the domain policy, which changes without a redeploy.
+    pub configuration: ConfigurationCache,
 }
 
 #[tracing::instrument(skip(deps, event))]
@@ -14,12 +30,19 @@ pub(crate) async fn function_handler<R: UrlRepository, E: EventPublisher>(
     deps: &HandlerDeps<R, E>,
     event: Request,
 ) -> Result<impl IntoResponse, Error> {
-    tracing::info!("Received event: {:?}", event);
-
     let link_id = event
         .path_parameters_ref()
         .and_then(|params| params.first("linkId"))
         .unwrap_or("");
+    // `/{linkId}+` is short for `/{linkId}?preview`
+    let link_id = link_id.strip_suffix('+').unwrap_or(link_id);
+    // Not the whole request: the body carries the password of protected links
+    tracing::info!(
+        "Received {} {} for link {}",
+        event.method(),
+        event.uri().path(),
+        link_id
+    );
 
     if link_id.is_empty() {
         return empty_response(&StatusCode::NOT_FOUND);
@@ -30,19 +53,208 @@ pub(crate) async fn function_handler<R: UrlRepository, E: EventPublisher>(
     match full_url {
         Err(e) => {
             tracing::error!("Failed to retrieve URL: {:?}", e);
-            empty_response(&StatusCode::INTERNAL_SERVER_ERROR)
+            repository_error_response(&e)
         }
         Ok(None) => empty_response(&StatusCode::NOT_FOUND),
+        Ok(Some(short_url)) if short_url.status == LinkStatus::Deleted => {
+            empty_response(&StatusCode::NOT_FOUND)
+        }
+        Ok(Some(short_url)) if short_url.status == LinkStatus::Disabled => {
+            empty_response(&StatusCode::GONE)
+        }
+        Ok(Some(short_url))
+            if short_url.is_expired(epoch_seconds()) || short_url.is_exhausted() =>
+        {
+            empty_response(&StatusCode::GONE)
+        }
         Ok(Some(short_url)) => {
-            let publish_result = deps.event_publisher.publish_link_clicked(&short_url).await;
-            if let Err(e) = &publish_result {
-                tracing::warn!("Failed to publish link clicked event: {:?}", e);
+            // Domains can be blocked after links to them were created
+            let configuration = match deps.configuration.current().await {
+                Ok(configuration) => configuration,
+                Err(e) => {
+                    tracing::error!("Not redirecting link {}: {}", short_url.link_id, e);
+                    return configuration_error_response(&e);
+                }
+            };
+            let (destination, _) = destination(&event, &short_url);
+            if let Err(e) = configuration.domain_policy.check(destination) {
+                tracing::warn!("Not redirecting link {}: {}", short_url.link_id, e);
+                return preview::blocked_response();
+            }
+            match &short_url.password {
+                Some(password) => password_gate::unlock(deps, &event, &short_url, password).await,
+                None => proceed(deps, &event, &short_url).await,
             }
-            redirect_response(&short_url.original_link)
         }
     }
 }
 
+/// The query parameter asking for the preview page instead of a redirect.
+const PREVIEW_PARAMETER: &str = "preview";
+
+/// Shows the preview page when the visitor asked for it or the link always
+/// does, and follows the link otherwise. Continuing from the preview posts
+/// back to the same URL, which always follows.
+pub(crate) async fn proceed<R: UrlRepository, E: EventPublisher>(
+    deps: &HandlerDeps<R, E>,
+    event: &Request,
+    short_url: &ShortUrl,
+) -> Result<Response<Body>, Error> {
+    if event.method() != Method::POST && (short_url.interstitial || wants_preview(event)) {
+        return preview::preview_response(event, short_url);
+    }
+    follow(deps, event, short_url).await
+}
+
+fn wants_preview(event: &Request) -> bool {
+    let plus_route = event
+        .path_parameters_ref()
+        .and_then(|params| params.first("linkId"))
+        .is_some_and(|link_id| link_id.ends_with('+'));
+    plus_route
+        || form_urlencoded::parse(event.uri().query().unwrap_or_default().as_bytes())
+            .any(|(name, _)| name == PREVIEW_PARAMETER)
+}
+
+/// The query string the visitor added, without the parameter meant for us.
+pub(crate) fn visitor_query(event: &Request) -> Option<String> {
+    let query = event
+        .uri()
+        .query()?
+        .split('&')
+        .filter(|segment| segment.split('=').next() != Some(PREVIEW_PARAMETER))
+        .collect::<Vec<_>>()
+        .join("&");
+    Some(query)
+}
+
+/// Headers CDNs put the visitor's country in, most trusted first.
+const COUNTRY_HEADERS: [&str; 3] = [
+    "cloudfront-viewer-country",
+    "cf-ipcountry",
+    "x-vercel-ip-country",
+];
+
+/// Counts the visit and redirects. The first matching redirect rule wins,
+/// then the targets of an A/B link, and the link's own destination is the
+/// fallback. Whichever it is gets the link's query parameters.
+pub(crate) async fn follow<R: UrlRepository, E: EventPublisher>(
+    deps: &HandlerDeps<R, E>,
+    event: &Request,
+    short_url: &ShortUrl,
+) -> Result<Response<Body>, Error> {
+    let (destination, variant) = destination(event, short_url);
+
+    let publish_result = deps
+        .event_publisher
+        .publish_link_clicked(short_url, variant)
+        .await;
+    if let Err(e) = &publish_result {
+        tracing::warn!("Failed to publish link clicked event: {:?}", e);
+    }
+    let status = match short_url.redirect_type {
+        // A 307 or 308 would replay the form's POST, password included, on the destination
+        redirect_type if event.method() == Method::POST && redirect_type.preserves_method() => {
+            StatusCode::SEE_OTHER
+        }
+        redirect_type => StatusCode::from_u16(redirect_type.status_code())?,
+    };
+    redirect_response(
+        &status,
+        &redirect_location(destination, short_url, visitor_query(event).as_deref()),
+        &cache_control(short_url, epoch_seconds()),
+    )
+}
+
+/// Where the visitor is sent, before query parameters, and for A/B links the
+/// index of the target chosen.
+pub(crate) fn destination<'a>(
+    event: &Request,
+    short_url: &'a ShortUrl,
+) -> (&'a str, Option<usize>) {
+    match first_match(&short_url.rules, &visitor(event)) {
+        Some(destination) => (destination, None),
+        None => {
+            let variant =
+                choose_target(&short_url.targets, &visitor_key(event, &short_url.link_id));
+            let destination = variant
+                .and_then(|variant| short_url.targets.get(variant))
+                .map_or(short_url.original_link.as_str(), |target| {
+                    target.url.as_str()
+                });
+            (destination, variant)
+        }
+    }
+}
+
+/// How long clients may reuse a permanent redirect, at most.
+const PERMANENT_MAX_AGE_SECONDS: u64 = 30 * 24 * 3600;
+
+/// Temporary redirects are never cached, so every click reaches us and is
+/// counted. Permanent ones are, but not past the link's expiry, not at all
+/// when a click limit needs every hit, and only by the visitor's own browser
+/// when the destination depends on who they are.
+fn cache_control(short_url: &ShortUrl, now: u64) -> String {
+    if !short_url.redirect_type.is_permanent() || short_url.max_clicks.is_some() {
+        return "no-store".to_string();
+    }
+    let max_age = short_url
+        .expires_at
+        .map_or(PERMANENT_MAX_AGE_SECONDS, |expires_at| {
+            expires_at
+                .saturating_sub(now)
+                .min(PERMANENT_MAX_AGE_SECONDS)
+        });
+    let per_visitor = !short_url.rules.is_empty()
+        || !short_url.targets.is_empty()
+        || short_url.password.is_some();
+    format!(
+        "{}, max-age={}",
+        if per_visitor { "private" } else { "public" },
+        max_age
+    )
+}
+
+/// What the request headers tell about the visitor.
+fn visitor(event: &Request) -> Visitor {
+    let header = |name| {
+        event
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+    };
+    Visitor {
+        device: Device::from_user_agent(header(USER_AGENT.as_str()).unwrap_or_default()),
+        country: COUNTRY_HEADERS
+            .iter()
+            .find_map(|name| header(name))
+            .map(str::trim)
+            // Cloudflare reports unknown and Tor visitors as XX and T1
+            .filter(|country| country.len() == 2 && !["XX", "T1"].contains(country))
+            .map(str::to_ascii_uppercase),
+        language: header(ACCEPT_LANGUAGE.as_str()).and_then(preferred_language),
+    }
+}
+
+/// Identifies a visitor well enough to keep them on the same variant, without
+/// storing anything about them.
+fn visitor_key(event: &Request, link_id: &str) -> String {
+    let source_ip = match event.request_context_ref() {
+        Some(RequestContext::ApiGatewayV2(context)) => context.http.source_ip.as_deref(),
+        _ => None,
+    };
+    let user_agent = event
+        .headers()
+        .get(USER_AGENT)
+        .and_then(|user_agent| user_agent.to_str().ok());
+    format!(
+        "{}|{}|{}",
+        link_id,
+        source_ip.unwrap_or_default(),
+        user_agent.unwrap_or_default()
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::{function_handler, HandlerDeps};
@@ -50,8 +262,14 @@ mod tests {
     use lambda_http::http::Request;
     use lambda_http::{Body, IntoResponse, RequestExt};
     use mockall::predicate::{eq, function};
-    use shared::core::{MockUrlRepository, ShortUrl};
-    use std::collections::HashMap;
+    use shared::configuration::{Configuration, ConfigurationCache};
+    use shared::core::{
+        epoch_seconds, LinkStatus, MockUrlRepository, QueryMode, RedirectRule, RedirectType,
+        RepositoryError, ShortUrl, Target,
+    };
+    use shared::password::AccessSigner;
+    use shared::rules::Device;
+    use std::collections::{BTreeMap, HashMap};
 
     #[tokio::test]
     async fn when_valid_request_made_with_path_parameter_should_return_redirect() {
@@ -70,13 +288,16 @@ mod tests {
         event_publisher
             .expect_publish_link_clicked()
             .times(1)
-            .with(function(|short_url: &ShortUrl| {
-                short_url.link_id == "123456789"
-            }))
-            .returning(|_| Ok(()));
+            .with(
+                function(|short_url: &ShortUrl| short_url.link_id == "123456789"),
+                eq(None),
+            )
+            .returning(|_, _| Ok(()));
         let deps = HandlerDeps {
             url_repo: mock_url_repo,
             event_publisher,
+            access_signer: AccessSigner::new("test-secret"),
+            configuration: ConfigurationCache::fixed(Configuration::default()),
         };
         let mut path_params = HashMap::new();
         path_params.insert("linkId".to_string(), "123456789".to_string());
@@ -102,6 +323,8 @@ mod tests {
         let deps = HandlerDeps {
             url_repo: mock_url_repo,
             event_publisher,
+            access_signer: AccessSigner::new("test-secret"),
+            configuration: ConfigurationCache::fixed(Configuration::default()),
         };
         let request = Request::builder()
             .header("Content-Type", "application/json")
@@ -122,12 +345,18 @@ mod tests {
             .expect_get_url_from_short_link()
             .times(1)
             .with(eq("aoinf87".to_string()))
-            .returning(|_link_id| Err("Failed to retrieve from DB".to_string()));
+            .returning(|_link_id| {
+                Err(RepositoryError::Fatal(
+                    "Failed to retrieve from DB".to_string(),
+                ))
+            });
         let mut event_publisher = MockEventPublisher::new();
         event_publisher.expect_publish_link_clicked().times(0);
         let deps = HandlerDeps {
             url_repo: mock_url_repo,
             event_publisher,
+            access_signer: AccessSigner::new("test-secret"),
+            configuration: ConfigurationCache::fixed(Configuration::default()),
         };
         let mut path_params = HashMap::new();
         path_params.insert("linkId".to_string(), "aoinf87".to_string());
@@ -144,6 +373,39 @@ mod tests {
         assert_eq!(data.status(), 500);
     }
 
+    #[tokio::test]
+    async fn when_database_is_unavailable_should_return_503_with_retry_after() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo
+            .expect_get_url_from_short_link()
+            .times(1)
+            .returning(|_link_id| Err(RepositoryError::Transient("timeout".to_string())));
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher.expect_publish_link_clicked().times(0);
+        let deps = HandlerDeps {
+            url_repo: mock_url_repo,
+            event_publisher,
+            access_signer: AccessSigner::new("test-secret"),
+            configuration: ConfigurationCache::fixed(Configuration::default()),
+        };
+        let mut path_params = HashMap::new();
+        path_params.insert("linkId".to_string(), "aoinf87".to_string());
+        let request = Request::builder()
+            .header("Content-Type", "application/json")
+            .body(Body::Empty)
+            .unwrap()
+            .with_path_parameters(path_params);
+
+        let data = function_handler(&deps, request)
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+
+        assert_eq!(data.status(), 503);
+        assert!(data.headers().contains_key("Retry-After"));
+    }
+
     #[tokio::test]
     async fn when_link_not_found_should_return_404() {
         let mut mock_url_repo = MockUrlRepository::default();
@@ -157,6 +419,8 @@ mod tests {
         let deps = HandlerDeps {
             url_repo: mock_url_repo,
             event_publisher,
+            access_signer: AccessSigner::new("test-secret"),
+            configuration: ConfigurationCache::fixed(Configuration::default()),
         };
 
         let mut path_params = HashMap::new();
@@ -174,6 +438,446 @@ mod tests {
         assert_eq!(data.status(), 404);
     }
 
+    #[tokio::test]
+    async fn when_link_is_expired_or_exhausted_should_return_410_without_publishing() {
+        let expired = ShortUrl {
+            expires_at: Some(1),
+            ..ShortUrl::new("expired".into(), "https://example.com".into())
+        };
+        let exhausted = ShortUrl {
+            clicks: 10,
+            max_clicks: Some(10),
+            ..ShortUrl::new("exhausted".into(), "https://example.com".into())
+        };
+
+        for short_url in [expired, exhausted] {
+            let mut mock_url_repo = MockUrlRepository::default();
+            let link_id = short_url.link_id.clone();
+            mock_url_repo
+                .expect_get_url_from_short_link()
+                .times(1)
+                .returning(move |_link_id| Ok(Some(short_url.clone())));
+            let mut event_publisher = MockEventPublisher::new();
+            event_publisher.expect_publish_link_clicked().times(0);
+            let deps = HandlerDeps {
+                url_repo: mock_url_repo,
+                event_publisher,
+                access_signer: AccessSigner::new("test-secret"),
+                configuration: ConfigurationCache::fixed(Configuration::default()),
+            };
+            let mut path_params = HashMap::new();
+            path_params.insert("linkId".to_string(), link_id.clone());
+            let request = Request::builder()
+                .body(Body::Empty)
+                .unwrap()
+                .with_path_parameters(path_params);
+
+            let data = function_handler(&deps, request)
+                .await
+                .unwrap()
+                .into_response()
+                .await;
+
+            assert_eq!(data.status(), 410, "{}", link_id);
+        }
+    }
+
+    #[tokio::test]
+    async fn when_link_is_deleted_or_disabled_should_not_redirect() {
+        let cases = [(LinkStatus::Deleted, 404), (LinkStatus::Disabled, 410)];
+
+        for (status, expected_status) in cases {
+            let mut mock_url_repo = MockUrlRepository::default();
+            mock_url_repo
+                .expect_get_url_from_short_link()
+                .times(1)
+                .returning(move |link_id| {
+                    Ok(Some(ShortUrl {
+                        status,
+                        ..ShortUrl::new(link_id.to_string(), "https://example.com".into())
+                    }))
+                });
+            let mut event_publisher = MockEventPublisher::new();
+            event_publisher.expect_publish_link_clicked().times(0);
+            let deps = HandlerDeps {
+                url_repo: mock_url_repo,
+                event_publisher,
+                access_signer: AccessSigner::new("test-secret"),
+                configuration: ConfigurationCache::fixed(Configuration::default()),
+            };
+            let mut path_params = HashMap::new();
+            path_params.insert("linkId".to_string(), "abc123".to_string());
+            let request = Request::builder()
+                .body(Body::Empty)
+                .unwrap()
+                .with_path_parameters(path_params);
+
+            let data = function_handler(&deps, request)
+                .await
+                .unwrap()
+                .into_response()
+                .await;
+
+            assert_eq!(data.status(), expected_status, "{:?}", status);
+        }
+    }
+
+    #[tokio::test]
+    async fn when_link_has_not_expired_yet_should_redirect() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo
+            .expect_get_url_from_short_link()
+            .times(1)
+            .returning(|link_id| {
+                Ok(Some(ShortUrl {
+                    clicks: 9,
+                    max_clicks: Some(10),
+                    expires_at: Some(u64::MAX),
+                    ..ShortUrl::new(link_id.to_string(), "https://example.com".into())
+                }))
+            });
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher
+            .expect_publish_link_clicked()
+            .times(1)
+            .returning(|_, _| Ok(()));
+        let deps = HandlerDeps {
+            url_repo: mock_url_repo,
+            event_publisher,
+            access_signer: AccessSigner::new("test-secret"),
+            configuration: ConfigurationCache::fixed(Configuration::default()),
+        };
+        let mut path_params = HashMap::new();
+        path_params.insert("linkId".to_string(), "abc123".to_string());
+        let request = Request::builder()
+            .body(Body::Empty)
+            .unwrap()
+            .with_path_parameters(path_params);
+
+        let data = function_handler(&deps, request)
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+
+        assert_eq!(data.status(), 302);
+    }
+
+    #[tokio::test]
+    async fn when_link_has_targets_should_redirect_to_the_chosen_variant_and_publish_it() {
+        let targets = vec![
+            Target {
+                url: "https://example.com/paused".into(),
+                weight: 0,
+                clicks: 0,
+            },
+            Target {
+                url: "https://example.com/b".into(),
+                weight: 1,
+                clicks: 0,
+            },
+        ];
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo
+            .expect_get_url_from_short_link()
+            .times(1)
+            .returning(move |link_id| {
+                Ok(Some(ShortUrl {
+                    targets: targets.clone(),
+                    ..ShortUrl::new(link_id.to_string(), "https://example.com".into())
+                }))
+            });
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher
+            .expect_publish_link_clicked()
+            .times(1)
+            .with(function(|_: &ShortUrl| true), eq(Some(1)))
+            .returning(|_, _| Ok(()));
+        let deps = HandlerDeps {
+            url_repo: mock_url_repo,
+            event_publisher,
+            access_signer: AccessSigner::new("test-secret"),
+            configuration: ConfigurationCache::fixed(Configuration::default()),
+        };
+        let mut path_params = HashMap::new();
+        path_params.insert("linkId".to_string(), "abc123".to_string());
+        let request = Request::builder()
+            .header("User-Agent", "test-agent")
+            .body(Body::Empty)
+            .unwrap()
+            .with_path_parameters(path_params);
+
+        let data = function_handler(&deps, request)
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+
+        assert_eq!(data.status(), 302);
+        assert_eq!(data.headers()["location"], "https://example.com/b");
+    }
+
+    #[tokio::test]
+    async fn when_link_has_rules_should_redirect_on_the_first_matching_one() {
+        let rule =
+            |device, countries: &[&str], languages: &[&str], destination: &str| RedirectRule {
+                device,
+                countries: countries.iter().map(|c| c.to_string()).collect(),
+                languages: languages.iter().map(|l| l.to_string()).collect(),
+                destination: destination.to_string(),
+            };
+        let rules = vec![
+            rule(Some(Device::Ios), &[], &[], "https://apps.apple.com/app"),
+            rule(
+                Some(Device::Android),
+                &[],
+                &[],
+                "https://play.google.com/app",
+            ),
+            rule(None, &["BE", "FR"], &["fr"], "https://example.com/fr"),
+            rule(None, &["DE"], &[], "https://example.com/de"),
+            rule(None, &[], &["es"], "https://example.com/es"),
+        ];
+        let iphone = "Mozilla/5.0 (iPhone; CPU iPhone OS 17_0 like Mac OS X)";
+        let android = "Mozilla/5.0 (Linux; Android 14; Pixel 8)";
+        let desktop = "Mozilla/5.0 (Windows NT 10.0; Win64; x64)";
+        let cases: [(&[(&str, &str)], &str); 8] = [
+            (&[("User-Agent", iphone)], "https://apps.apple.com/app"),
+            (
+                &[("User-Agent", android), ("CloudFront-Viewer-Country", "BE")],
+                "https://play.google.com/app",
+            ),
+            (
+                &[
+                    ("User-Agent", desktop),
+                    ("CloudFront-Viewer-Country", "BE"),
+                    ("Accept-Language", "fr-BE,fr;q=0.9"),
+                ],
+                "https://example.com/fr",
+            ),
+            (
+                &[
+                    ("User-Agent", desktop),
+                    ("CF-IPCountry", "FR"),
+                    ("Accept-Language", "en-GB"),
+                ],
+                "https://example.com",
+            ),
+            (&[("CF-IPCountry", "de")], "https://example.com/de"),
+            (&[("CF-IPCountry", "XX")], "https://example.com"),
+            (
+                &[("Accept-Language", "en;q=0.8, es")],
+                "https://example.com/es",
+            ),
+            (&[], "https://example.com"),
+        ];
+
+        for (headers, location) in cases {
+            let rules = rules.clone();
+            let mut mock_url_repo = MockUrlRepository::default();
+            mock_url_repo
+                .expect_get_url_from_short_link()
+                .times(1)
+                .returning(move |link_id| {
+                    Ok(Some(ShortUrl {
+                        rules: rules.clone(),
+                        ..ShortUrl::new(link_id.to_string(), "https://example.com".into())
+                    }))
+                });
+            let mut event_publisher = MockEventPublisher::new();
+            event_publisher
+                .expect_publish_link_clicked()
+                .times(1)
+                .with(function(|_: &ShortUrl| true), eq(None))
+                .returning(|_, _| Ok(()));
+            let deps = HandlerDeps {
+                url_repo: mock_url_repo,
+                event_publisher,
+                access_signer: AccessSigner::new("test-secret"),
+                configuration: ConfigurationCache::fixed(Configuration::default()),
+            };
+            let mut request = Request::builder();
+            for (name, value) in headers {
+                request = request.header(*name, *value);
+            }
+            let request = request
+                .body(Body::Empty)
+                .unwrap()
+                .with_path_parameters(HashMap::from([(
+                    "linkId".to_string(),
+                    "abc123".to_string(),
+                )]));
+
+            let data = function_handler(&deps, request)
+                .await
+                .unwrap()
+                .into_response()
+                .await;
+
+            assert_eq!(data.status(), 302, "{:?}", headers);
+            assert_eq!(data.headers()["location"], location, "{:?}", headers);
+        }
+    }
+
+    #[tokio::test]
+    async fn when_link_merges_the_query_should_pass_it_on_with_the_utm_parameters() {
+        let mut mock_url_repo = MockUrlRepository::default();
+        mock_url_repo
+            .expect_get_url_from_short_link()
+            .times(1)
+            .returning(|link_id| {
+                Ok(Some(ShortUrl {
+                    query_mode: QueryMode::Merge,
+                    utm: BTreeMap::from([("utm_source".into(), "newsletter".into())]),
+                    ..ShortUrl::new(link_id.to_string(), "https://example.com/a?lang=en".into())
+                }))
+            });
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher
+            .expect_publish_link_clicked()
+            .times(1)
+            .returning(|_, _| Ok(()));
+        let deps = HandlerDeps {
+            url_repo: mock_url_repo,
+            event_publisher,
+            access_signer: AccessSigner::new("test-secret"),
+            configuration: ConfigurationCache::fixed(Configuration::default()),
+        };
+        let request = Request::builder()
+            .uri("https://sho.rt/abc123?ref=t%C3%A9st&lang=fr")
+            .body(Body::Empty)
+            .unwrap()
+            .with_path_parameters(HashMap::from([(
+                "linkId".to_string(),
+                "abc123".to_string(),
+            )]));
+
+        let data = function_handler(&deps, request)
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+
+        assert_eq!(data.status(), 302);
+        assert_eq!(
+            data.headers()["location"],
+            "https://example.com/a?lang=en&utm_source=newsletter&ref=t%C3%A9st"
+        );
+    }
+
+    #[tokio::test]
+    async fn when_link_has_a_redirect_type_should_use_its_status_and_cache_headers() {
+        let now = epoch_seconds();
+        let cases = [
+            (ShortUrl::default(), 302, "no-store"),
+            (
+                ShortUrl {
+                    redirect_type: RedirectType::TemporaryRedirect,
+                    ..Default::default()
+                },
+                307,
+                "no-store",
+            ),
+            (
+                ShortUrl {
+                    redirect_type: RedirectType::MovedPermanently,
+                    ..Default::default()
+                },
+                301,
+                "public, max-age=2592000",
+            ),
+            (
+                ShortUrl {
+                    redirect_type: RedirectType::PermanentRedirect,
+                    ..Default::default()
+                },
+                308,
+                "public, max-age=2592000",
+            ),
+            (
+                ShortUrl {
+                    redirect_type: RedirectType::MovedPermanently,
+                    max_clicks: Some(100),
+                    ..Default::default()
+                },
+                301,
+                "no-store",
+            ),
+            (
+                ShortUrl {
+                    redirect_type: RedirectType::MovedPermanently,
+                    expires_at: Some(now + 600),
+                    ..Default::default()
+                },
+                301,
+                "public, max-age=600",
+            ),
+            (
+                ShortUrl {
+                    redirect_type: RedirectType::PermanentRedirect,
+                    rules: vec![RedirectRule {
+                        device: Some(Device::Ios),
+                        countries: vec![],
+                        languages: vec![],
+                        destination: "https://apps.apple.com/app".into(),
+                    }],
+                    ..Default::default()
+                },
+                308,
+                "private, max-age=2592000",
+            ),
+        ];
+
+        for (short_url, status, cache_control) in cases {
+            let mut mock_url_repo = MockUrlRepository::default();
+            mock_url_repo
+                .expect_get_url_from_short_link()
+                .times(1)
+                .returning(move |link_id| {
+                    Ok(Some(ShortUrl {
+                        link_id: link_id.to_string(),
+                        original_link: "https://example.com".into(),
+                        ..short_url.clone()
+                    }))
+                });
+            let mut event_publisher = MockEventPublisher::new();
+            event_publisher
+                .expect_publish_link_clicked()
+                .times(1)
+                .returning(|_, _| Ok(()));
+            let deps = HandlerDeps {
+                url_repo: mock_url_repo,
+                event_publisher,
+                access_signer: AccessSigner::new("test-secret"),
+                configuration: ConfigurationCache::fixed(Configuration::default()),
+            };
+            let request = Request::builder()
+                .body(Body::Empty)
+                .unwrap()
+                .with_path_parameters(HashMap::from([(
+                    "linkId".to_string(),
+                    "abc123".to_string(),
+                )]));
+
+            let data = function_handler(&deps, request)
+                .await
+                .unwrap()
+                .into_response()
+                .await;
+
+            assert_eq!(data.status(), status);
+            assert_eq!(data.headers()["location"], "https://example.com");
+            // The handler's clock may have ticked past `now`
+            let header = data.headers()["cache-control"].to_str().unwrap();
+            assert!(
+                header == cache_control || header == cache_control.replace("=600", "=599"),
+                "{} for {}",
+                header,
+                status
+            );
+        }
+    }
+
     #[tokio::test]
     async fn when_publish_fails_should_still_redirect() {
         let mut mock_url_repo = MockUrlRepository::default();
@@ -191,15 +895,12 @@ mod tests {
         event_publisher
             .expect_publish_link_clicked()
             .times(1)
-            .returning(|_| {
-                Err(Box::new(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    "publish failed",
-                )))
-            });
+            .returning(|_, _| Err(Box::new(std::io::Error::other("publish failed"))));
         let deps = HandlerDeps {
             url_repo: mock_url_repo,
             event_publisher,
+            access_signer: AccessSigner::new("test-secret"),
+            configuration: ConfigurationCache::fixed(Configuration::default()),
         };
         let mut path_params = HashMap::new();
         path_params.insert("linkId".to_string(), "abc123".to_string());
@@ -7,14 +7,21 @@ use crate::config::Config;
 use crate::http_handler::HandlerDeps;
 use http_handler::function_handler;
 use lambda_http::{run, service_fn, Error};
-use shared::adapters::DynamoDbUrlRepository;
+use shared::adapters::{url_repository, DynamoDbUrlRepository};
+use shared::configuration::ConfigurationCache;
+use shared::password::AccessSigner;
+use std::time::Duration;
 use tracing::Instrument;
 
 mod config;
 mod event_publisher;
 mod http_handler;
+mod password_gate;
+mod preview;
 
 static IS_COLD_START: AtomicBool = AtomicBool::new(true);
+/// How soon a newly blocked domain stops being redirected to.
+const CONFIGURATION_MAX_AGE: Duration = Duration::from_secs(60);
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
@@ -24,12 +31,21 @@ async fn main() -> Result<(), Error> {
     let dynamodb_client = aws_sdk_dynamodb::Client::new(&aws_config);
     let kinesis_client = aws_sdk_kinesis::Client::new(&aws_config);
     let config = Config::load()?;
-    let url_repo = DynamoDbUrlRepository::new(config.table_name, dynamodb_client);
+    let url_repo = url_repository(config.in_memory_repository, || {
+        DynamoDbUrlRepository::new(config.table_name, dynamodb_client)
+    });
     let event_publisher =
         event_publisher::KinesisEventPublisher::new(kinesis_client, config.stream_name);
     let deps = HandlerDeps {
         url_repo,
         event_publisher,
+        access_signer: AccessSigner::new(config.access_cookie_secret),
+        configuration: ConfigurationCache::load(
+            aws_sdk_ssm::Client::new(&aws_config),
+            aws_sdk_secretsmanager::Client::new(&aws_config),
+            CONFIGURATION_MAX_AGE,
+        )
+        .await,
     };
 
     run(service_fn(|event| async {
@@ -0,0 +1,365 @@
+use crate::event_publisher::EventPublisher;
+use crate::http_handler::{follow, proceed, HandlerDeps};
+use lambda_http::http::header::{HeaderValue, COOKIE, RETRY_AFTER, SET_COOKIE};
+use lambda_http::http::{Method, StatusCode};
+use lambda_http::{tracing, Body, Error, Request, Response};
+use shared::core::{epoch_seconds, ShortUrl, UrlRepository};
+use shared::password::{
+    access_cookie_name, failure_window, seconds_until_unlocked, LinkPassword,
+    ACCESS_COOKIE_SECONDS, MAX_PASSWORD_FAILURES,
+};
+use shared::utils::{html_response, repository_error_response};
+
+/// Serves a protected link: the password form, its submission, and visitors
+/// coming back with a valid access cookie. Nothing is published until the
+/// password is known to be right.
+pub(crate) async fn unlock<R: UrlRepository, E: EventPublisher>(
+    deps: &HandlerDeps<R, E>,
+    event: &Request,
+    short_url: &ShortUrl,
+    password: &LinkPassword,
+) -> Result<Response<Body>, Error> {
+    let now = epoch_seconds();
+    // Includes continuing from the preview page, which posts back here
+    if has_access(deps, event, &short_url.link_id, now) {
+        return proceed(deps, event, short_url).await;
+    }
+    if event.method() != Method::POST {
+        return form_response(&StatusCode::OK, None);
+    }
+
+    // Once locked, even the right password waits for the window to end
+    if password.is_locked(now) {
+        return locked_response(now);
+    }
+    let submitted = form_urlencoded::parse(event.body().as_ref())
+        .find(|(name, _)| name == "password")
+        .map(|(_, value)| value.into_owned())
+        .unwrap_or_default();
+    if !password.verify(&submitted) {
+        return match deps
+            .url_repo
+            .record_password_failure(&short_url.link_id, failure_window(now))
+            .await
+        {
+            Ok(failures) if failures >= MAX_PASSWORD_FAILURES => locked_response(now),
+            Ok(_) => form_response(&StatusCode::UNAUTHORIZED, Some("Wrong password.")),
+            Err(e) => {
+                tracing::error!("Failed to record password failure: {:?}", e);
+                repository_error_response(&e)
+            }
+        };
+    }
+
+    let mut response = follow(deps, event, short_url).await?;
+    let token = deps
+        .access_signer
+        .issue(&short_url.link_id, now + ACCESS_COOKIE_SECONDS);
+    // A path of `/{linkId}` would not match the `/{linkId}+` preview route, so the
+    // cookie goes to every path and only its signature ties it to the link
+    let cookie = format!(
+        "{}={}; Path=/; Max-Age={}; HttpOnly; Secure; SameSite=Lax",
+        access_cookie_name(&short_url.link_id),
+        token,
+        ACCESS_COOKIE_SECONDS
+    );
+    response
+        .headers_mut()
+        .insert(SET_COOKIE, HeaderValue::from_str(&cookie)?);
+    Ok(response)
+}
+
+fn has_access<R: UrlRepository, E: EventPublisher>(
+    deps: &HandlerDeps<R, E>,
+    event: &Request,
+    link_id: &str,
+    now: u64,
+) -> bool {
+    let cookie_name = access_cookie_name(link_id);
+    event
+        .headers()
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|header| header.to_str().ok())
+        .flat_map(|header| header.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .any(|(name, value)| name == cookie_name && deps.access_signer.verify(value, link_id, now))
+}
+
+fn form_response(status: &StatusCode, message: Option<&str>) -> Result<Response<Body>, Error> {
+    let message = message
+        .map(|message| format!("<p role=\"alert\">{}</p>", message))
+        .unwrap_or_default();
+    html_response(
+        status,
+        format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head><meta charset="utf-8"><meta name="viewport" content="width=device-width"><title>Protected link</title></head>
+<body>
+<h1>This link is password protected</h1>
+{}
+<form method="post">
+<label>Password <input type="password" name="password" required autofocus></label>
+<button type="submit">Continue</button>
+</form>
+</body>
+</html>
+"#,
+            message
+        ),
+    )
+}
+
+fn locked_response(now: u64) -> Result<Response<Body>, Error> {
+    let mut response = html_response(
+        &StatusCode::TOO_MANY_REQUESTS,
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head><meta charset="utf-8"><title>Protected link</title></head>
+<body><h1>Too many wrong passwords, please try again later.</h1></body>
+</html>
+"#
+        .to_string(),
+    )?;
+    response
+        .headers_mut()
+        .insert(RETRY_AFTER, HeaderValue::from(seconds_until_unlocked(now)));
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::event_publisher::MockEventPublisher;
+    use crate::http_handler::{function_handler, HandlerDeps};
+    use lambda_http::http::Request;
+    use lambda_http::{Body, IntoResponse, RequestExt, Response};
+    use shared::configuration::{Configuration, ConfigurationCache};
+    use shared::core::{epoch_seconds, MockUrlRepository, RedirectType, ShortUrl};
+    use shared::password::{
+        failure_window, hash_password, AccessSigner, LinkPassword, MAX_PASSWORD_FAILURES,
+    };
+    use std::collections::HashMap;
+
+    fn protected_link(failures: u32) -> ShortUrl {
+        ShortUrl {
+            password: Some(LinkPassword {
+                hash: hash_password("open sesame").unwrap(),
+                failures,
+                failure_window: failure_window(epoch_seconds()),
+            }),
+            ..ShortUrl::new("abc123".into(), "https://example.com".into())
+        }
+    }
+
+    fn create_deps(
+        short_url: ShortUrl,
+        url_repo: MockUrlRepository,
+        event_publisher: MockEventPublisher,
+    ) -> HandlerDeps<MockUrlRepository, MockEventPublisher> {
+        let mut url_repo = url_repo;
+        url_repo
+            .expect_get_url_from_short_link()
+            .returning(move |_link_id| Ok(Some(short_url.clone())));
+        HandlerDeps {
+            url_repo,
+            event_publisher,
+            access_signer: AccessSigner::new("test-secret"),
+            configuration: ConfigurationCache::fixed(Configuration::default()),
+        }
+    }
+
+    fn create_request(method: &str, body: &str, cookie: Option<&str>) -> lambda_http::Request {
+        create_request_for("abc123", method, body, cookie)
+    }
+
+    fn create_request_for(
+        link_id: &str,
+        method: &str,
+        body: &str,
+        cookie: Option<&str>,
+    ) -> lambda_http::Request {
+        let mut request = Request::builder()
+            .method(method)
+            .header("Content-Type", "application/x-www-form-urlencoded");
+        if let Some(cookie) = cookie {
+            request = request.header("Cookie", cookie);
+        }
+        request
+            .body(Body::from(body.to_string()))
+            .unwrap()
+            .with_path_parameters(HashMap::from([("linkId".to_string(), link_id.to_string())]))
+    }
+
+    async fn send(
+        deps: &HandlerDeps<MockUrlRepository, MockEventPublisher>,
+        request: lambda_http::Request,
+    ) -> Response<Body> {
+        function_handler(deps, request)
+            .await
+            .unwrap()
+            .into_response()
+            .await
+    }
+
+    #[tokio::test]
+    async fn when_link_is_protected_should_serve_the_form_without_publishing() {
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher.expect_publish_link_clicked().times(0);
+        let deps = create_deps(
+            protected_link(0),
+            MockUrlRepository::default(),
+            event_publisher,
+        );
+
+        let response = send(&deps, create_request("GET", "", None)).await;
+
+        assert_eq!(response.status(), 200);
+        assert!(response.headers()["content-type"]
+            .to_str()
+            .unwrap()
+            .starts_with("text/html"));
+        let Body::Text(html) = response.body() else {
+            panic!("expected an HTML body");
+        };
+        assert!(html.contains("<form method=\"post\">"));
+    }
+
+    #[tokio::test]
+    async fn when_password_is_right_should_redirect_publish_and_set_a_cookie_that_skips_the_form() {
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher
+            .expect_publish_link_clicked()
+            .times(2)
+            .returning(|_, _| Ok(()));
+        let mut url_repo = MockUrlRepository::default();
+        url_repo.expect_record_password_failure().times(0);
+        let deps = create_deps(protected_link(0), url_repo, event_publisher);
+
+        let response = send(&deps, create_request("POST", "password=open+sesame", None)).await;
+
+        assert_eq!(response.status(), 302);
+        let cookie = response.headers()["set-cookie"].to_str().unwrap();
+        assert!(cookie.starts_with("link_access_abc123="));
+        assert!(cookie.contains("Path=/;"));
+        assert!(cookie.contains("HttpOnly"));
+        let (pair, _) = cookie.split_once(';').unwrap();
+
+        let response = send(
+            &deps,
+            create_request("GET", "", Some(&format!("theme=dark; {}", pair))),
+        )
+        .await;
+
+        assert_eq!(response.status(), 302);
+    }
+
+    #[tokio::test]
+    async fn when_unlocked_on_one_route_should_skip_the_form_on_the_other() {
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher
+            .expect_publish_link_clicked()
+            .times(3)
+            .returning(|_, _| Ok(()));
+        let deps = create_deps(
+            protected_link(0),
+            MockUrlRepository::default(),
+            event_publisher,
+        );
+
+        // The preview route shows the preview page, the plain one follows the link
+        for (unlocked_on, visited, status) in
+            [("abc123", "abc123+", 200), ("abc123+", "abc123", 302)]
+        {
+            let response = send(
+                &deps,
+                create_request_for(unlocked_on, "POST", "password=open+sesame", None),
+            )
+            .await;
+            let cookie = response.headers()["set-cookie"].to_str().unwrap();
+            // Browsers only send it to `/{visited}` if its path is a prefix of it
+            assert!(cookie.contains("Path=/;"), "{}", unlocked_on);
+            let (pair, _) = cookie.split_once(';').unwrap();
+
+            let response = send(&deps, create_request_for(visited, "GET", "", Some(pair))).await;
+
+            assert_eq!(response.status(), status, "{}", visited);
+            if let Body::Text(html) = response.body() {
+                assert!(!html.contains("type=\"password\""), "{}", visited);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn when_link_preserves_the_method_should_not_replay_the_form_on_the_destination() {
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher
+            .expect_publish_link_clicked()
+            .times(1)
+            .returning(|_, _| Ok(()));
+        let short_url = ShortUrl {
+            redirect_type: RedirectType::PermanentRedirect,
+            ..protected_link(0)
+        };
+        let deps = create_deps(short_url, MockUrlRepository::default(), event_publisher);
+
+        let response = send(&deps, create_request("POST", "password=open+sesame", None)).await;
+
+        assert_eq!(response.status(), 303);
+    }
+
+    #[tokio::test]
+    async fn when_password_is_wrong_should_count_the_failure_and_not_publish() {
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher.expect_publish_link_clicked().times(0);
+        let mut url_repo = MockUrlRepository::default();
+        url_repo
+            .expect_record_password_failure()
+            .times(1)
+            .returning(|_link_id, _window| Ok(1));
+        let deps = create_deps(protected_link(0), url_repo, event_publisher);
+
+        let response = send(&deps, create_request("POST", "password=guess", None)).await;
+
+        assert_eq!(response.status(), 401);
+        assert!(!response.headers().contains_key("set-cookie"));
+    }
+
+    #[tokio::test]
+    async fn when_too_many_wrong_passwords_should_return_429_even_for_the_right_one() {
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher.expect_publish_link_clicked().times(0);
+        let mut url_repo = MockUrlRepository::default();
+        url_repo.expect_record_password_failure().times(0);
+        let deps = create_deps(
+            protected_link(MAX_PASSWORD_FAILURES),
+            url_repo,
+            event_publisher,
+        );
+
+        let response = send(&deps, create_request("POST", "password=open+sesame", None)).await;
+
+        assert_eq!(response.status(), 429);
+        assert!(response.headers().contains_key("retry-after"));
+    }
+
+    #[tokio::test]
+    async fn when_cookie_is_forged_should_serve_the_form() {
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher.expect_publish_link_clicked().times(0);
+        let deps = create_deps(
+            protected_link(0),
+            MockUrlRepository::default(),
+            event_publisher,
+        );
+        let forged = AccessSigner::new("other-secret").issue("abc123", u64::MAX);
+
+        let response = send(
+            &deps,
+            create_request("GET", "", Some(&format!("link_access_abc123={}", forged))),
+        )
+        .await;
+
+        assert_eq!(response.status(), 200);
+    }
+}
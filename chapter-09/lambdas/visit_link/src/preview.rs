@@ -0,0 +1,216 @@
+use crate::http_handler::{destination, visitor_query};
+use lambda_http::http::StatusCode;
+use lambda_http::{Body, Error, Request, Response};
+use shared::core::ShortUrl;
+use shared::query::redirect_location;
+use shared::utils::{escape_html, html_response};
+
+/// Renders where the link goes instead of going there. Nothing is published:
+/// the continue button posts back to the same URL, and that counts the click.
+pub(crate) fn preview_response(
+    event: &Request,
+    short_url: &ShortUrl,
+) -> Result<Response<Body>, Error> {
+    let (destination, _) = destination(event, short_url);
+    let location = redirect_location(destination, short_url, visitor_query(event).as_deref());
+
+    // The scraped details describe the link's own destination, not those of
+    // its rules or targets
+    let mut details = String::new();
+    if destination == short_url.original_link {
+        let fields = [
+            ("Title", &short_url.title),
+            ("Description", &short_url.description),
+            ("Content type", &short_url.content_type),
+        ];
+        for (label, value) in fields {
+            if let Some(value) = value {
+                details.push_str(&format!(
+                    "<dt>{}</dt><dd>{}</dd>\n",
+                    label,
+                    escape_html(value)
+                ));
+            }
+        }
+    }
+
+    html_response(
+        &StatusCode::OK,
+        format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head><meta charset="utf-8"><meta name="viewport" content="width=device-width"><meta name="robots" content="noindex"><title>Link preview</title></head>
+<body>
+<h1>This link leads to</h1>
+<p><code>{}</code></p>
+<dl>
+{}</dl>
+<form method="post">
+<button type="submit">Continue</button>
+</form>
+</body>
+</html>
+"#,
+            escape_html(&location),
+            details
+        ),
+    )
+}
+
+/// Shown instead of redirecting when the destination's domain is blocked. It
+/// does not say which domain, password-protected links keep theirs private.
+pub(crate) fn blocked_response() -> Result<Response<Body>, Error> {
+    html_response(
+        &StatusCode::FORBIDDEN,
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head><meta charset="utf-8"><meta name="viewport" content="width=device-width"><meta name="robots" content="noindex"><title>Link blocked</title></head>
+<body>
+<h1>This link has been blocked</h1>
+<p>It leads to a site we no longer send visitors to, for example because it is known for phishing or malware.</p>
+</body>
+</html>
+"#
+        .to_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::event_publisher::MockEventPublisher;
+    use crate::http_handler::{function_handler, HandlerDeps};
+    use lambda_http::http::Request;
+    use lambda_http::{Body, IntoResponse, RequestExt, Response};
+    use shared::configuration::{Configuration, ConfigurationCache};
+    use shared::core::{MockUrlRepository, QueryMode, ShortUrl};
+    use shared::domain_policy::DomainPolicy;
+    use shared::password::{AccessSigner, LinkPassword};
+    use std::collections::HashMap;
+
+    fn create_deps(
+        short_url: ShortUrl,
+        clicks: usize,
+    ) -> HandlerDeps<MockUrlRepository, MockEventPublisher> {
+        let mut url_repo = MockUrlRepository::default();
+        url_repo
+            .expect_get_url_from_short_link()
+            .returning(move |_link_id| Ok(Some(short_url.clone())));
+        let mut event_publisher = MockEventPublisher::new();
+        event_publisher
+            .expect_publish_link_clicked()
+            .times(clicks)
+            .returning(|_, _| Ok(()));
+        HandlerDeps {
+            url_repo,
+            event_publisher,
+            access_signer: AccessSigner::new("test-secret"),
+            configuration: ConfigurationCache::fixed(Configuration::default()),
+        }
+    }
+
+    fn create_request(method: &str, link_id: &str, uri: &str) -> lambda_http::Request {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::Empty)
+            .unwrap()
+            .with_path_parameters(HashMap::from([("linkId".to_string(), link_id.to_string())]))
+    }
+
+    #[tokio::test]
+    async fn when_destination_domain_is_blocked_should_show_a_warning_without_redirecting() {
+        let short_url = ShortUrl {
+            password: Some(LinkPassword::default()),
+            ..ShortUrl::new("abc123".into(), "https://login.phish.example.net/".into())
+        };
+        let mut deps = create_deps(short_url, 0);
+        deps.configuration = ConfigurationCache::fixed(Configuration {
+            domain_policy: DomainPolicy {
+                allow: vec![],
+                deny: vec!["*.phish.example.net".parse().unwrap()],
+            },
+            ..Default::default()
+        });
+
+        let response = send(
+            &deps,
+            create_request("GET", "abc123", "https://sho.rt/abc123"),
+        )
+        .await;
+
+        assert_eq!(response.status(), 403);
+        assert!(!response.headers().contains_key("location"));
+        let Body::Text(html) = response.body() else {
+            panic!("expected an HTML body");
+        };
+        assert!(html.contains("This link has been blocked"));
+        assert!(!html.contains("example.net"));
+    }
+
+    async fn send(
+        deps: &HandlerDeps<MockUrlRepository, MockEventPublisher>,
+        request: lambda_http::Request,
+    ) -> Response<Body> {
+        function_handler(deps, request)
+            .await
+            .unwrap()
+            .into_response()
+            .await
+    }
+
+    #[tokio::test]
+    async fn when_preview_is_asked_for_should_show_the_destination_without_publishing() {
+        let short_url = ShortUrl {
+            title: Some("<script>alert(1)</script>".into()),
+            content_type: Some("text/html".into()),
+            ..ShortUrl::new("abc123".into(), "https://example.com/?a=1&b=2".into())
+        };
+        let deps = create_deps(short_url, 0);
+
+        for (link_id, uri) in [
+            ("abc123", "https://sho.rt/abc123?preview"),
+            ("abc123+", "https://sho.rt/abc123+"),
+        ] {
+            let response = send(&deps, create_request("GET", link_id, uri)).await;
+
+            assert_eq!(response.status(), 200, "{}", uri);
+            let Body::Text(html) = response.body() else {
+                panic!("expected an HTML body");
+            };
+            assert!(html.contains("<code>https://example.com/?a=1&amp;b=2</code>"));
+            assert!(html.contains("<dd>&lt;script&gt;alert(1)&lt;/script&gt;</dd>"));
+            assert!(html.contains("<dd>text/html</dd>"));
+            assert!(html.contains("<form method=\"post\">"));
+        }
+    }
+
+    #[tokio::test]
+    async fn when_link_is_interstitial_should_preview_and_follow_on_continue() {
+        let short_url = ShortUrl {
+            interstitial: true,
+            query_mode: QueryMode::Merge,
+            ..ShortUrl::new("abc123".into(), "https://example.com".into())
+        };
+        let deps = create_deps(short_url, 1);
+
+        let response = send(
+            &deps,
+            create_request("GET", "abc123", "https://sho.rt/abc123?ref=tw"),
+        )
+        .await;
+
+        assert_eq!(response.status(), 200);
+
+        let response = send(
+            &deps,
+            create_request("POST", "abc123", "https://sho.rt/abc123?ref=tw&preview"),
+        )
+        .await;
+
+        assert_eq!(response.status(), 302);
+        assert_eq!(
+            response.headers()["location"],
+            "https://example.com/?ref=tw"
+        );
+    }
+}
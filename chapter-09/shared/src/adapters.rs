@@ -1,14 +1,52 @@
 use crate::{
-    core::{ShortUrl, UrlRepository},
+    core::{
+        epoch_seconds, DestinationChange, LinkStatus, ListOptions, Page, PageKey, QueryMode,
+        RedirectRule, RedirectType, RepositoryError, ShortUrl, SortOrder, Target, UrlRepository,
+    },
+    normalise::url_hash,
+    password::LinkPassword,
+    rules::Device,
+    search::{index_terms, page_of, query_terms, rank, term_key},
+    tags::{tag_key, TagError, MAX_TAGS},
     url_info::UrlDetails,
 };
 use async_trait::async_trait;
-use aws_sdk_dynamodb::{types::AttributeValue, Client};
-use std::collections::HashMap;
+use aws_sdk_dynamodb::{
+    error::{ProvideErrorMetadata, SdkError},
+    operation::transact_write_items::TransactWriteItemsError,
+    types::{
+        AttributeValue, Delete, DeleteRequest, KeysAndAttributes, Put, PutRequest, ReturnValue,
+        TransactWriteItem, Update, WriteRequest,
+    },
+    Client,
+};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Debug;
+use std::ops::Bound::{Excluded, Unbounded};
+use std::sync::RwLock;
+use std::time::Duration;
+
+/// Sparse GSI on `OwnerId`, with `LinkId` as the sort key.
+const OWNER_INDEX: &str = "OwnerIndex";
+/// Sparse GSI on `UrlHash`, with `OwnerId` as the sort key.
+const URL_HASH_INDEX: &str = "UrlHashIndex";
+/// The most items a single BatchWriteItem call accepts.
+const BATCH_WRITE_SIZE: usize = 25;
+const MAX_BATCH_ATTEMPTS: u32 = 5;
+/// Doubled after every batch attempt that leaves unprocessed items.
+const BATCH_BASE_DELAY: Duration = Duration::from_millis(50);
+/// Deletes read a link's tags first, and start over when they change before the write.
+const MAX_DELETE_ATTEMPTS: usize = 3;
+/// Items created before statuses existed have no Status attribute
+const ACTIVE_FILTER_EXPRESSION: &str = "attribute_not_exists(#status) OR #status = :active";
 
 #[derive(Debug)]
 pub struct DynamoDbUrlRepository {
     table_name: String,
+    /// Holds one item per tagged link, keyed by `TagKey` (owner and tag) and `LinkId`.
+    tags_table_name: Option<String>,
+    /// Inverted index of titles and descriptions, keyed by `TermKey` (owner and term) and `LinkId`.
+    search_table_name: Option<String>,
     dynamodb_client: Client,
 }
 
@@ -16,15 +54,504 @@ impl DynamoDbUrlRepository {
     pub fn new(table_name: String, dynamodb_client: Client) -> Self {
         Self {
             table_name,
+            tags_table_name: None,
+            search_table_name: None,
             dynamodb_client,
         }
     }
+
+    /// Enables tagging and listing by tag, using `tags_table_name` as the tag index.
+    pub fn with_tags_table(mut self, tags_table_name: String) -> Self {
+        self.tags_table_name = Some(tags_table_name);
+        self
+    }
+
+    /// Enables search, with `search_table_name` holding the index that
+    /// `add_details_to_short_url` keeps up to date.
+    pub fn with_search_table(mut self, search_table_name: String) -> Self {
+        self.search_table_name = Some(search_table_name);
+        self
+    }
+
+    fn search_table(&self) -> Result<&str, RepositoryError> {
+        self.search_table_name
+            .as_deref()
+            .ok_or_else(|| RepositoryError::Fatal("No search table configured".to_string()))
+    }
+
+    fn tags_table(&self) -> Result<&str, RepositoryError> {
+        self.tags_table_name
+            .as_deref()
+            .ok_or_else(|| RepositoryError::Fatal("No tags table configured".to_string()))
+    }
+}
+
+impl DynamoDbUrlRepository {
+    /// Writes up to `BATCH_WRITE_SIZE` links, retrying the items DynamoDB
+    /// reports as unprocessed with exponential backoff.
+    async fn write_chunk(&self, chunk: Vec<ShortUrl>) -> Result<Vec<ShortUrl>, RepositoryError> {
+        let mut pending = chunk;
+        let mut written = Vec::with_capacity(pending.len());
+
+        for attempt in 0..MAX_BATCH_ATTEMPTS {
+            if attempt > 0 {
+                tokio::time::sleep(BATCH_BASE_DELAY * 2u32.pow(attempt - 1)).await;
+            }
+            let write_requests = pending
+                .iter()
+                .map(|short_url| {
+                    PutRequest::builder()
+                        .set_item(Some(HashMap::from(short_url)))
+                        .build()
+                        .map(|put_request| WriteRequest::builder().put_request(put_request).build())
+                        .map_err(|e| RepositoryError::Fatal(format!("Error building batch: {}", e)))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let result = self
+                .dynamodb_client
+                .batch_write_item()
+                .request_items(&self.table_name, write_requests)
+                .send()
+                .await;
+            let output = match result {
+                Ok(output) => output,
+                Err(e) => {
+                    let e = map_sdk_error("Error writing batch", e, RepositoryError::Fatal);
+                    if e.is_retryable() && attempt + 1 < MAX_BATCH_ATTEMPTS {
+                        tracing::warn!("Retrying batch write: {:?}", e);
+                        continue;
+                    }
+                    if written.is_empty() {
+                        return Err(e);
+                    }
+                    tracing::error!("Giving up on {} unwritten items: {:?}", pending.len(), e);
+                    return Ok(written);
+                }
+            };
+
+            let unprocessed: Vec<String> = output
+                .unprocessed_items
+                .and_then(|mut items| items.remove(&self.table_name))
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|write_request| write_request.put_request)
+                .filter_map(|put_request| match put_request.item.get("LinkId") {
+                    Some(AttributeValue::S(link_id)) => Some(link_id.clone()),
+                    _ => None,
+                })
+                .collect();
+            let (retry, done): (Vec<ShortUrl>, Vec<ShortUrl>) = pending
+                .into_iter()
+                .partition(|short_url| unprocessed.contains(&short_url.link_id));
+            written.extend(done);
+            pending = retry;
+            if pending.is_empty() {
+                break;
+            }
+        }
+
+        if !pending.is_empty() {
+            tracing::error!(
+                "{} items were still unprocessed after {} attempts",
+                pending.len(),
+                MAX_BATCH_ATTEMPTS
+            );
+        }
+        Ok(written)
+    }
+
+    /// Reads links by id, in the order of `link_ids`. Missing links are skipped.
+    async fn get_urls_by_ids(&self, link_ids: &[String]) -> Result<Vec<ShortUrl>, RepositoryError> {
+        let mut found: HashMap<String, ShortUrl> = HashMap::with_capacity(link_ids.len());
+        let mut pending: Vec<HashMap<String, AttributeValue>> = link_ids
+            .iter()
+            .map(|link_id| {
+                HashMap::from([("LinkId".to_string(), AttributeValue::S(link_id.clone()))])
+            })
+            .collect();
+
+        for attempt in 0..MAX_BATCH_ATTEMPTS {
+            if pending.is_empty() {
+                break;
+            }
+            if attempt > 0 {
+                tokio::time::sleep(BATCH_BASE_DELAY * 2u32.pow(attempt - 1)).await;
+            }
+            let keys = KeysAndAttributes::builder()
+                .set_keys(Some(pending))
+                .build()
+                .map_err(|e| RepositoryError::Fatal(format!("Error building batch: {}", e)))?;
+            let output = self
+                .dynamodb_client
+                .batch_get_item()
+                .request_items(&self.table_name, keys)
+                .send()
+                .await
+                .map_err(|e| map_sdk_error("Error reading batch", e, RepositoryError::Fatal))?;
+
+            for item in output
+                .responses
+                .and_then(|mut responses| responses.remove(&self.table_name))
+                .unwrap_or_default()
+            {
+                let short_url = ShortUrl::try_from(item).map_err(RepositoryError::Fatal)?;
+                found.insert(short_url.link_id.clone(), short_url);
+            }
+            pending = output
+                .unprocessed_keys
+                .and_then(|mut unprocessed| unprocessed.remove(&self.table_name))
+                .map(|keys| keys.keys)
+                .unwrap_or_default();
+        }
+
+        if !pending.is_empty() {
+            return Err(RepositoryError::Throttled(format!(
+                "Error reading batch: {} keys still unprocessed after {} attempts",
+                pending.len(),
+                MAX_BATCH_ATTEMPTS
+            )));
+        }
+        Ok(link_ids
+            .iter()
+            .filter_map(|link_id| found.remove(link_id))
+            .collect())
+    }
+
+    /// Writes and deletes items in chunks, retrying unprocessed ones with backoff.
+    async fn write_requests(
+        &self,
+        table_name: &str,
+        requests: Vec<WriteRequest>,
+    ) -> Result<(), RepositoryError> {
+        for chunk in requests.chunks(BATCH_WRITE_SIZE) {
+            let mut pending = chunk.to_vec();
+            for attempt in 0..MAX_BATCH_ATTEMPTS {
+                if pending.is_empty() {
+                    break;
+                }
+                if attempt > 0 {
+                    tokio::time::sleep(BATCH_BASE_DELAY * 2u32.pow(attempt - 1)).await;
+                }
+                let output = self
+                    .dynamodb_client
+                    .batch_write_item()
+                    .request_items(table_name, pending)
+                    .send()
+                    .await
+                    .map_err(|e| map_sdk_error("Error writing batch", e, RepositoryError::Fatal))?;
+                pending = output
+                    .unprocessed_items
+                    .and_then(|mut items| items.remove(table_name))
+                    .unwrap_or_default();
+            }
+            if !pending.is_empty() {
+                return Err(RepositoryError::Throttled(format!(
+                    "Error writing batch: {} items still unprocessed after {} attempts",
+                    pending.len(),
+                    MAX_BATCH_ATTEMPTS
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Brings the search index in line with a link's new details, given the
+    /// item as it was before the update.
+    ///
+    /// Every current term is written again, not only the changed ones, so a
+    /// retry after a failed index write repairs the index.
+    async fn reindex(
+        &self,
+        search_table: &str,
+        short_link: &str,
+        previous: &HashMap<String, AttributeValue>,
+        url_details: &UrlDetails,
+    ) -> Result<(), RepositoryError> {
+        let text = |name: &str| {
+            previous
+                .get(name)
+                .and_then(|value| value.as_s().ok())
+                .map(String::as_str)
+        };
+        // Links without an owner cannot be searched
+        let Some(owner_id) = text("OwnerId") else {
+            return Ok(());
+        };
+        let old_terms = index_terms(text("Title"), text("Description"));
+        let new_terms = index_terms(
+            url_details.title.as_deref(),
+            url_details.description.as_deref(),
+        );
+        let key = |term: &str| {
+            HashMap::from([
+                (
+                    "TermKey".to_string(),
+                    AttributeValue::S(term_key(owner_id, term)),
+                ),
+                (
+                    "LinkId".to_string(),
+                    AttributeValue::S(short_link.to_string()),
+                ),
+            ])
+        };
+
+        let mut requests = Vec::with_capacity(old_terms.len() + new_terms.len());
+        for term in old_terms
+            .keys()
+            .filter(|term| !new_terms.contains_key(*term))
+        {
+            let delete = DeleteRequest::builder()
+                .set_key(Some(key(term)))
+                .build()
+                .map_err(|e| RepositoryError::Fatal(format!("Error building delete: {}", e)))?;
+            requests.push(WriteRequest::builder().delete_request(delete).build());
+        }
+        for (term, weight) in &new_terms {
+            let mut item = key(term);
+            item.insert("Weight".to_string(), AttributeValue::N(weight.to_string()));
+            let put = PutRequest::builder()
+                .set_item(Some(item))
+                .build()
+                .map_err(|e| RepositoryError::Fatal(format!("Error building put: {}", e)))?;
+            requests.push(WriteRequest::builder().put_request(put).build());
+        }
+        self.write_requests(search_table, requests).await
+    }
+
+    /// Reads the `(link_id, weight)` postings of one of an owner's terms.
+    ///
+    /// Postings are sorted by link id, not by weight, and are ranked together
+    /// afterwards, so every page of them is read: the best match of a common
+    /// term can be on the last one.
+    async fn read_postings(
+        &self,
+        search_table: &str,
+        owner_id: &str,
+        term: &str,
+    ) -> Result<Vec<(String, u32)>, RepositoryError> {
+        let mut postings = Vec::new();
+        let mut start_key = None;
+        loop {
+            let result = self
+                .dynamodb_client
+                .query()
+                .table_name(search_table)
+                .key_condition_expression("TermKey = :term_key")
+                .expression_attribute_values(
+                    ":term_key",
+                    AttributeValue::S(term_key(owner_id, term)),
+                )
+                .projection_expression("LinkId, Weight")
+                .set_exclusive_start_key(start_key)
+                .send()
+                .await
+                .map_err(|e| map_sdk_error("Error executing query", e, RepositoryError::Fatal))?;
+
+            postings.extend(
+                result
+                    .items
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter_map(|item| {
+                        let link_id = item.get("LinkId")?.as_s().ok()?.to_string();
+                        let weight = item.get("Weight")?.as_n().ok()?.parse::<u32>().ok()?;
+                        Some((link_id, weight))
+                    }),
+            );
+            start_key = result.last_evaluated_key;
+            if start_key.is_none() {
+                return Ok(postings);
+            }
+        }
+    }
+
+    /// Reads a link that can still be tagged, i.e. one that exists, is not
+    /// deleted and belongs to `owner_id` when that is set.
+    async fn get_taggable_url(
+        &self,
+        short_link: &str,
+        owner_id: Option<&str>,
+        context: &str,
+    ) -> Result<ShortUrl, RepositoryError> {
+        match self.get_url_from_short_link(short_link).await? {
+            Some(current)
+                if current.status != LinkStatus::Deleted && current.belongs_to(owner_id) =>
+            {
+                Ok(current)
+            }
+            _ => Err(RepositoryError::NotFound(format!(
+                "{}: LinkId {} not found",
+                context, short_link
+            ))),
+        }
+    }
+
+    /// The update of a link's `Tags` set, as part of a transaction with the tag index.
+    fn tags_update(
+        &self,
+        short_link: &str,
+        owner_id: Option<&str>,
+        update_expression: &str,
+        tags: &[String],
+    ) -> Result<TransactWriteItem, RepositoryError> {
+        let mut update = Update::builder()
+            .table_name(&self.table_name)
+            .key("LinkId", AttributeValue::S(short_link.to_string()))
+            .update_expression(update_expression)
+            .condition_expression(owner_condition(
+                "attribute_exists(LinkId) AND (attribute_not_exists(#status) OR #status <> :deleted)",
+                owner_id,
+            ))
+            .expression_attribute_names("#status", "Status")
+            .expression_attribute_values(":tags", AttributeValue::Ss(tags.to_vec()))
+            .expression_attribute_values(
+                ":deleted",
+                AttributeValue::S(LinkStatus::Deleted.as_str().to_string()),
+            );
+        if let Some(owner_id) = owner_id {
+            update =
+                update.expression_attribute_values(":owner", AttributeValue::S(owner_id.into()));
+        }
+        let update = update
+            .build()
+            .map_err(|e| RepositoryError::Fatal(format!("Error building update: {}", e)))?;
+        Ok(TransactWriteItem::builder().update(update).build())
+    }
+
+    /// Marks a link deleted and removes its rows from the tag index in the same
+    /// transaction, so listings by tag do not come back short. The transaction
+    /// only goes through while the link has the tags that were read.
+    async fn delete_url(
+        &self,
+        short_link: &str,
+        owner_id: Option<&str>,
+    ) -> Result<ShortUrl, RepositoryError> {
+        for _ in 0..MAX_DELETE_ATTEMPTS {
+            let current = self
+                .get_taggable_url(short_link, owner_id, "Error deleting link")
+                .await?;
+            // A set that loses its last element is removed altogether
+            let tags_condition = if current.tags.is_empty() {
+                "attribute_not_exists(Tags)"
+            } else {
+                "Tags = :tags"
+            };
+            let mut update = Update::builder()
+                .table_name(&self.table_name)
+                .key("LinkId", AttributeValue::S(short_link.to_string()))
+                .update_expression("SET #status = :deleted")
+                .condition_expression(owner_condition(
+                    &format!(
+                        "attribute_exists(LinkId) AND (attribute_not_exists(#status) OR #status <> :deleted) AND {}",
+                        tags_condition
+                    ),
+                    owner_id,
+                ))
+                .expression_attribute_names("#status", "Status")
+                .expression_attribute_values(
+                    ":deleted",
+                    AttributeValue::S(LinkStatus::Deleted.as_str().to_string()),
+                );
+            if !current.tags.is_empty() {
+                update = update
+                    .expression_attribute_values(":tags", AttributeValue::Ss(current.tags.clone()));
+            }
+            if let Some(owner_id) = owner_id {
+                update = update
+                    .expression_attribute_values(":owner", AttributeValue::S(owner_id.into()));
+            }
+            let update = update
+                .build()
+                .map_err(|e| RepositoryError::Fatal(format!("Error building update: {}", e)))?;
+
+            let mut transaction = vec![TransactWriteItem::builder().update(update).build()];
+            let link_owner_id = current.owner_id.as_deref().unwrap_or_default();
+            for tag in &current.tags {
+                let delete = Delete::builder()
+                    .table_name(self.tags_table()?)
+                    .key("TagKey", AttributeValue::S(tag_key(link_owner_id, tag)))
+                    .key("LinkId", AttributeValue::S(short_link.to_string()))
+                    .build()
+                    .map_err(|e| RepositoryError::Fatal(format!("Error building delete: {}", e)))?;
+                transaction.push(TransactWriteItem::builder().delete(delete).build());
+            }
+            let result = self
+                .dynamodb_client
+                .transact_write_items()
+                .set_transact_items(Some(transaction))
+                .send()
+                .await
+                .map_err(|e| {
+                    map_transaction_error("Error deleting link", e, RepositoryError::Conflict)
+                });
+            match result {
+                Ok(_) => {
+                    return Ok(ShortUrl {
+                        status: LinkStatus::Deleted,
+                        ..current
+                    })
+                }
+                // Read the link again, it may have been tagged or deleted in between
+                Err(RepositoryError::Conflict(_)) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Err(RepositoryError::Transient(format!(
+            "Error deleting link: tags of LinkId {} kept changing",
+            short_link
+        )))
+    }
+
+    /// Lists a page of one owner's links from the tag index, then reads the links.
+    async fn list_tagged_urls(
+        &self,
+        owner_id: &str,
+        tag: &str,
+        options: ListOptions,
+    ) -> Result<Page, RepositoryError> {
+        let result = self
+            .dynamodb_client
+            .query()
+            .table_name(self.tags_table()?)
+            .key_condition_expression("TagKey = :tag_key")
+            .expression_attribute_values(":tag_key", AttributeValue::S(tag_key(owner_id, tag)))
+            .scan_index_forward(options.sort == SortOrder::Ascending)
+            .limit(options.limit as i32)
+            .set_exclusive_start_key(options.start_key.map(to_key_attributes))
+            .send()
+            .await
+            .map_err(|e| map_sdk_error("Error executing query", e, RepositoryError::Fatal))?;
+
+        let link_ids: Vec<String> = result
+            .items
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|item| item.get("LinkId").and_then(|id| id.as_s().ok()).cloned())
+            .collect();
+        let short_urls = if link_ids.is_empty() {
+            Vec::new()
+        } else {
+            self.get_urls_by_ids(&link_ids).await?
+        };
+
+        Ok(Page {
+            short_urls: short_urls
+                .into_iter()
+                .filter(|short_url| options.include_inactive || short_url.status.is_active())
+                .collect(),
+            next_key: result.last_evaluated_key.map(to_page_key),
+        })
+    }
 }
 
 #[async_trait]
 impl UrlRepository for DynamoDbUrlRepository {
     #[tracing::instrument(skip(self, short_link))]
-    async fn get_url_from_short_link(&self, short_link: &str) -> Result<Option<ShortUrl>, String> {
+    async fn get_url_from_short_link(
+        &self,
+        short_link: &str,
+    ) -> Result<Option<ShortUrl>, RepositoryError> {
         let result = self
             .dynamodb_client
             .get_item()
@@ -32,45 +559,61 @@ impl UrlRepository for DynamoDbUrlRepository {
             .key("LinkId", AttributeValue::S(short_link.to_string()))
             .send()
             .await
-            .map_err(|e| format!("Error getting item: {:?}", e))?;
+            .map_err(|e| map_sdk_error("Error getting item", e, RepositoryError::Fatal))?;
 
         match result.item {
             Some(item) => {
-                let short_url = ShortUrl::try_from(item)?;
+                let short_url = ShortUrl::try_from(item).map_err(RepositoryError::Fatal)?;
                 Ok(Some(short_url))
             }
             None => Ok(None),
         }
     }
 
-    #[tracing::instrument(skip(self, url_to_shorten, short_url))]
-    async fn store_short_url(
-        &self,
-        url_to_shorten: String,
-        short_url: String,
-    ) -> Result<ShortUrl, String> {
-        let put_item = self
-            .dynamodb_client
+    #[tracing::instrument(skip(self, short_url))]
+    async fn store_short_url(&self, short_url: ShortUrl) -> Result<ShortUrl, RepositoryError> {
+        self.dynamodb_client
             .put_item()
             .table_name(&self.table_name)
-            .item("LinkId", AttributeValue::S(short_url.clone()))
-            .item("OriginalLink", AttributeValue::S(url_to_shorten.clone()))
-            .item("Clicks", AttributeValue::N("0".to_string()));
-
-        put_item
+            .set_item(Some(HashMap::from(&short_url)))
             .condition_expression("attribute_not_exists(LinkId)")
             .send()
             .await
-            .map(|_| ShortUrl::new(short_url, url_to_shorten))
-            .map_err(|e| format!("Error adding item: {:?}", e))
+            .map(|_| short_url)
+            .map_err(|e| map_sdk_error("Error adding item", e, RepositoryError::AlreadyExists))
+    }
+
+    #[tracing::instrument(skip(self, short_urls))]
+    async fn store_short_urls(
+        &self,
+        short_urls: Vec<ShortUrl>,
+    ) -> Result<Vec<ShortUrl>, RepositoryError> {
+        let mut written = Vec::with_capacity(short_urls.len());
+        let mut last_error = None;
+
+        for chunk in short_urls.chunks(BATCH_WRITE_SIZE) {
+            match self.write_chunk(chunk.to_vec()).await {
+                Ok(chunk_written) => written.extend(chunk_written),
+                Err(e) => {
+                    tracing::error!("Failed to write batch of {} items: {:?}", chunk.len(), e);
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        match last_error {
+            Some(e) if written.is_empty() => Err(e),
+            _ => Ok(written),
+        }
     }
 
-    #[tracing::instrument(skip(self, short_link, url_details))]
+    #[tracing::instrument(skip(self, short_link, original_link, url_details))]
     async fn add_details_to_short_url(
         &self,
         short_link: String,
+        original_link: &str,
         url_details: UrlDetails,
-    ) -> Result<(), String> {
+    ) -> Result<(), RepositoryError> {
         let mut update_item = self
             .dynamodb_client
             .update_item()
@@ -78,45 +621,69 @@ impl UrlRepository for DynamoDbUrlRepository {
             .key("LinkId", AttributeValue::S(short_link.to_string()));
 
         let mut set_clauses: Vec<&str> = Vec::new();
+        let mut remove_clauses: Vec<&str> = Vec::new();
 
-        if let Some(ref title) = url_details.title {
-            set_clauses.push("Title = :title");
-            update_item =
-                update_item.expression_attribute_values(":title", AttributeValue::S(title.clone()));
-        }
-        if let Some(ref description) = url_details.description {
-            set_clauses.push("Description = :description");
-            update_item = update_item.expression_attribute_values(
+        for (attribute, clause, placeholder, value) in [
+            ("Title", "Title = :title", ":title", &url_details.title),
+            (
+                "Description",
+                "Description = :description",
                 ":description",
-                AttributeValue::S(description.clone()),
-            );
-        }
-        if let Some(ref content_type) = url_details.content_type {
-            set_clauses.push("ContentType = :content_type");
-            update_item = update_item.expression_attribute_values(
+                &url_details.description,
+            ),
+            (
+                "ContentType",
+                "ContentType = :content_type",
                 ":content_type",
-                AttributeValue::S(content_type.clone()),
-            );
+                &url_details.content_type,
+            ),
+        ] {
+            match value {
+                Some(value) => {
+                    set_clauses.push(clause);
+                    update_item = update_item
+                        .expression_attribute_values(placeholder, AttributeValue::S(value.clone()));
+                }
+                // Left from a previous scrape, the page no longer has it
+                None => remove_clauses.push(attribute),
+            }
         }
 
-        if set_clauses.is_empty() {
-            return Ok(());
+        let mut update_expression = Vec::new();
+        if !set_clauses.is_empty() {
+            update_expression.push(format!("SET {}", set_clauses.join(", ")));
         }
-
-        let update_expression = format!("SET {}", set_clauses.join(", "));
+        if !remove_clauses.is_empty() {
+            update_expression.push(format!("REMOVE {}", remove_clauses.join(", ")));
+        }
+        // A slow scrape of the previous destination must not overwrite the details
+        // of the current one. Links that are gone fail the condition too.
         update_item = update_item
-            .update_expression(update_expression)
-            .condition_expression("attribute_exists(LinkId)");
+            .update_expression(update_expression.join(" "))
+            .condition_expression("OriginalLink = :original_link")
+            .expression_attribute_values(
+                ":original_link",
+                AttributeValue::S(original_link.to_string()),
+            )
+            .return_values(ReturnValue::AllOld);
 
-        update_item
+        let result = update_item
             .send()
             .await
-            .map(|_| ())
-            .map_err(|e| format!("Error updating item: {:?}", e))
+            .map_err(|e| map_sdk_error("Error updating item", e, RepositoryError::Conflict))?;
+
+        match &self.search_table_name {
+            Some(search_table) => {
+                let previous = result.attributes.unwrap_or_default();
+                self.reindex(search_table, &short_link, &previous, &url_details)
+                    .await
+            }
+            None => Ok(()),
+        }
     }
 
     #[tracing::instrument(skip(self, short_link, n))]
-    async fn increment_clicks(&self, short_link: &str, n: u64) -> Result<(), String> {
+    async fn increment_clicks(&self, short_link: &str, n: u64) -> Result<(), RepositoryError> {
         self.dynamodb_client
             .update_item()
             .table_name(&self.table_name)
@@ -128,42 +695,586 @@ impl UrlRepository for DynamoDbUrlRepository {
             .send()
             .await
             .map(|_| ())
-            .map_err(|e| format!("Error incrementing clicks: {:?}", e))
+            .map_err(|e| map_sdk_error("Error incrementing clicks", e, RepositoryError::NotFound))
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn increment_target_clicks(
+        &self,
+        short_link: &str,
+        variant: usize,
+        n: u64,
+    ) -> Result<(), RepositoryError> {
+        // List indexes cannot be expression values, they go in the path itself
+        let clicks = format!("Targets[{}].Clicks", variant);
+        self.dynamodb_client
+            .update_item()
+            .table_name(&self.table_name)
+            .key("LinkId", AttributeValue::S(short_link.to_string()))
+            .update_expression(format!(
+                "SET {} = if_not_exists({}, :zero) + :val",
+                clicks, clicks
+            ))
+            .expression_attribute_values(":val", AttributeValue::N(n.to_string()))
+            .expression_attribute_values(":zero", AttributeValue::N("0".to_string()))
+            .condition_expression(format!("attribute_exists(Targets[{}])", variant))
+            .send()
+            .await
+            .map(|_| ())
+            .map_err(|e| {
+                map_sdk_error(
+                    "Error incrementing target clicks",
+                    e,
+                    RepositoryError::NotFound,
+                )
+            })
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn record_password_failure(
+        &self,
+        short_link: &str,
+        window: u64,
+    ) -> Result<u32, RepositoryError> {
+        let key = AttributeValue::S(short_link.to_string());
+        let window = AttributeValue::N(window.to_string());
+        // A counter that resets needs two conditional updates: count within the
+        // window, or start the window over. Either can lose a race against
+        // another visitor, so each gets a second try.
+        for _ in 0..2 {
+            let counted = self
+                .dynamodb_client
+                .update_item()
+                .table_name(&self.table_name)
+                .key("LinkId", key.clone())
+                .update_expression("ADD PasswordFailures :one")
+                .condition_expression("PasswordFailureWindow = :window")
+                .expression_attribute_values(":one", AttributeValue::N("1".to_string()))
+                .expression_attribute_values(":window", window.clone())
+                .return_values(ReturnValue::UpdatedNew)
+                .send()
+                .await
+                .map_err(|e| {
+                    map_sdk_error(
+                        "Error counting password failure",
+                        e,
+                        RepositoryError::Conflict,
+                    )
+                });
+            match counted {
+                Ok(output) => {
+                    return Ok(output
+                        .attributes
+                        .as_ref()
+                        .and_then(|attributes| attributes.get("PasswordFailures"))
+                        .and_then(|n| n.as_n().ok())
+                        .and_then(|n| n.parse::<u32>().ok())
+                        .unwrap_or(1))
+                }
+                Err(RepositoryError::Conflict(_)) => {}
+                Err(e) => return Err(e),
+            }
+
+            let restarted = self
+                .dynamodb_client
+                .update_item()
+                .table_name(&self.table_name)
+                .key("LinkId", key.clone())
+                .update_expression("SET PasswordFailures = :one, PasswordFailureWindow = :window")
+                .condition_expression(
+                    "attribute_exists(PasswordHash) AND \
+                     (attribute_not_exists(PasswordFailureWindow) OR PasswordFailureWindow < :window)",
+                )
+                .expression_attribute_values(":one", AttributeValue::N("1".to_string()))
+                .expression_attribute_values(":window", window.clone())
+                .send()
+                .await
+                .map_err(|e| {
+                    map_sdk_error("Error counting password failure", e, RepositoryError::Conflict)
+                });
+            match restarted {
+                Ok(_) => return Ok(1),
+                Err(RepositoryError::Conflict(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Err(RepositoryError::NotFound(format!(
+            "Error counting password failure: LinkId {} not found or not protected",
+            short_link
+        )))
+    }
+
+    #[tracing::instrument(skip(self, short_link, original_link))]
+    async fn update_destination(
+        &self,
+        short_link: &str,
+        owner_id: Option<String>,
+        original_link: String,
+        expected_version: u64,
+    ) -> Result<ShortUrl, RepositoryError> {
+        // The previous destination goes into the history, and an update expression
+        // cannot append an attribute's own value to a list, so read it first.
+        // The version condition below makes sure it has not changed since.
+        let current = match self.get_url_from_short_link(short_link).await? {
+            Some(current)
+                if current.status != LinkStatus::Deleted
+                    && current.belongs_to(owner_id.as_deref()) =>
+            {
+                current
+            }
+            _ => {
+                return Err(RepositoryError::NotFound(format!(
+                    "Error updating destination: LinkId {} not found",
+                    short_link
+                )))
+            }
+        };
+        if current.version != expected_version {
+            return Err(stale_version_error(short_link, expected_version));
+        }
+        let previous = DestinationChange {
+            original_link: current.original_link,
+            replaced_at: epoch_seconds(),
+        };
+        // The lookup hash follows the destination, unless it is no longer a valid URL
+        let (hash_clause, url_hash) = match url_hash(&original_link) {
+            Ok(url_hash) => (", UrlHash = :url_hash", Some(url_hash)),
+            Err(_) => (" REMOVE UrlHash", None),
+        };
+        // Links created before versioning have no Version attribute yet
+        let condition_expression = if expected_version == 0 {
+            "attribute_not_exists(Version) OR Version = :expected"
+        } else {
+            "Version = :expected"
+        };
+
+        let mut update_item = self
+            .dynamodb_client
+            .update_item()
+            .table_name(&self.table_name)
+            .key("LinkId", AttributeValue::S(short_link.to_string()))
+            .update_expression(format!(
+                "SET OriginalLink = :link, Version = :next, History = list_append(if_not_exists(History, :empty), :previous){}",
+                hash_clause
+            ))
+            .condition_expression(owner_condition(condition_expression, owner_id.as_deref()))
+            .expression_attribute_values(":link", AttributeValue::S(original_link))
+            .expression_attribute_values(":expected", AttributeValue::N(expected_version.to_string()))
+            .expression_attribute_values(
+                ":next",
+                AttributeValue::N((expected_version + 1).to_string()),
+            )
+            .expression_attribute_values(":empty", AttributeValue::L(vec![]))
+            .expression_attribute_values(
+                ":previous",
+                AttributeValue::L(vec![AttributeValue::from(&previous)]),
+            )
+            .return_values(ReturnValue::AllNew);
+        if let Some(url_hash) = url_hash {
+            update_item =
+                update_item.expression_attribute_values(":url_hash", AttributeValue::S(url_hash));
+        }
+        if let Some(owner_id) = owner_id {
+            update_item =
+                update_item.expression_attribute_values(":owner", AttributeValue::S(owner_id));
+        }
+
+        let result = update_item.send().await.map_err(|e| {
+            map_sdk_error("Error updating destination", e, RepositoryError::Conflict)
+        })?;
+
+        ShortUrl::try_from(result.attributes.unwrap_or_default()).map_err(RepositoryError::Fatal)
+    }
+
+    #[tracing::instrument(skip(self, short_link))]
+    async fn set_link_status(
+        &self,
+        short_link: &str,
+        owner_id: Option<String>,
+        status: LinkStatus,
+    ) -> Result<ShortUrl, RepositoryError> {
+        if status == LinkStatus::Deleted {
+            return self.delete_url(short_link, owner_id.as_deref()).await;
+        }
+        let mut update_item = self
+            .dynamodb_client
+            .update_item()
+            .table_name(&self.table_name)
+            .key("LinkId", AttributeValue::S(short_link.to_string()))
+            .update_expression("SET #status = :status")
+            .condition_expression(owner_condition(
+                "attribute_exists(LinkId) AND (attribute_not_exists(#status) OR #status <> :deleted)",
+                owner_id.as_deref(),
+            ))
+            .expression_attribute_names("#status", "Status")
+            .expression_attribute_values(":status", AttributeValue::S(status.as_str().to_string()))
+            .expression_attribute_values(
+                ":deleted",
+                AttributeValue::S(LinkStatus::Deleted.as_str().to_string()),
+            )
+            .return_values(ReturnValue::AllNew);
+        if let Some(owner_id) = owner_id {
+            update_item =
+                update_item.expression_attribute_values(":owner", AttributeValue::S(owner_id));
+        }
+        let result = update_item
+            .send()
+            .await
+            .map_err(|e| map_sdk_error("Error updating status", e, RepositoryError::NotFound))?;
+
+        ShortUrl::try_from(result.attributes.unwrap_or_default()).map_err(RepositoryError::Fatal)
+    }
+
+    #[tracing::instrument(skip(self, tags))]
+    async fn add_tags(
+        &self,
+        short_link: &str,
+        owner_id: Option<String>,
+        tags: Vec<String>,
+    ) -> Result<ShortUrl, RepositoryError> {
+        let current = self
+            .get_taggable_url(short_link, owner_id.as_deref(), "Error adding tags")
+            .await?;
+        let tags_table = self.tags_table()?;
+        if tags.is_empty() {
+            return Ok(current);
+        }
+        let tagged: BTreeSet<String> = current.tags.iter().chain(&tags).cloned().collect();
+        if tagged.len() > MAX_TAGS {
+            return Err(RepositoryError::Validation(TagError::TooMany.to_string()));
+        }
+        // The link and its tag index entries change together, or not at all
+        let mut transaction =
+            vec![self.tags_update(short_link, owner_id.as_deref(), "ADD Tags :tags", &tags)?];
+        for tag in &tags {
+            let put = Put::builder()
+                .table_name(tags_table)
+                .set_item(Some(tag_item(&current, tag)))
+                .build()
+                .map_err(|e| RepositoryError::Fatal(format!("Error building put: {}", e)))?;
+            transaction.push(TransactWriteItem::builder().put(put).build());
+        }
+        self.dynamodb_client
+            .transact_write_items()
+            .set_transact_items(Some(transaction))
+            .send()
+            .await
+            .map_err(|e| {
+                map_transaction_error("Error adding tags", e, RepositoryError::NotFound)
+            })?;
+
+        Ok(ShortUrl {
+            tags: tagged.into_iter().collect(),
+            ..current
+        })
+    }
+
+    #[tracing::instrument(skip(self, tags))]
+    async fn remove_tags(
+        &self,
+        short_link: &str,
+        owner_id: Option<String>,
+        tags: Vec<String>,
+    ) -> Result<ShortUrl, RepositoryError> {
+        let current = self
+            .get_taggable_url(short_link, owner_id.as_deref(), "Error removing tags")
+            .await?;
+        let tags_table = self.tags_table()?;
+        if tags.is_empty() {
+            return Ok(current);
+        }
+        let mut transaction =
+            vec![self.tags_update(short_link, owner_id.as_deref(), "DELETE Tags :tags", &tags)?];
+        let owner_id = current.owner_id.as_deref().unwrap_or_default();
+        for tag in &tags {
+            let delete = Delete::builder()
+                .table_name(tags_table)
+                .key("TagKey", AttributeValue::S(tag_key(owner_id, tag)))
+                .key("LinkId", AttributeValue::S(short_link.to_string()))
+                .build()
+                .map_err(|e| RepositoryError::Fatal(format!("Error building delete: {}", e)))?;
+            transaction.push(TransactWriteItem::builder().delete(delete).build());
+        }
+        self.dynamodb_client
+            .transact_write_items()
+            .set_transact_items(Some(transaction))
+            .send()
+            .await
+            .map_err(|e| {
+                map_transaction_error("Error removing tags", e, RepositoryError::NotFound)
+            })?;
+
+        Ok(ShortUrl {
+            tags: current
+                .tags
+                .into_iter()
+                .filter(|tag| !tags.contains(tag))
+                .collect(),
+            ..current
+        })
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn find_urls_by_hash(
+        &self,
+        owner_id: &str,
+        url_hash: &str,
+    ) -> Result<Vec<ShortUrl>, RepositoryError> {
+        let result = self
+            .dynamodb_client
+            .query()
+            .table_name(&self.table_name)
+            .index_name(URL_HASH_INDEX)
+            .key_condition_expression("UrlHash = :url_hash AND OwnerId = :owner_id")
+            .filter_expression(ACTIVE_FILTER_EXPRESSION)
+            .expression_attribute_names("#status", "Status")
+            .expression_attribute_values(":url_hash", AttributeValue::S(url_hash.to_string()))
+            .expression_attribute_values(":owner_id", AttributeValue::S(owner_id.to_string()))
+            .expression_attribute_values(
+                ":active",
+                AttributeValue::S(LinkStatus::Active.as_str().to_string()),
+            )
+            .send()
+            .await
+            .map_err(|e| map_sdk_error("Error executing query", e, RepositoryError::Fatal))?;
+
+        Ok(into_page(result.items, None).short_urls)
     }
 
-    #[tracing::instrument(skip(self, last_evaluated_id))]
-    async fn list_urls(
+    #[tracing::instrument(skip(self))]
+    async fn search_urls(
         &self,
-        last_evaluated_id: Option<String>,
-    ) -> Result<(Vec<ShortUrl>, Option<String>), String> {
+        owner_id: &str,
+        query: &str,
+        options: ListOptions,
+    ) -> Result<Page, RepositoryError> {
+        let search_table = self.search_table()?;
+        let mut postings = HashMap::new();
+        for term in query_terms(query) {
+            let links = self.read_postings(search_table, owner_id, &term).await?;
+            postings.insert(term, links);
+        }
+
+        let (link_ids, next_key) =
+            page_of(rank(&postings), options.start_key.as_ref(), options.limit);
+        let short_urls = if link_ids.is_empty() {
+            Vec::new()
+        } else {
+            self.get_urls_by_ids(&link_ids).await?
+        };
+
+        Ok(Page {
+            short_urls: short_urls
+                .into_iter()
+                .filter(|short_url| options.include_inactive || short_url.status.is_active())
+                .collect(),
+            next_key,
+        })
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn list_urls(&self, options: ListOptions) -> Result<Page, RepositoryError> {
+        // A scan has no order, so `options.sort` does not apply
         let mut scan = self
             .dynamodb_client
             .scan()
             .table_name(&self.table_name)
-            .limit(50);
-        if let Some(last_evaluated_id) = last_evaluated_id {
+            .limit(options.limit as i32)
+            .set_exclusive_start_key(options.start_key.map(to_key_attributes));
+        // Without an owner there is no tag index partition to read, so tags filter the scan
+        let mut filters = Vec::new();
+        if !options.include_inactive {
+            filters.push(format!("({})", ACTIVE_FILTER_EXPRESSION));
             scan = scan
-                .exclusive_start_key("LinkId", AttributeValue::S(last_evaluated_id.to_string()));
+                .expression_attribute_names("#status", "Status")
+                .expression_attribute_values(
+                    ":active",
+                    AttributeValue::S(LinkStatus::Active.as_str().to_string()),
+                );
+        }
+        if let Some(tag) = options.tag {
+            filters.push("contains(Tags, :tag)".to_string());
+            scan = scan.expression_attribute_values(":tag", AttributeValue::S(tag));
+        }
+        if !filters.is_empty() {
+            scan = scan.filter_expression(filters.join(" AND "));
         }
         let result = scan
             .send()
             .await
-            .map_err(|e| format!("Error executing scan: {:?}", e))?;
+            .map_err(|e| map_sdk_error("Error executing scan", e, RepositoryError::Fatal))?;
 
-        let short_urls: Vec<ShortUrl> = result
-            .items
-            .unwrap_or_default()
-            .into_iter()
-            .filter_map(|item| ShortUrl::try_from(item).ok())
-            .collect();
+        Ok(into_page(result.items, result.last_evaluated_key))
+    }
 
-        let last_evaluated_id = result
-            .last_evaluated_key
-            .unwrap_or_default()
-            .get("LinkId")
-            .and_then(|s| s.as_s().ok().map(|v| v.to_string()));
+    #[tracing::instrument(skip(self))]
+    async fn list_urls_for_owner(
+        &self,
+        owner_id: &str,
+        options: ListOptions,
+    ) -> Result<Page, RepositoryError> {
+        if let Some(tag) = options.tag.clone() {
+            return self.list_tagged_urls(owner_id, &tag, options).await;
+        }
+        let mut query = self
+            .dynamodb_client
+            .query()
+            .table_name(&self.table_name)
+            .index_name(OWNER_INDEX)
+            .key_condition_expression("OwnerId = :owner_id")
+            .expression_attribute_values(":owner_id", AttributeValue::S(owner_id.to_string()))
+            .scan_index_forward(options.sort == SortOrder::Ascending)
+            .limit(options.limit as i32)
+            .set_exclusive_start_key(options.start_key.map(to_key_attributes));
+        if !options.include_inactive {
+            query = query
+                .filter_expression(ACTIVE_FILTER_EXPRESSION)
+                .expression_attribute_names("#status", "Status")
+                .expression_attribute_values(
+                    ":active",
+                    AttributeValue::S(LinkStatus::Active.as_str().to_string()),
+                );
+        }
+        let result = query
+            .send()
+            .await
+            .map_err(|e| map_sdk_error("Error executing query", e, RepositoryError::Fatal))?;
+
+        Ok(into_page(result.items, result.last_evaluated_key))
+    }
+}
 
-        Ok((short_urls, last_evaluated_id))
+/// Turns the items and `LastEvaluatedKey` of a scan or query into a page of links.
+fn into_page(
+    items: Option<Vec<HashMap<String, AttributeValue>>>,
+    last_evaluated_key: Option<HashMap<String, AttributeValue>>,
+) -> Page {
+    let short_urls: Vec<ShortUrl> = items
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|item| ShortUrl::try_from(item).ok())
+        .collect();
+
+    Page {
+        short_urls,
+        next_key: last_evaluated_key.map(to_page_key),
+    }
+}
+
+/// Table and index keys are all strings, so nothing is lost here.
+fn to_page_key(key: HashMap<String, AttributeValue>) -> PageKey {
+    key.into_iter()
+        .filter_map(|(name, value)| value.as_s().ok().map(|value| (name, value.to_string())))
+        .collect()
+}
+
+fn to_key_attributes(key: PageKey) -> HashMap<String, AttributeValue> {
+    key.into_iter()
+        .map(|(name, value)| (name, AttributeValue::S(value)))
+        .collect()
+}
+
+/// The tag index item of `short_url` for `tag`. It expires with the link, so
+/// DynamoDB removes both once the link's TTL passes.
+fn tag_item(short_url: &ShortUrl, tag: &str) -> HashMap<String, AttributeValue> {
+    let owner_id = short_url.owner_id.as_deref().unwrap_or_default();
+    let mut item = HashMap::from([
+        (
+            "TagKey".to_string(),
+            AttributeValue::S(tag_key(owner_id, tag)),
+        ),
+        (
+            "LinkId".to_string(),
+            AttributeValue::S(short_url.link_id.clone()),
+        ),
+        ("Tag".to_string(), AttributeValue::S(tag.to_string())),
+        (
+            "OwnerId".to_string(),
+            AttributeValue::S(owner_id.to_string()),
+        ),
+    ]);
+    if let Some(expires_at) = short_url.expires_at {
+        item.insert(
+            "ExpiresAt".to_string(),
+            AttributeValue::N(expires_at.to_string()),
+        );
+    }
+    item
+}
+
+/// Narrows the condition of an update to links of `owner_id`, checked against
+/// `:owner`, so the owner cannot change between reading a link and updating it.
+fn owner_condition(condition: &str, owner_id: Option<&str>) -> String {
+    match owner_id {
+        Some(_) => format!("({}) AND OwnerId = :owner", condition),
+        None => condition.to_string(),
+    }
+}
+
+/// Maps a DynamoDB SDK error into a `RepositoryError`.
+///
+/// A failed condition expression means different things depending on the
+/// operation (an id collision on create, a missing item on update), so the
+/// caller decides which variant it becomes.
+pub(crate) fn map_sdk_error<E, R>(
+    context: &str,
+    error: SdkError<E, R>,
+    on_condition_failed: fn(String) -> RepositoryError,
+) -> RepositoryError
+where
+    E: ProvideErrorMetadata + Debug,
+    R: Debug,
+{
+    let message = format!("{}: {:?}", context, error);
+    match &error {
+        SdkError::TimeoutError(_) | SdkError::DispatchFailure(_) | SdkError::ResponseError(_) => {
+            RepositoryError::Transient(message)
+        }
+        SdkError::ServiceError(service_error) => match service_error.err().code() {
+            Some("ConditionalCheckFailedException") => on_condition_failed(message),
+            Some(
+                "ProvisionedThroughputExceededException"
+                | "RequestLimitExceeded"
+                | "ThrottlingException",
+            ) => RepositoryError::Throttled(message),
+            // Our request to DynamoDB was malformed, which is our bug, not the client's
+            Some("ValidationException") => RepositoryError::Fatal(message),
+            Some("InternalServerError" | "ServiceUnavailable" | "TransactionConflictException") => {
+                RepositoryError::Transient(message)
+            }
+            _ => RepositoryError::Fatal(message),
+        },
+        _ => RepositoryError::Fatal(message),
+    }
+}
+
+/// Like `map_sdk_error`, for transactions: their condition failures and
+/// conflicts come back as the reasons of a cancelled transaction.
+fn map_transaction_error<R: Debug>(
+    context: &str,
+    error: SdkError<TransactWriteItemsError, R>,
+    on_condition_failed: fn(String) -> RepositoryError,
+) -> RepositoryError {
+    let reasons: Vec<String> = error
+        .as_service_error()
+        .and_then(|e| match e {
+            TransactWriteItemsError::TransactionCanceledException(e) => Some(e),
+            _ => None,
+        })
+        .map(|e| {
+            e.cancellation_reasons()
+                .iter()
+                .filter_map(|reason| reason.code().map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default();
+    let message = format!("{}: {:?}", context, error);
+    if reasons.iter().any(|code| code == "ConditionalCheckFailed") {
+        on_condition_failed(message)
+    } else if reasons.iter().any(|code| code == "TransactionConflict") {
+        RepositoryError::Transient(message)
+    } else {
+        map_sdk_error(context, error, on_condition_failed)
     }
 }
 
@@ -201,14 +1312,1645 @@ impl TryFrom<HashMap<String, AttributeValue>> for ShortUrl {
         let description = item
             .get("Description")
             .and_then(|c| c.as_s().map(|s| s.to_string()).ok());
+        let expires_at = item
+            .get("ExpiresAt")
+            .and_then(|n| n.as_n().ok())
+            .and_then(|n| n.parse::<u64>().ok());
+        let max_clicks = item
+            .get("MaxClicks")
+            .and_then(|n| n.as_n().ok())
+            .and_then(|n| n.parse::<u32>().ok());
+        let status = match item.get("Status") {
+            Some(status) => status
+                .as_s()
+                .map_err(|_| "Status is not a String".to_string())?
+                .parse::<LinkStatus>()?,
+            None => LinkStatus::Active,
+        };
+        let version = item
+            .get("Version")
+            .and_then(|n| n.as_n().ok())
+            .and_then(|n| n.parse::<u64>().ok())
+            .unwrap_or_default();
+        let history = match item.get("History") {
+            Some(history) => history
+                .as_l()
+                .map_err(|_| "History is not a List".to_string())?
+                .iter()
+                .map(DestinationChange::try_from)
+                .collect::<Result<Vec<_>, _>>()?,
+            None => Vec::new(),
+        };
 
-        Ok(ShortUrl::with_details(
-            link_id,
-            original_link,
-            clicks,
-            title,
-            description,
-            content_type,
-        ))
+        let owner_id = item
+            .get("OwnerId")
+            .and_then(|s| s.as_s().map(|s| s.to_string()).ok());
+        let url_hash = item
+            .get("UrlHash")
+            .and_then(|s| s.as_s().map(|s| s.to_string()).ok());
+        // String sets have no order, sort them so links compare and serialise the same
+        let mut tags = item
+            .get("Tags")
+            .and_then(|tags| tags.as_ss().ok())
+            .cloned()
+            .unwrap_or_default();
+        tags.sort_unstable();
+        let targets = match item.get("Targets") {
+            Some(targets) => targets
+                .as_l()
+                .map_err(|_| "Targets is not a List".to_string())?
+                .iter()
+                .map(Target::try_from)
+                .collect::<Result<Vec<_>, _>>()?,
+            None => Vec::new(),
+        };
+        let rules = match item.get("Rules") {
+            Some(rules) => rules
+                .as_l()
+                .map_err(|_| "Rules is not a List".to_string())?
+                .iter()
+                .map(RedirectRule::try_from)
+                .collect::<Result<Vec<_>, _>>()?,
+            None => Vec::new(),
+        };
+        let query_mode = match item.get("QueryMode") {
+            Some(query_mode) => query_mode
+                .as_s()
+                .map_err(|_| "QueryMode is not a String".to_string())?
+                .parse::<QueryMode>()?,
+            None => QueryMode::Ignore,
+        };
+        let override_query = item
+            .get("OverrideQuery")
+            .and_then(|b| b.as_bool().ok())
+            .copied()
+            .unwrap_or_default();
+        let utm = item
+            .get("Utm")
+            .and_then(|utm| utm.as_m().ok())
+            .map(|utm| {
+                utm.iter()
+                    .filter_map(|(name, value)| Some((name.clone(), value.as_s().ok()?.clone())))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let redirect_type = match item.get("RedirectType") {
+            Some(redirect_type) => redirect_type
+                .as_n()
+                .map_err(|_| "RedirectType is not a number".to_string())?
+                .parse::<u16>()
+                .map_err(|_| "Cannot convert RedirectType into u16".to_string())?
+                .try_into()?,
+            None => RedirectType::Found,
+        };
+        let interstitial = item
+            .get("Interstitial")
+            .and_then(|b| b.as_bool().ok())
+            .copied()
+            .unwrap_or_default();
+        let password = item
+            .get("PasswordHash")
+            .and_then(|s| s.as_s().ok())
+            .map(|hash| LinkPassword {
+                hash: hash.to_string(),
+                failures: item
+                    .get("PasswordFailures")
+                    .and_then(|n| n.as_n().ok())
+                    .and_then(|n| n.parse::<u32>().ok())
+                    .unwrap_or_default(),
+                failure_window: item
+                    .get("PasswordFailureWindow")
+                    .and_then(|n| n.as_n().ok())
+                    .and_then(|n| n.parse::<u64>().ok())
+                    .unwrap_or_default(),
+            });
+
+        Ok(ShortUrl {
+            owner_id,
+            url_hash,
+            expires_at,
+            max_clicks,
+            status,
+            version,
+            history,
+            tags,
+            password,
+            targets,
+            rules,
+            query_mode,
+            override_query,
+            utm,
+            redirect_type,
+            interstitial,
+            ..ShortUrl::with_details(
+                link_id,
+                original_link,
+                clicks,
+                title,
+                description,
+                content_type,
+            )
+        })
+    }
+}
+
+impl From<&ShortUrl> for HashMap<String, AttributeValue> {
+    fn from(short_url: &ShortUrl) -> Self {
+        let mut item = HashMap::from([
+            (
+                "LinkId".to_string(),
+                AttributeValue::S(short_url.link_id.clone()),
+            ),
+            (
+                "OriginalLink".to_string(),
+                AttributeValue::S(short_url.original_link.clone()),
+            ),
+            (
+                "Clicks".to_string(),
+                AttributeValue::N(short_url.clicks.to_string()),
+            ),
+            (
+                "Status".to_string(),
+                AttributeValue::S(short_url.status.as_str().to_string()),
+            ),
+            (
+                "Version".to_string(),
+                AttributeValue::N(short_url.version.to_string()),
+            ),
+        ]);
+        if !short_url.history.is_empty() {
+            item.insert(
+                "History".to_string(),
+                AttributeValue::L(short_url.history.iter().map(AttributeValue::from).collect()),
+            );
+        }
+        // Only owned links are written to the owner index
+        if let Some(ref owner_id) = short_url.owner_id {
+            item.insert("OwnerId".to_string(), AttributeValue::S(owner_id.clone()));
+        }
+        if let Some(ref url_hash) = short_url.url_hash {
+            item.insert("UrlHash".to_string(), AttributeValue::S(url_hash.clone()));
+        }
+        // Empty sets are not allowed
+        if !short_url.tags.is_empty() {
+            item.insert(
+                "Tags".to_string(),
+                AttributeValue::Ss(short_url.tags.clone()),
+            );
+        }
+        if let Some(ref title) = short_url.title {
+            item.insert("Title".to_string(), AttributeValue::S(title.clone()));
+        }
+        if let Some(ref description) = short_url.description {
+            item.insert(
+                "Description".to_string(),
+                AttributeValue::S(description.clone()),
+            );
+        }
+        if let Some(ref content_type) = short_url.content_type {
+            item.insert(
+                "ContentType".to_string(),
+                AttributeValue::S(content_type.clone()),
+            );
+        }
+        // ExpiresAt doubles as the table's TTL attribute, so DynamoDB removes expired links
+        if let Some(expires_at) = short_url.expires_at {
+            item.insert(
+                "ExpiresAt".to_string(),
+                AttributeValue::N(expires_at.to_string()),
+            );
+        }
+        if let Some(max_clicks) = short_url.max_clicks {
+            item.insert(
+                "MaxClicks".to_string(),
+                AttributeValue::N(max_clicks.to_string()),
+            );
+        }
+        if !short_url.targets.is_empty() {
+            item.insert(
+                "Targets".to_string(),
+                AttributeValue::L(short_url.targets.iter().map(AttributeValue::from).collect()),
+            );
+        }
+        if !short_url.rules.is_empty() {
+            item.insert(
+                "Rules".to_string(),
+                AttributeValue::L(short_url.rules.iter().map(AttributeValue::from).collect()),
+            );
+        }
+        if !short_url.query_mode.is_ignore() {
+            item.insert(
+                "QueryMode".to_string(),
+                AttributeValue::S(short_url.query_mode.as_str().to_string()),
+            );
+        }
+        if short_url.override_query {
+            item.insert("OverrideQuery".to_string(), AttributeValue::Bool(true));
+        }
+        if !short_url.utm.is_empty() {
+            item.insert(
+                "Utm".to_string(),
+                AttributeValue::M(
+                    short_url
+                        .utm
+                        .iter()
+                        .map(|(name, value)| (name.clone(), AttributeValue::S(value.clone())))
+                        .collect(),
+                ),
+            );
+        }
+        if !short_url.redirect_type.is_found() {
+            item.insert(
+                "RedirectType".to_string(),
+                AttributeValue::N(short_url.redirect_type.status_code().to_string()),
+            );
+        }
+        if short_url.interstitial {
+            item.insert("Interstitial".to_string(), AttributeValue::Bool(true));
+        }
+        // Failures are only ever counted by `record_password_failure`
+        if let Some(ref password) = short_url.password {
+            item.insert(
+                "PasswordHash".to_string(),
+                AttributeValue::S(password.hash.clone()),
+            );
+        }
+        item
+    }
+}
+
+impl TryFrom<&AttributeValue> for DestinationChange {
+    type Error = String;
+
+    fn try_from(value: &AttributeValue) -> Result<Self, Self::Error> {
+        let entry = value
+            .as_m()
+            .map_err(|_| "History entry is not a Map".to_string())?;
+        let original_link = entry
+            .get("OriginalLink")
+            .and_then(|s| s.as_s().ok())
+            .ok_or_else(|| "History entry has no OriginalLink".to_string())?
+            .to_string();
+        let replaced_at = entry
+            .get("ReplacedAt")
+            .and_then(|n| n.as_n().ok())
+            .and_then(|n| n.parse::<u64>().ok())
+            .ok_or_else(|| "History entry has no ReplacedAt".to_string())?;
+        Ok(DestinationChange {
+            original_link,
+            replaced_at,
+        })
+    }
+}
+
+impl From<&DestinationChange> for AttributeValue {
+    fn from(change: &DestinationChange) -> Self {
+        AttributeValue::M(HashMap::from([
+            (
+                "OriginalLink".to_string(),
+                AttributeValue::S(change.original_link.clone()),
+            ),
+            (
+                "ReplacedAt".to_string(),
+                AttributeValue::N(change.replaced_at.to_string()),
+            ),
+        ]))
+    }
+}
+
+impl TryFrom<&AttributeValue> for Target {
+    type Error = String;
+
+    fn try_from(value: &AttributeValue) -> Result<Self, Self::Error> {
+        let entry = value
+            .as_m()
+            .map_err(|_| "Target is not a Map".to_string())?;
+        let url = entry
+            .get("Url")
+            .and_then(|s| s.as_s().ok())
+            .ok_or_else(|| "Target has no Url".to_string())?
+            .to_string();
+        let weight = entry
+            .get("Weight")
+            .and_then(|n| n.as_n().ok())
+            .and_then(|n| n.parse::<u32>().ok())
+            .ok_or_else(|| "Target has no Weight".to_string())?;
+        let clicks = entry
+            .get("Clicks")
+            .and_then(|n| n.as_n().ok())
+            .and_then(|n| n.parse::<u64>().ok())
+            .unwrap_or_default();
+        Ok(Target {
+            url,
+            weight,
+            clicks,
+        })
+    }
+}
+
+impl From<&Target> for AttributeValue {
+    fn from(target: &Target) -> Self {
+        AttributeValue::M(HashMap::from([
+            ("Url".to_string(), AttributeValue::S(target.url.clone())),
+            (
+                "Weight".to_string(),
+                AttributeValue::N(target.weight.to_string()),
+            ),
+            (
+                "Clicks".to_string(),
+                AttributeValue::N(target.clicks.to_string()),
+            ),
+        ]))
+    }
+}
+
+impl TryFrom<&AttributeValue> for RedirectRule {
+    type Error = String;
+
+    fn try_from(value: &AttributeValue) -> Result<Self, Self::Error> {
+        let entry = value.as_m().map_err(|_| "Rule is not a Map".to_string())?;
+        let strings = |name: &str| -> Vec<String> {
+            entry
+                .get(name)
+                .and_then(|list| list.as_l().ok())
+                .map(|list| list.iter().filter_map(|s| s.as_s().ok().cloned()).collect())
+                .unwrap_or_default()
+        };
+        let device = match entry.get("Device") {
+            Some(device) => Some(
+                device
+                    .as_s()
+                    .map_err(|_| "Rule Device is not a String".to_string())?
+                    .parse::<Device>()?,
+            ),
+            None => None,
+        };
+        let destination = entry
+            .get("Destination")
+            .and_then(|s| s.as_s().ok())
+            .ok_or_else(|| "Rule has no Destination".to_string())?
+            .to_string();
+        Ok(RedirectRule {
+            device,
+            countries: strings("Countries"),
+            languages: strings("Languages"),
+            destination,
+        })
+    }
+}
+
+impl From<&RedirectRule> for AttributeValue {
+    fn from(rule: &RedirectRule) -> Self {
+        let strings = |values: &[String]| {
+            AttributeValue::L(values.iter().cloned().map(AttributeValue::S).collect())
+        };
+        let mut entry = HashMap::from([
+            (
+                "Destination".to_string(),
+                AttributeValue::S(rule.destination.clone()),
+            ),
+            ("Countries".to_string(), strings(&rule.countries)),
+            ("Languages".to_string(), strings(&rule.languages)),
+        ]);
+        if let Some(device) = rule.device {
+            entry.insert(
+                "Device".to_string(),
+                AttributeValue::S(device.as_str().to_string()),
+            );
+        }
+        AttributeValue::M(entry)
+    }
+}
+
+fn stale_version_error(short_link: &str, expected_version: u64) -> RepositoryError {
+    RepositoryError::Conflict(format!(
+        "Error updating destination: LinkId {} is no longer at version {}",
+        short_link, expected_version
+    ))
+}
+
+/// The repository of a function: the DynamoDB one, or with `in_memory` set
+/// (`IN_MEMORY_REPOSITORY=true`) an `InMemoryUrlRepository`, so the function
+/// runs without DynamoDB. Links kept in memory last as long as the process and
+/// are not shared with other functions.
+pub fn url_repository(
+    in_memory: bool,
+    dynamodb: impl FnOnce() -> DynamoDbUrlRepository,
+) -> Box<dyn UrlRepository + Send + Sync> {
+    if in_memory {
+        tracing::warn!("Keeping links in memory, they are lost when the function stops");
+        Box::new(InMemoryUrlRepository::new())
+    } else {
+        Box::new(dynamodb())
+    }
+}
+
+/// A `UrlRepository` that keeps links in memory, useful for local runs and tests.
+///
+/// It mirrors the semantics of `DynamoDbUrlRepository`: ids must be unique on
+/// creation, updates require the link to exist and listing returns pages of at
+/// most `limit` items with the key to continue from.
+#[derive(Debug, Default)]
+pub struct InMemoryUrlRepository {
+    urls: RwLock<BTreeMap<String, ShortUrl>>,
+}
+
+impl InMemoryUrlRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pages like a limited scan, or a limited query on the owner index when
+    /// `owner_id` is set. Both are ordered by link id here.
+    fn list_page(
+        &self,
+        owner_id: Option<&str>,
+        options: ListOptions,
+    ) -> Result<Page, RepositoryError> {
+        let urls = self
+            .urls
+            .read()
+            .map_err(|e| RepositoryError::Fatal(format!("Error executing scan: {}", e)))?;
+
+        let start_id = options
+            .start_key
+            .as_ref()
+            .and_then(|key| key.get("LinkId"))
+            .cloned();
+        let candidates: Box<dyn Iterator<Item = &ShortUrl>> = match (options.sort, start_id) {
+            (SortOrder::Ascending, Some(start_id)) => Box::new(
+                urls.range::<String, _>((Excluded(start_id), Unbounded))
+                    .map(|(_, v)| v),
+            ),
+            (SortOrder::Ascending, None) => Box::new(urls.values()),
+            (SortOrder::Descending, Some(start_id)) => Box::new(
+                urls.range::<String, _>((Unbounded, Excluded(start_id)))
+                    .rev()
+                    .map(|(_, v)| v),
+            ),
+            (SortOrder::Descending, None) => Box::new(urls.values().rev()),
+        };
+        let has_tag = |short_url: &ShortUrl| {
+            options
+                .tag
+                .as_ref()
+                .is_none_or(|tag| short_url.tags.contains(tag))
+        };
+        // An owner's tagged links are read from the tag index, before the limit
+        let scanned: Vec<&ShortUrl> = candidates
+            .filter(|short_url| owner_id.is_none() || short_url.owner_id.as_deref() == owner_id)
+            .filter(|short_url| owner_id.is_none() || has_tag(short_url))
+            .take(options.limit)
+            .collect();
+
+        // Like a DynamoDB scan with a limit, a full page always returns a key,
+        // even when no items are left after it.
+        let next_key = if scanned.len() == options.limit {
+            scanned.last().map(|short_url| {
+                let mut key = PageKey::from([("LinkId".to_string(), short_url.link_id.clone())]);
+                match (owner_id, &options.tag) {
+                    (Some(owner_id), Some(tag)) => {
+                        key.insert("TagKey".to_string(), tag_key(owner_id, tag));
+                    }
+                    (Some(owner_id), None) => {
+                        key.insert("OwnerId".to_string(), owner_id.to_string());
+                    }
+                    (None, _) => {}
+                }
+                key
+            })
+        } else {
+            None
+        };
+
+        // ...and the filter applies after the limit, like a scan filter expression
+        let short_urls = scanned
+            .into_iter()
+            .filter(|short_url| options.include_inactive || short_url.status.is_active())
+            .filter(|short_url| has_tag(short_url))
+            .cloned()
+            .collect();
+
+        Ok(Page {
+            short_urls,
+            next_key,
+        })
+    }
+}
+
+#[async_trait]
+impl UrlRepository for InMemoryUrlRepository {
+    async fn get_url_from_short_link(
+        &self,
+        short_link: &str,
+    ) -> Result<Option<ShortUrl>, RepositoryError> {
+        let urls = self
+            .urls
+            .read()
+            .map_err(|e| RepositoryError::Fatal(format!("Error getting item: {}", e)))?;
+        Ok(urls.get(short_link).cloned())
+    }
+
+    async fn store_short_url(&self, short_url: ShortUrl) -> Result<ShortUrl, RepositoryError> {
+        let mut urls = self
+            .urls
+            .write()
+            .map_err(|e| RepositoryError::Fatal(format!("Error adding item: {}", e)))?;
+        if urls.contains_key(&short_url.link_id) {
+            return Err(RepositoryError::AlreadyExists(format!(
+                "Error adding item: ConditionalCheckFailed for LinkId {}",
+                short_url.link_id
+            )));
+        }
+        urls.insert(short_url.link_id.clone(), short_url.clone());
+        Ok(short_url)
+    }
+
+    async fn store_short_urls(
+        &self,
+        short_urls: Vec<ShortUrl>,
+    ) -> Result<Vec<ShortUrl>, RepositoryError> {
+        let mut urls = self
+            .urls
+            .write()
+            .map_err(|e| RepositoryError::Fatal(format!("Error writing batch: {}", e)))?;
+        // Batch puts overwrite existing items, there is no condition to check
+        for short_url in &short_urls {
+            urls.insert(short_url.link_id.clone(), short_url.clone());
+        }
+        Ok(short_urls)
+    }
+
+    async fn add_details_to_short_url(
+        &self,
+        short_link: String,
+        original_link: &str,
+        url_details: UrlDetails,
+    ) -> Result<(), RepositoryError> {
+        let mut urls = self
+            .urls
+            .write()
+            .map_err(|e| RepositoryError::Fatal(format!("Error updating item: {}", e)))?;
+        let short_url = urls.get_mut(&short_link).ok_or_else(|| {
+            RepositoryError::NotFound(format!(
+                "Error updating item: ConditionalCheckFailed for LinkId {}",
+                short_link
+            ))
+        })?;
+        if short_url.original_link != original_link {
+            return Err(RepositoryError::Conflict(format!(
+                "Error updating item: LinkId {} no longer points to {}",
+                short_link, original_link
+            )));
+        }
+        short_url.title = url_details.title;
+        short_url.description = url_details.description;
+        short_url.content_type = url_details.content_type;
+        Ok(())
+    }
+
+    async fn increment_clicks(&self, short_link: &str, n: u64) -> Result<(), RepositoryError> {
+        let mut urls = self
+            .urls
+            .write()
+            .map_err(|e| RepositoryError::Fatal(format!("Error incrementing clicks: {}", e)))?;
+        let short_url = urls.get_mut(short_link).ok_or_else(|| {
+            RepositoryError::NotFound(format!(
+                "Error incrementing clicks: ConditionalCheckFailed for LinkId {}",
+                short_link
+            ))
+        })?;
+        short_url.clicks = u32::try_from(u64::from(short_url.clicks) + n)
+            .map_err(|_| RepositoryError::Fatal("Cannot convert Clicks into u32".to_string()))?;
+        Ok(())
+    }
+
+    async fn increment_target_clicks(
+        &self,
+        short_link: &str,
+        variant: usize,
+        n: u64,
+    ) -> Result<(), RepositoryError> {
+        let mut urls = self.urls.write().map_err(|e| {
+            RepositoryError::Fatal(format!("Error incrementing target clicks: {}", e))
+        })?;
+        let target = urls
+            .get_mut(short_link)
+            .and_then(|short_url| short_url.targets.get_mut(variant))
+            .ok_or_else(|| {
+                RepositoryError::NotFound(format!(
+                    "Error incrementing target clicks: ConditionalCheckFailed for LinkId {}",
+                    short_link
+                ))
+            })?;
+        target.clicks += n;
+        Ok(())
+    }
+
+    async fn record_password_failure(
+        &self,
+        short_link: &str,
+        window: u64,
+    ) -> Result<u32, RepositoryError> {
+        let mut urls = self.urls.write().map_err(|e| {
+            RepositoryError::Fatal(format!("Error counting password failure: {}", e))
+        })?;
+        let password = urls
+            .get_mut(short_link)
+            .and_then(|short_url| short_url.password.as_mut())
+            .ok_or_else(|| {
+                RepositoryError::NotFound(format!(
+                    "Error counting password failure: LinkId {} not found or not protected",
+                    short_link
+                ))
+            })?;
+        if password.failure_window == window {
+            password.failures += 1;
+        } else {
+            password.failures = 1;
+            password.failure_window = window;
+        }
+        Ok(password.failures)
+    }
+
+    async fn update_destination(
+        &self,
+        short_link: &str,
+        owner_id: Option<String>,
+        original_link: String,
+        expected_version: u64,
+    ) -> Result<ShortUrl, RepositoryError> {
+        let mut urls = self
+            .urls
+            .write()
+            .map_err(|e| RepositoryError::Fatal(format!("Error updating destination: {}", e)))?;
+        let short_url = urls
+            .get_mut(short_link)
+            .filter(|short_url| {
+                short_url.status != LinkStatus::Deleted && short_url.belongs_to(owner_id.as_deref())
+            })
+            .ok_or_else(|| {
+                RepositoryError::NotFound(format!(
+                    "Error updating destination: LinkId {} not found",
+                    short_link
+                ))
+            })?;
+        if short_url.version != expected_version {
+            return Err(stale_version_error(short_link, expected_version));
+        }
+        short_url.url_hash = url_hash(&original_link).ok();
+        let previous = std::mem::replace(&mut short_url.original_link, original_link);
+        short_url.history.push(DestinationChange {
+            original_link: previous,
+            replaced_at: epoch_seconds(),
+        });
+        short_url.version += 1;
+        Ok(short_url.clone())
+    }
+
+    async fn set_link_status(
+        &self,
+        short_link: &str,
+        owner_id: Option<String>,
+        status: LinkStatus,
+    ) -> Result<ShortUrl, RepositoryError> {
+        let mut urls = self
+            .urls
+            .write()
+            .map_err(|e| RepositoryError::Fatal(format!("Error updating status: {}", e)))?;
+        let short_url = urls
+            .get_mut(short_link)
+            .filter(|short_url| {
+                short_url.status != LinkStatus::Deleted && short_url.belongs_to(owner_id.as_deref())
+            })
+            .ok_or_else(|| {
+                RepositoryError::NotFound(format!(
+                    "Error updating status: ConditionalCheckFailed for LinkId {}",
+                    short_link
+                ))
+            })?;
+        short_url.status = status;
+        Ok(short_url.clone())
+    }
+
+    async fn add_tags(
+        &self,
+        short_link: &str,
+        owner_id: Option<String>,
+        tags: Vec<String>,
+    ) -> Result<ShortUrl, RepositoryError> {
+        let mut urls = self
+            .urls
+            .write()
+            .map_err(|e| RepositoryError::Fatal(format!("Error adding tags: {}", e)))?;
+        let short_url = urls
+            .get_mut(short_link)
+            .filter(|short_url| {
+                short_url.status != LinkStatus::Deleted && short_url.belongs_to(owner_id.as_deref())
+            })
+            .ok_or_else(|| {
+                RepositoryError::NotFound(format!(
+                    "Error adding tags: LinkId {} not found",
+                    short_link
+                ))
+            })?;
+        let tagged: BTreeSet<String> = short_url.tags.iter().chain(&tags).cloned().collect();
+        if tagged.len() > MAX_TAGS {
+            return Err(RepositoryError::Validation(TagError::TooMany.to_string()));
+        }
+        short_url.tags = tagged.into_iter().collect();
+        Ok(short_url.clone())
+    }
+
+    async fn remove_tags(
+        &self,
+        short_link: &str,
+        owner_id: Option<String>,
+        tags: Vec<String>,
+    ) -> Result<ShortUrl, RepositoryError> {
+        let mut urls = self
+            .urls
+            .write()
+            .map_err(|e| RepositoryError::Fatal(format!("Error removing tags: {}", e)))?;
+        let short_url = urls
+            .get_mut(short_link)
+            .filter(|short_url| {
+                short_url.status != LinkStatus::Deleted && short_url.belongs_to(owner_id.as_deref())
+            })
+            .ok_or_else(|| {
+                RepositoryError::NotFound(format!(
+                    "Error removing tags: LinkId {} not found",
+                    short_link
+                ))
+            })?;
+        short_url.tags.retain(|tag| !tags.contains(tag));
+        Ok(short_url.clone())
+    }
+
+    async fn find_urls_by_hash(
+        &self,
+        owner_id: &str,
+        url_hash: &str,
+    ) -> Result<Vec<ShortUrl>, RepositoryError> {
+        let urls = self
+            .urls
+            .read()
+            .map_err(|e| RepositoryError::Fatal(format!("Error executing query: {}", e)))?;
+        Ok(urls
+            .values()
+            .filter(|short_url| {
+                short_url.url_hash.as_deref() == Some(url_hash)
+                    && short_url.owner_id.as_deref() == Some(owner_id)
+                    && short_url.status.is_active()
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn search_urls(
+        &self,
+        owner_id: &str,
+        query: &str,
+        options: ListOptions,
+    ) -> Result<Page, RepositoryError> {
+        let urls = self
+            .urls
+            .read()
+            .map_err(|e| RepositoryError::Fatal(format!("Error executing query: {}", e)))?;
+
+        // Builds the postings the DynamoDB index would hold for the query terms
+        let mut postings: HashMap<String, Vec<(String, u32)>> = HashMap::new();
+        let owned = urls
+            .values()
+            .filter(|short_url| short_url.owner_id.as_deref() == Some(owner_id));
+        for short_url in owned {
+            let terms = index_terms(short_url.title.as_deref(), short_url.description.as_deref());
+            for term in query_terms(query) {
+                if let Some(weight) = terms.get(&term) {
+                    postings
+                        .entry(term)
+                        .or_default()
+                        .push((short_url.link_id.clone(), *weight));
+                }
+            }
+        }
+
+        let (link_ids, next_key) =
+            page_of(rank(&postings), options.start_key.as_ref(), options.limit);
+        Ok(Page {
+            short_urls: link_ids
+                .iter()
+                .filter_map(|link_id| urls.get(link_id))
+                .filter(|short_url| options.include_inactive || short_url.status.is_active())
+                .cloned()
+                .collect(),
+            next_key,
+        })
+    }
+
+    async fn list_urls(&self, options: ListOptions) -> Result<Page, RepositoryError> {
+        self.list_page(None, options)
+    }
+
+    async fn list_urls_for_owner(
+        &self,
+        owner_id: &str,
+        options: ListOptions,
+    ) -> Result<Page, RepositoryError> {
+        self.list_page(Some(owner_id), options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{map_sdk_error, tag_item, url_repository, InMemoryUrlRepository};
+    use crate::{
+        core::{
+            DestinationChange, LinkStatus, ListOptions, Page, PageKey, QueryMode, RedirectRule,
+            RedirectType, RepositoryError, ShortUrl, SortOrder, Target, UrlRepository,
+        },
+        normalise::url_hash,
+        password::LinkPassword,
+        rules::Device,
+        tags::MAX_TAGS,
+        url_info::UrlDetails,
+    };
+    use aws_sdk_dynamodb::{
+        config::http::HttpResponse,
+        error::{ErrorMetadata, SdkError},
+        operation::put_item::PutItemError,
+        types::AttributeValue,
+    };
+    use aws_smithy_runtime_api::http::StatusCode;
+    use aws_smithy_types::body::SdkBody;
+    use std::collections::{BTreeMap, HashMap};
+
+    fn service_error(code: &str) -> SdkError<PutItemError, HttpResponse> {
+        SdkError::service_error(
+            PutItemError::generic(ErrorMetadata::builder().code(code).build()),
+            HttpResponse::new(StatusCode::try_from(400).unwrap(), SdkBody::empty()),
+        )
+    }
+
+    #[test]
+    fn sdk_errors_should_map_to_repository_errors() {
+        let map = |error| map_sdk_error("test", error, RepositoryError::AlreadyExists);
+
+        assert!(matches!(
+            map(service_error("ConditionalCheckFailedException")),
+            RepositoryError::AlreadyExists(_)
+        ));
+        assert!(matches!(
+            map(service_error("ProvisionedThroughputExceededException")),
+            RepositoryError::Throttled(_)
+        ));
+        assert!(matches!(
+            map(service_error("ThrottlingException")),
+            RepositoryError::Throttled(_)
+        ));
+        assert!(matches!(
+            map(service_error("ValidationException")),
+            RepositoryError::Fatal(_)
+        ));
+        assert!(matches!(
+            map(service_error("InternalServerError")),
+            RepositoryError::Transient(_)
+        ));
+        assert!(matches!(
+            map(service_error("ResourceNotFoundException")),
+            RepositoryError::Fatal(_)
+        ));
+        assert!(matches!(
+            map(SdkError::timeout_error("timed out")),
+            RepositoryError::Transient(_)
+        ));
+        assert!(matches!(
+            map(SdkError::construction_failure("bad request")),
+            RepositoryError::Fatal(_)
+        ));
+    }
+
+    #[test]
+    fn short_url_should_round_trip_through_dynamodb_item() {
+        let short_url = ShortUrl {
+            title: Some("Example".into()),
+            expires_at: Some(1_700_000_000),
+            max_clicks: Some(100),
+            url_hash: Some("0123abcd".into()),
+            version: 2,
+            history: vec![DestinationChange {
+                original_link: "https://exmaple.com".into(),
+                replaced_at: 1_690_000_000,
+            }],
+            tags: vec!["campaign".into(), "spring".into()],
+            password: Some(LinkPassword {
+                hash: "$argon2id$v=19$m=19456,t=2,p=1$c2FsdA$aGFzaA".into(),
+                ..Default::default()
+            }),
+            targets: vec![
+                Target {
+                    url: "https://example.com/a".into(),
+                    weight: 3,
+                    clicks: 12,
+                },
+                Target {
+                    url: "https://example.com/b".into(),
+                    weight: 1,
+                    clicks: 0,
+                },
+            ],
+            rules: vec![RedirectRule {
+                device: Some(Device::Ios),
+                countries: vec!["BE".into(), "FR".into()],
+                languages: vec![],
+                destination: "https://apps.apple.com/app".into(),
+            }],
+            query_mode: QueryMode::Merge,
+            override_query: true,
+            utm: BTreeMap::from([("utm_source".into(), "newsletter".into())]),
+            redirect_type: RedirectType::PermanentRedirect,
+            interstitial: true,
+            ..ShortUrl::new("abc123".into(), "https://example.com".into())
+        };
+
+        let mut item = HashMap::from(&short_url);
+        item.insert(
+            "PasswordFailures".to_string(),
+            AttributeValue::N("2".into()),
+        );
+        // Sets come back in any order
+        item.insert(
+            "Tags".to_string(),
+            AttributeValue::Ss(vec!["spring".into(), "campaign".into()]),
+        );
+        assert_eq!(
+            item.get("ExpiresAt"),
+            Some(&AttributeValue::N("1700000000".into()))
+        );
+        assert!(!item.contains_key("Description"));
+
+        let read_back = ShortUrl::try_from(item).unwrap();
+        assert_eq!(read_back.link_id, "abc123");
+        assert_eq!(read_back.title.as_deref(), Some("Example"));
+        assert_eq!(read_back.expires_at, Some(1_700_000_000));
+        assert_eq!(read_back.max_clicks, Some(100));
+        assert_eq!(read_back.url_hash.as_deref(), Some("0123abcd"));
+        assert_eq!(read_back.version, 2);
+        assert_eq!(read_back.history, short_url.history);
+        assert_eq!(read_back.tags, short_url.tags);
+        let password = read_back.password.unwrap();
+        assert!(password.hash.starts_with("$argon2id$"));
+        assert_eq!(password.failures, 2);
+        assert_eq!(read_back.targets, short_url.targets);
+        assert_eq!(read_back.rules, short_url.rules);
+        assert_eq!(read_back.query_mode, QueryMode::Merge);
+        assert!(read_back.override_query);
+        assert_eq!(read_back.utm, short_url.utm);
+        assert_eq!(read_back.redirect_type, RedirectType::PermanentRedirect);
+        assert!(read_back.interstitial);
+    }
+
+    #[test]
+    fn when_link_expires_its_tag_items_should_expire_with_it() {
+        let short_url = ShortUrl {
+            owner_id: Some("alice".into()),
+            expires_at: Some(1_900_000_000),
+            ..ShortUrl::new("abc123".into(), "https://example.com".into())
+        };
+
+        let item = tag_item(&short_url, "spring");
+
+        assert_eq!(
+            item.get("TagKey"),
+            Some(&AttributeValue::S("alice#spring".into()))
+        );
+        assert_eq!(
+            item.get("ExpiresAt"),
+            Some(&AttributeValue::N("1900000000".into()))
+        );
+        let item = tag_item(
+            &ShortUrl {
+                expires_at: None,
+                ..short_url
+            },
+            "spring",
+        );
+        assert_eq!(item.get("ExpiresAt"), None);
+    }
+
+    #[tokio::test]
+    async fn when_link_is_stored_should_be_retrievable() {
+        let repo = InMemoryUrlRepository::new();
+
+        repo.store_short_url(ShortUrl::new("abc123".into(), "https://example.com".into()))
+            .await
+            .unwrap();
+
+        let short_url = repo
+            .get_url_from_short_link("abc123")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(short_url.original_link, "https://example.com");
+        assert_eq!(short_url.clicks, 0);
+        assert!(repo
+            .get_url_from_short_link("missing")
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn when_links_are_stored_in_bulk_should_all_be_retrievable() {
+        let repo = InMemoryUrlRepository::new();
+        let short_urls: Vec<ShortUrl> = (0..60)
+            .map(|i| ShortUrl::new(format!("link{:02}", i), "https://example.com".into()))
+            .collect();
+
+        let written = repo.store_short_urls(short_urls).await.unwrap();
+
+        assert_eq!(written.len(), 60);
+        assert!(repo
+            .get_url_from_short_link("link59")
+            .await
+            .unwrap()
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn when_link_id_already_exists_should_fail_to_store() {
+        let repo = InMemoryUrlRepository::new();
+        repo.store_short_url(ShortUrl::new("abc123".into(), "https://example.com".into()))
+            .await
+            .unwrap();
+
+        let result = repo
+            .store_short_url(ShortUrl::new("abc123".into(), "https://other.com".into()))
+            .await;
+
+        assert!(matches!(result, Err(RepositoryError::AlreadyExists(_))));
+        let short_url = repo
+            .get_url_from_short_link("abc123")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(short_url.original_link, "https://example.com");
+    }
+
+    #[tokio::test]
+    async fn when_link_does_not_exist_updates_should_fail() {
+        let repo = InMemoryUrlRepository::new();
+
+        let details = UrlDetails {
+            title: Some("Example".into()),
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            repo.add_details_to_short_url("missing".into(), "https://example.com", details)
+                .await,
+            Err(RepositoryError::NotFound(_))
+        ));
+        assert!(matches!(
+            repo.increment_clicks("missing", 1).await,
+            Err(RepositoryError::NotFound(_))
+        ));
+        assert!(repo
+            .get_url_from_short_link("missing")
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn when_link_exists_should_add_details_and_increment_clicks() {
+        let repo = InMemoryUrlRepository::new();
+        repo.store_short_url(ShortUrl::new("abc123".into(), "https://example.com".into()))
+            .await
+            .unwrap();
+
+        repo.add_details_to_short_url(
+            "abc123".into(),
+            "https://example.com",
+            UrlDetails {
+                title: Some("Example".into()),
+                description: None,
+                content_type: Some("text/html".into()),
+            },
+        )
+        .await
+        .unwrap();
+        repo.increment_clicks("abc123", 3).await.unwrap();
+        repo.increment_clicks("abc123", 2).await.unwrap();
+
+        let short_url = repo
+            .get_url_from_short_link("abc123")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(short_url.title.as_deref(), Some("Example"));
+        assert_eq!(short_url.description, None);
+        assert_eq!(short_url.content_type.as_deref(), Some("text/html"));
+        assert_eq!(short_url.clicks, 5);
+    }
+
+    #[tokio::test]
+    async fn when_details_are_scraped_again_should_remove_the_ones_the_page_lost() {
+        let repo = InMemoryUrlRepository::new();
+        repo.store_short_url(ShortUrl::new("abc123".into(), "https://example.com".into()))
+            .await
+            .unwrap();
+        repo.add_details_to_short_url(
+            "abc123".into(),
+            "https://example.com",
+            UrlDetails {
+                title: Some("Old title".into()),
+                description: Some("Old description".into()),
+                content_type: Some("text/html".into()),
+            },
+        )
+        .await
+        .unwrap();
+
+        repo.add_details_to_short_url(
+            "abc123".into(),
+            "https://example.com",
+            UrlDetails {
+                content_type: Some("application/pdf".into()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        let short_url = repo
+            .get_url_from_short_link("abc123")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(short_url.title, None);
+        assert_eq!(short_url.description, None);
+        assert_eq!(short_url.content_type.as_deref(), Some("application/pdf"));
+    }
+
+    #[tokio::test]
+    async fn when_destination_changed_since_the_scrape_should_keep_the_details_with_a_conflict() {
+        let repo = InMemoryUrlRepository::new();
+        repo.store_short_url(ShortUrl::new("abc123".into(), "https://example.com".into()))
+            .await
+            .unwrap();
+        repo.update_destination("abc123", None, "https://example.com/new".into(), 0)
+            .await
+            .unwrap();
+
+        let result = repo
+            .add_details_to_short_url(
+                "abc123".into(),
+                "https://example.com",
+                UrlDetails {
+                    title: Some("Old destination".into()),
+                    ..Default::default()
+                },
+            )
+            .await;
+
+        assert!(matches!(result, Err(RepositoryError::Conflict(_))));
+        let short_url = repo
+            .get_url_from_short_link("abc123")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(short_url.title, None);
+    }
+
+    #[tokio::test]
+    async fn when_in_memory_is_selected_should_not_build_the_dynamodb_repository() {
+        let repo = url_repository(true, || unreachable!("DynamoDB is not used"));
+
+        repo.store_short_url(ShortUrl::new("abc123".into(), "https://example.com".into()))
+            .await
+            .unwrap();
+
+        assert!(repo
+            .get_url_from_short_link("abc123")
+            .await
+            .unwrap()
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn when_listing_should_page_with_next_key() {
+        let repo = InMemoryUrlRepository::new();
+        for i in 0..120 {
+            repo.store_short_url(ShortUrl::new(
+                format!("link{:03}", i),
+                format!("https://example.com/{}", i),
+            ))
+            .await
+            .unwrap();
+        }
+
+        let first_page = repo.list_urls(ListOptions::default()).await.unwrap();
+        assert_eq!(first_page.short_urls.len(), 50);
+        assert_eq!(first_page.next_key, Some(link_key("link049")));
+
+        let second_page = repo
+            .list_urls(ListOptions {
+                start_key: first_page.next_key,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(second_page.short_urls.len(), 50);
+        assert_eq!(second_page.short_urls[0].link_id, "link050");
+        assert_eq!(second_page.next_key, Some(link_key("link099")));
+
+        let last_page = repo
+            .list_urls(ListOptions {
+                start_key: second_page.next_key,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(last_page.short_urls.len(), 20);
+        assert_eq!(last_page.next_key, None);
+    }
+
+    #[tokio::test]
+    async fn when_listing_descending_with_limit_should_page_backwards() {
+        let repo = InMemoryUrlRepository::new();
+        for i in 0..5 {
+            repo.store_short_url(ShortUrl::new(
+                format!("link{:03}", i),
+                "https://example.com".into(),
+            ))
+            .await
+            .unwrap();
+        }
+        let options = ListOptions {
+            limit: 2,
+            sort: SortOrder::Descending,
+            ..Default::default()
+        };
+
+        let first_page = repo.list_urls(options.clone()).await.unwrap();
+        let second_page = repo
+            .list_urls(ListOptions {
+                start_key: first_page.next_key.clone(),
+                ..options
+            })
+            .await
+            .unwrap();
+
+        let ids = |page: &Page| {
+            page.short_urls
+                .iter()
+                .map(|short_url| short_url.link_id.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(&first_page), vec!["link004", "link003"]);
+        assert_eq!(ids(&second_page), vec!["link002", "link001"]);
+    }
+
+    fn link_key(link_id: &str) -> PageKey {
+        PageKey::from([("LinkId".to_string(), link_id.to_string())])
+    }
+
+    #[tokio::test]
+    async fn when_link_status_changes_should_hide_inactive_links_from_listing() {
+        let repo = InMemoryUrlRepository::new();
+        for link_id in ["active", "disabled", "deleted"] {
+            repo.store_short_url(ShortUrl::new(link_id.into(), "https://example.com".into()))
+                .await
+                .unwrap();
+        }
+
+        let disabled = repo
+            .set_link_status("disabled", None, LinkStatus::Disabled)
+            .await
+            .unwrap();
+        repo.set_link_status("deleted", None, LinkStatus::Deleted)
+            .await
+            .unwrap();
+
+        assert_eq!(disabled.status, LinkStatus::Disabled);
+        let visible = repo.list_urls(ListOptions::default()).await.unwrap();
+        assert_eq!(visible.short_urls.len(), 1);
+        assert_eq!(visible.short_urls[0].link_id, "active");
+        let all = repo
+            .list_urls(ListOptions {
+                include_inactive: true,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(all.short_urls.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn when_link_is_deleted_or_missing_status_cannot_change() {
+        let repo = InMemoryUrlRepository::new();
+        repo.store_short_url(ShortUrl::new("abc123".into(), "https://example.com".into()))
+            .await
+            .unwrap();
+        repo.set_link_status("abc123", None, LinkStatus::Deleted)
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            repo.set_link_status("abc123", None, LinkStatus::Active)
+                .await,
+            Err(RepositoryError::NotFound(_))
+        ));
+        assert!(matches!(
+            repo.set_link_status("missing", None, LinkStatus::Disabled)
+                .await,
+            Err(RepositoryError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn when_link_belongs_to_another_owner_updates_should_report_it_missing() {
+        let repo = InMemoryUrlRepository::new();
+        repo.store_short_url(ShortUrl {
+            owner_id: Some("alice".into()),
+            ..ShortUrl::new("abc123".into(), "https://example.com".into())
+        })
+        .await
+        .unwrap();
+
+        let bob = || Some("bob".to_string());
+        assert!(matches!(
+            repo.set_link_status("abc123", bob(), LinkStatus::Disabled)
+                .await,
+            Err(RepositoryError::NotFound(_))
+        ));
+        assert!(matches!(
+            repo.update_destination("abc123", bob(), "https://example.org".into(), 0)
+                .await,
+            Err(RepositoryError::NotFound(_))
+        ));
+        assert!(matches!(
+            repo.add_tags("abc123", bob(), vec!["spring".into()]).await,
+            Err(RepositoryError::NotFound(_))
+        ));
+        assert!(matches!(
+            repo.remove_tags("abc123", bob(), vec!["spring".into()])
+                .await,
+            Err(RepositoryError::NotFound(_))
+        ));
+
+        // The owner, and admins, who pass no owner, still can
+        repo.add_tags("abc123", Some("alice".into()), vec!["spring".into()])
+            .await
+            .unwrap();
+        let disabled = repo
+            .set_link_status("abc123", None, LinkStatus::Disabled)
+            .await
+            .unwrap();
+        assert_eq!(disabled.status, LinkStatus::Disabled);
+        assert_eq!(disabled.tags, vec!["spring".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn when_password_failures_are_recorded_should_count_per_window() {
+        let repo = InMemoryUrlRepository::new();
+        repo.store_short_url(ShortUrl {
+            password: Some(LinkPassword::default()),
+            ..ShortUrl::new("abc123".into(), "https://example.com".into())
+        })
+        .await
+        .unwrap();
+        repo.store_short_url(ShortUrl::new("open".into(), "https://example.com".into()))
+            .await
+            .unwrap();
+
+        assert_eq!(repo.record_password_failure("abc123", 7).await.unwrap(), 1);
+        assert_eq!(repo.record_password_failure("abc123", 7).await.unwrap(), 2);
+        assert_eq!(repo.record_password_failure("abc123", 8).await.unwrap(), 1);
+        assert!(matches!(
+            repo.record_password_failure("open", 8).await,
+            Err(RepositoryError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn when_destination_is_updated_should_bump_version_and_keep_history() {
+        let repo = InMemoryUrlRepository::new();
+        repo.store_short_url(ShortUrl::new("abc123".into(), "https://exmaple.com".into()))
+            .await
+            .unwrap();
+
+        let updated = repo
+            .update_destination("abc123", None, "https://example.com".into(), 0)
+            .await
+            .unwrap();
+        let stale = repo
+            .update_destination("abc123", None, "https://example.org".into(), 0)
+            .await;
+
+        assert_eq!(updated.original_link, "https://example.com");
+        assert_eq!(updated.version, 1);
+        assert_eq!(updated.history.len(), 1);
+        assert_eq!(updated.history[0].original_link, "https://exmaple.com");
+        assert!(matches!(stale, Err(RepositoryError::Conflict(_))));
+        assert!(matches!(
+            repo.update_destination("missing", None, "https://example.com".into(), 0)
+                .await,
+            Err(RepositoryError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn when_listing_for_owner_should_only_page_through_their_links() {
+        let repo = InMemoryUrlRepository::new();
+        for i in 0..120 {
+            let owner_id = if i % 2 == 0 { "alice" } else { "bob" };
+            repo.store_short_url(ShortUrl {
+                owner_id: Some(owner_id.to_string()),
+                ..ShortUrl::new(format!("link-{:03}", i), "https://example.com".into())
+            })
+            .await
+            .unwrap();
+        }
+
+        let first_page = repo
+            .list_urls_for_owner("alice", ListOptions::default())
+            .await
+            .unwrap();
+        let second_page = repo
+            .list_urls_for_owner(
+                "alice",
+                ListOptions {
+                    start_key: first_page.next_key.clone(),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(first_page.short_urls.len(), 50);
+        assert_eq!(
+            first_page
+                .next_key
+                .unwrap()
+                .get("OwnerId")
+                .map(String::as_str),
+            Some("alice")
+        );
+        assert_eq!(second_page.short_urls.len(), 10);
+        assert!(second_page.next_key.is_none());
+        assert!(first_page
+            .short_urls
+            .iter()
+            .chain(second_page.short_urls.iter())
+            .all(|short_url| short_url.owner_id.as_deref() == Some("alice")));
+    }
+
+    #[tokio::test]
+    async fn when_listing_by_tag_should_only_return_that_owners_tagged_links() {
+        let repo = InMemoryUrlRepository::new();
+        for (link_id, owner_id) in [("a", "alice"), ("b", "alice"), ("c", "bob"), ("d", "alice")] {
+            repo.store_short_url(ShortUrl {
+                owner_id: Some(owner_id.to_string()),
+                ..ShortUrl::new(link_id.into(), "https://example.com".into())
+            })
+            .await
+            .unwrap();
+        }
+        for link_id in ["a", "c", "d"] {
+            repo.add_tags(link_id, None, vec!["spring".into(), "sale".into()])
+                .await
+                .unwrap();
+        }
+        let updated = repo
+            .remove_tags("d", None, vec!["spring".into()])
+            .await
+            .unwrap();
+        assert_eq!(updated.tags, vec!["sale".to_string()]);
+
+        let page = repo
+            .list_urls_for_owner(
+                "alice",
+                ListOptions {
+                    tag: Some("spring".into()),
+                    limit: 1,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        let link_ids: Vec<&str> = page.short_urls.iter().map(|u| u.link_id.as_str()).collect();
+        assert_eq!(link_ids, ["a"]);
+        assert_eq!(
+            page.next_key.unwrap().get("TagKey").map(String::as_str),
+            Some("alice#spring")
+        );
+    }
+
+    #[tokio::test]
+    async fn when_link_has_too_many_tags_or_is_deleted_tags_cannot_change() {
+        let repo = InMemoryUrlRepository::new();
+        repo.store_short_url(ShortUrl::new("abc123".into(), "https://example.com".into()))
+            .await
+            .unwrap();
+
+        let too_many: Vec<String> = (0..=MAX_TAGS).map(|i| format!("tag{}", i)).collect();
+        let result = repo.add_tags("abc123", None, too_many).await;
+        assert!(matches!(result, Err(RepositoryError::Validation(_))));
+
+        repo.set_link_status("abc123", None, LinkStatus::Deleted)
+            .await
+            .unwrap();
+        let result = repo.add_tags("abc123", None, vec!["spring".into()]).await;
+        assert!(matches!(result, Err(RepositoryError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn when_searching_should_rank_owners_matching_links_and_page_through_them() {
+        let repo = InMemoryUrlRepository::new();
+        let links = [
+            ("a", "alice", "Rust on Lambda", "Serverless Rust"),
+            (
+                "b",
+                "alice",
+                "Cooking pasta",
+                "A recipe with Rust-coloured sauce",
+            ),
+            ("c", "bob", "Rust on Lambda", "Same title, other owner"),
+            ("d", "alice", "Gardening", "Nothing to see"),
+        ];
+        for (link_id, owner_id, title, description) in links {
+            repo.store_short_url(ShortUrl {
+                owner_id: Some(owner_id.to_string()),
+                ..ShortUrl::new(link_id.into(), "https://example.com".into())
+            })
+            .await
+            .unwrap();
+            repo.add_details_to_short_url(
+                link_id.into(),
+                "https://example.com",
+                UrlDetails {
+                    title: Some(title.into()),
+                    description: Some(description.into()),
+                    content_type: Some("text/html".into()),
+                },
+            )
+            .await
+            .unwrap();
+        }
+        let options = ListOptions {
+            limit: 1,
+            ..Default::default()
+        };
+
+        let first = repo
+            .search_urls("alice", "rust lambda", options.clone())
+            .await
+            .unwrap();
+        let second = repo
+            .search_urls(
+                "alice",
+                "rust lambda",
+                ListOptions {
+                    start_key: first.next_key.clone(),
+                    ..options
+                },
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(first.short_urls[0].link_id, "a");
+        assert_eq!(second.short_urls[0].link_id, "b");
+        assert!(second.next_key.is_none());
+    }
+
+    #[tokio::test]
+    async fn when_finding_by_hash_should_match_owner_and_follow_destination_changes() {
+        let repo = InMemoryUrlRepository::new();
+        let hash = url_hash("https://example.com").unwrap();
+        for (link_id, owner_id) in [("mine", "alice"), ("theirs", "bob")] {
+            repo.store_short_url(ShortUrl {
+                owner_id: Some(owner_id.to_string()),
+                url_hash: Some(hash.clone()),
+                ..ShortUrl::new(link_id.into(), "https://example.com".into())
+            })
+            .await
+            .unwrap();
+        }
+
+        let found = repo.find_urls_by_hash("alice", &hash).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].link_id, "mine");
+
+        repo.update_destination("mine", None, "https://example.org".into(), 0)
+            .await
+            .unwrap();
+        assert!(repo
+            .find_urls_by_hash("alice", &hash)
+            .await
+            .unwrap()
+            .is_empty());
     }
 }
@@ -0,0 +1,130 @@
+use crate::jwt::{JwtError, JwtVerifier};
+use lambda_http::request::RequestContext;
+use lambda_http::{Request, RequestExt};
+
+/// Scope that lets a caller see every link instead of only their own.
+pub const ADMIN_SCOPE: &str = "links:admin";
+
+/// The authenticated caller of an HTTP request.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Caller {
+    pub owner_id: String,
+    pub is_admin: bool,
+}
+
+impl Caller {
+    /// The owner of the links this caller may change, `None` when they may
+    /// change any link.
+    pub fn owner_filter(&self) -> Option<String> {
+        (!self.is_admin).then(|| self.owner_id.clone())
+    }
+}
+
+/// Reads the caller from the API Gateway authorizer context.
+///
+/// JWT authorizers provide the `sub` claim and the granted scopes, Lambda
+/// authorizers an `owner_id` and an optional `is_admin` flag.
+/// Returns `None` when the request was not authenticated.
+pub fn caller_from_request(event: &Request) -> Option<Caller> {
+    let authorizer = match event.request_context_ref()? {
+        RequestContext::ApiGatewayV2(context) => context.authorizer.as_ref()?,
+        _ => return None,
+    };
+
+    if let Some(jwt) = &authorizer.jwt {
+        let owner_id = jwt.claims.get("sub").filter(|sub| !sub.is_empty())?;
+        // HTTP APIs only fill `scopes` for some token types, the raw claim is always there
+        let is_admin = jwt
+            .scopes
+            .iter()
+            .flatten()
+            .map(String::as_str)
+            .chain(
+                jwt.claims
+                    .get("scope")
+                    .into_iter()
+                    .flat_map(|s| s.split(' ')),
+            )
+            .any(|scope| scope == ADMIN_SCOPE);
+        return Some(Caller {
+            owner_id: owner_id.clone(),
+            is_admin,
+        });
+    }
+
+    let owner_id = authorizer.fields.get("owner_id")?.as_str()?;
+    Some(Caller {
+        owner_id: owner_id.to_string(),
+        is_admin: authorizer
+            .fields
+            .get("is_admin")
+            .and_then(|is_admin| is_admin.as_bool())
+            .unwrap_or_default(),
+    })
+}
+
+/// The caller of a request, from the authorizer context when API Gateway
+/// authenticated it, otherwise from its bearer token, checked by `verifier`.
+pub async fn authenticate_caller(
+    event: &Request,
+    verifier: Option<&JwtVerifier>,
+) -> Result<Caller, JwtError> {
+    if let Some(caller) = caller_from_request(event) {
+        return Ok(caller);
+    }
+    match verifier {
+        Some(verifier) => verifier.caller(event).await,
+        None => Err(JwtError::Missing),
+    }
+}
+
+/// Builds the request context a JWT authorizer would attach, for handler tests.
+#[cfg(any(test, feature = "mocks"))]
+pub fn jwt_request_context(sub: &str, scopes: &[&str]) -> RequestContext {
+    use lambda_http::aws_lambda_events::apigw::{
+        ApiGatewayRequestAuthorizer, ApiGatewayRequestAuthorizerJwtDescription,
+        ApiGatewayV2httpRequestContext,
+    };
+
+    RequestContext::ApiGatewayV2(ApiGatewayV2httpRequestContext {
+        authorizer: Some(ApiGatewayRequestAuthorizer {
+            jwt: Some(ApiGatewayRequestAuthorizerJwtDescription {
+                claims: [
+                    ("sub".to_string(), sub.to_string()),
+                    ("scope".to_string(), scopes.join(" ")),
+                ]
+                .into(),
+                scopes: None,
+            }),
+            ..Default::default()
+        }),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{caller_from_request, jwt_request_context, Caller, ADMIN_SCOPE};
+    use lambda_http::{Body, Request, RequestExt};
+
+    #[test]
+    fn when_jwt_authorizer_ran_should_read_sub_and_admin_scope() {
+        let request = Request::new(Body::Empty)
+            .with_request_context(jwt_request_context("user-1", &["openid", ADMIN_SCOPE]));
+
+        assert_eq!(
+            caller_from_request(&request),
+            Some(Caller {
+                owner_id: "user-1".to_string(),
+                is_admin: true,
+            })
+        );
+    }
+
+    #[test]
+    fn when_request_is_not_authenticated_should_return_none() {
+        let request = Request::new(Body::Empty);
+
+        assert_eq!(caller_from_request(&request), None);
+    }
+}
@@ -1,15 +1,43 @@
+use crate::api_key::ApiKey;
+use crate::domain_policy::DomainPolicy;
 use aws_sdk_ssm::Client;
 use figment::providers::{Env, Format, Json, Serialized};
 use figment::Figment;
 use serde::{Deserialize, Serialize};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use thiserror::Error;
 
 #[derive(Default, Debug, Serialize, Deserialize)]
 pub struct Configuration {
     pub table_name: String,
+    /// Destination domains links may point to, e.g.
+    /// `{"domain_policy": {"deny": ["*.phish.example"]}}` in the SSM parameter.
+    #[serde(default)]
+    pub domain_policy: DomainPolicy,
+    /// Keys mutating endpoints accept, best kept in the Secrets Manager secret,
+    /// e.g. `{"api_keys": [{"label": "ci-2026-10", "key": "..."}]}`. Without
+    /// any, those endpoints do not ask for one.
+    #[serde(default)]
+    pub api_keys: Vec<ApiKey>,
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ConfigurationError {
+    /// A configured source could not be read, or what it holds is not valid.
+    /// Going on without it would drop, say, the API keys and let every request in.
+    #[error("the configuration could not be loaded")]
+    Unavailable,
 }
 
 impl Configuration {
-    pub async fn load(ssm_client: &Client, secret_client: &aws_sdk_secretsmanager::Client) -> Self {
+    /// Loads the configuration, failing when a configured source cannot be
+    /// read rather than returning one that lacks what it holds.
+    pub async fn load(
+        ssm_client: &Client,
+        secret_client: &aws_sdk_secretsmanager::Client,
+    ) -> Result<Self, ConfigurationError> {
+        let mut complete = true;
         let mut config = Figment::from(Serialized::defaults(Configuration::default()))
             // .merge fills in any missing values from the environment
             .merge(Env::prefixed("APP_"));
@@ -17,7 +45,10 @@ impl Configuration {
         let ssm_configuration = Configuration::load_from_ssm(ssm_client).await;
         config = match ssm_configuration {
             Ok(ssm_config) => config.merge(Json::string(&ssm_config)),
-            Err(_) => config,
+            Err(_) => {
+                complete &= !Configuration::is_set("CONFIGURATION_PARAMETER_NAME");
+                config
+            }
         };
 
         let secret_manager_configuration =
@@ -26,23 +57,34 @@ impl Configuration {
             Ok(secret_config) => config
                 // .join overrides any existing values with new values from this JSON
                 .join(Json::string(&secret_config)),
-            Err(_) => config,
+            Err(_) => {
+                complete &= !Configuration::is_set("SECRET_MANAGER_SECRET_ID");
+                config
+            }
         };
 
         let config = config.extract();
 
         match config {
-            Ok(config) => {
+            Ok(config) if complete => {
                 println!("{}", config);
-                config
+                Ok(config)
+            }
+            Ok(_) => {
+                eprintln!("Failed to read a configured configuration source");
+                Err(ConfigurationError::Unavailable)
             }
             Err(e) => {
                 eprintln!("Failed to load configuration: {:?}", e);
-                Configuration::default()
+                Err(ConfigurationError::Unavailable)
             }
         }
     }
 
+    fn is_set(variable: &str) -> bool {
+        std::env::var(variable).is_ok_and(|value| !value.is_empty())
+    }
+
     async fn load_from_ssm(ssm_client: &Client) -> Result<String, ()> {
         let configuration_ssm_parameter_name = std::env::var("CONFIGURATION_PARAMETER_NAME");
 
@@ -75,10 +117,7 @@ impl Configuration {
     ) -> Result<String, ()> {
         let configuration_secret_id = std::env::var("SECRET_MANAGER_SECRET_ID");
 
-        let configuration_secret_id = match configuration_secret_id {
-            Ok(name) => name,
-            Err(_) => String::new(),
-        };
+        let configuration_secret_id = configuration_secret_id.unwrap_or_default();
         if !configuration_secret_id.is_empty() {
             let secret_value = secret_client
                 .get_secret_value()
@@ -96,6 +135,81 @@ impl Configuration {
     }
 }
 
+/// The configuration, loaded again once it is older than `max_age` so changes
+/// to the SSM parameter reach running functions without a redeploy.
+pub struct ConfigurationCache {
+    clients: Option<(Client, aws_sdk_secretsmanager::Client)>,
+    max_age: Duration,
+    /// `None` until a configuration could be loaded.
+    current: RwLock<(Instant, Option<Arc<Configuration>>)>,
+}
+
+impl ConfigurationCache {
+    pub async fn load(
+        ssm_client: Client,
+        secret_client: aws_sdk_secretsmanager::Client,
+        max_age: Duration,
+    ) -> Self {
+        let configuration = Configuration::load(&ssm_client, &secret_client)
+            .await
+            .ok()
+            .map(Arc::new);
+        Self {
+            clients: Some((ssm_client, secret_client)),
+            max_age,
+            current: RwLock::new((Instant::now(), configuration)),
+        }
+    }
+
+    /// A configuration that is never reloaded.
+    pub fn fixed(configuration: Configuration) -> Self {
+        Self {
+            clients: None,
+            max_age: Duration::MAX,
+            current: RwLock::new((Instant::now(), Some(Arc::new(configuration)))),
+        }
+    }
+
+    /// A configuration that could not be loaded, for handler tests.
+    #[cfg(any(test, feature = "mocks"))]
+    pub fn unavailable() -> Self {
+        Self {
+            clients: None,
+            max_age: Duration::MAX,
+            current: RwLock::new((Instant::now(), None)),
+        }
+    }
+
+    /// The current configuration, reloading it first when it is too old. If a
+    /// source cannot be read, the previous configuration is kept until the next
+    /// reload, rather than one missing, say, the domains blocked. Until one
+    /// could be loaded at all, every call tries again and fails when it cannot.
+    pub async fn current(&self) -> Result<Arc<Configuration>, ConfigurationError> {
+        let (loaded_at, previous) = self.current.read().unwrap().clone();
+        let Some((ssm_client, secret_client)) = &self.clients else {
+            return previous.ok_or(ConfigurationError::Unavailable);
+        };
+        if let Some(configuration) = &previous {
+            if loaded_at.elapsed() < self.max_age {
+                return Ok(configuration.clone());
+            }
+        }
+        let configuration = match Configuration::load(ssm_client, secret_client).await {
+            Ok(reloaded) => Arc::new(reloaded),
+            Err(e) => {
+                let Some(configuration) = previous else {
+                    tracing::error!("Failed to load configuration: {}", e);
+                    return Err(e);
+                };
+                tracing::warn!("Failed to reload configuration, keeping the previous one");
+                configuration
+            }
+        };
+        *self.current.write().unwrap() = (Instant::now(), Some(configuration.clone()));
+        Ok(configuration)
+    }
+}
+
 impl std::fmt::Display for Configuration {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "Configuration loaded successfully",)
@@ -103,13 +217,14 @@ impl std::fmt::Display for Configuration {
 }
 
 #[cfg(test)]
+#[allow(clippy::result_large_err)] // figment::Jail returns a large figment::Error
 mod tests {
     use figment::{
         providers::{Env, Format, Json},
         Figment,
     };
 
-    use crate::configuration::Configuration;
+    use crate::configuration::{Configuration, ConfigurationCache, ConfigurationError};
 
     #[tokio::test]
     async fn when_valid_configuration_should_load() {
@@ -130,6 +245,18 @@ mod tests {
         });
     }
 
+    #[tokio::test]
+    async fn when_configuration_could_not_be_loaded_should_refuse_to_hand_out_a_default() {
+        assert_eq!(
+            ConfigurationCache::unavailable().current().await.err(),
+            Some(ConfigurationError::Unavailable)
+        );
+        assert!(ConfigurationCache::fixed(Configuration::default())
+            .current()
+            .await
+            .is_ok());
+    }
+
     #[tokio::test]
     async fn when_valid_configuration_should_load_join_overrides() {
         figment::Jail::expect_with(|jail| {
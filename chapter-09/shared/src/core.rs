@@ -1,37 +1,269 @@
+use crate::password::LinkPassword;
+use crate::rules::Device;
 use crate::url_info::UrlDetails;
 use async_trait::async_trait;
 use cuid2::CuidConstructor;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fmt::Debug;
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
 
 #[cfg(any(test, feature = "mocks"))]
 use mockall::{automock, predicate::*};
 
+/// Errors returned by `UrlRepository` and `UrlInfo` implementations.
+///
+/// The variants describe what the caller can do about the failure rather than
+/// where it came from, so handlers can pick a status code without knowing which
+/// backend produced it.
+#[derive(Debug, Error, PartialEq)]
+pub enum RepositoryError {
+    #[error("not found: {0}")]
+    NotFound(String),
+    #[error("already exists: {0}")]
+    AlreadyExists(String),
+    /// The item changed since the caller read it.
+    #[error("conflict: {0}")]
+    Conflict(String),
+    #[error("throttled: {0}")]
+    Throttled(String),
+    #[error("validation failed: {0}")]
+    Validation(String),
+    #[error("transient failure: {0}")]
+    Transient(String),
+    #[error("fatal failure: {0}")]
+    Fatal(String),
+}
+
+impl RepositoryError {
+    /// Whether retrying the same operation later may succeed.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            RepositoryError::Throttled(_) | RepositoryError::Transient(_)
+        )
+    }
+}
+
 #[cfg_attr(any(test, feature = "mocks"), automock)]
 #[async_trait]
 pub trait UrlRepository: Debug {
-    async fn get_url_from_short_link(&self, short_link: &str) -> Result<Option<ShortUrl>, String>;
-    async fn store_short_url(
+    async fn get_url_from_short_link(
+        &self,
+        short_link: &str,
+    ) -> Result<Option<ShortUrl>, RepositoryError>;
+    async fn store_short_url(&self, short_url: ShortUrl) -> Result<ShortUrl, RepositoryError>;
+    /// Stores many links at once and returns the ones that were written.
+    ///
+    /// Unlike `store_short_url` this does not check that ids are unused, so it is
+    /// meant for freshly generated ids. Links that still could not be written
+    /// after retrying are left out of the result; it only fails when none were.
+    async fn store_short_urls(
+        &self,
+        short_urls: Vec<ShortUrl>,
+    ) -> Result<Vec<ShortUrl>, RepositoryError>;
+    /// Replaces the details of a link with the ones scraped from `original_link`,
+    /// removing those the page no longer has. Fails with `Conflict` once the link
+    /// points somewhere else, so details of an old destination are not kept.
+    async fn add_details_to_short_url(
         &self,
-        url_to_shorten: String,
         short_link: String,
-    ) -> Result<ShortUrl, String>;
+        original_link: &str,
+        url_details: UrlDetails,
+    ) -> Result<(), RepositoryError>;
+    async fn increment_clicks(&self, short_link: &str, n: u64) -> Result<(), RepositoryError>;
+    /// Adds `n` clicks to the target at index `variant` of an A/B link.
+    async fn increment_target_clicks(
+        &self,
+        short_link: &str,
+        variant: usize,
+        n: u64,
+    ) -> Result<(), RepositoryError>;
+    /// Points a link at a new destination, keeping the old one in its history.
+    /// Fails with `Conflict` when the link's version is no longer `expected_version`.
+    ///
+    /// This and the other updates below take the owner the link must have, or
+    /// `None` for admins. Links of other owners are reported as `NotFound`.
+    async fn update_destination(
+        &self,
+        short_link: &str,
+        owner_id: Option<String>,
+        original_link: String,
+        expected_version: u64,
+    ) -> Result<ShortUrl, RepositoryError>;
+    /// Counts a wrong password for a protected link in the failure window `window`,
+    /// starting over when the previous failure was in an earlier window.
+    /// Returns the failures of the window so far.
+    async fn record_password_failure(
+        &self,
+        short_link: &str,
+        window: u64,
+    ) -> Result<u32, RepositoryError>;
+    /// Moves a link to a new status and returns the updated link.
+    /// Deleted links cannot change status anymore and are reported as `NotFound`.
+    async fn set_link_status(
+        &self,
+        short_link: &str,
+        owner_id: Option<String>,
+        status: LinkStatus,
+    ) -> Result<ShortUrl, RepositoryError>;
+    /// Adds tags to a link and returns the updated link.
+    /// Deleted links are reported as `NotFound`, like for `set_link_status`.
+    async fn add_tags(
+        &self,
+        short_link: &str,
+        owner_id: Option<String>,
+        tags: Vec<String>,
+    ) -> Result<ShortUrl, RepositoryError>;
+    /// Removes tags from a link and returns the updated link. Unknown tags are ignored.
+    async fn remove_tags(
+        &self,
+        short_link: &str,
+        owner_id: Option<String>,
+        tags: Vec<String>,
+    ) -> Result<ShortUrl, RepositoryError>;
+    /// Finds the active links of `owner_id` whose destination normalises to `url_hash`.
+    async fn find_urls_by_hash(
+        &self,
+        owner_id: &str,
+        url_hash: &str,
+    ) -> Result<Vec<ShortUrl>, RepositoryError>;
+    /// Finds the links of `owner_id` whose title or description match `query`,
+    /// best matches first. Only `start_key`, `limit` and `include_inactive` apply.
+    async fn search_urls(
+        &self,
+        owner_id: &str,
+        query: &str,
+        options: ListOptions,
+    ) -> Result<Page, RepositoryError>;
+    /// Lists a page of all links, regardless of owner. Meant for admins only.
+    /// Disabled and deleted links are skipped unless `include_inactive` is set,
+    /// so a page can hold fewer items than the limit. The order is unspecified.
+    async fn list_urls(&self, options: ListOptions) -> Result<Page, RepositoryError>;
+    /// Lists a page of the links created by `owner_id`, sorted by link id.
+    /// With `options.tag` set, the page comes from the tag index instead.
+    async fn list_urls_for_owner(
+        &self,
+        owner_id: &str,
+        options: ListOptions,
+    ) -> Result<Page, RepositoryError>;
+}
+
+/// Lets a function pick its repository at start up, see `adapters::url_repository`.
+#[async_trait]
+impl<R: UrlRepository + Send + Sync + ?Sized> UrlRepository for Box<R> {
+    async fn get_url_from_short_link(
+        &self,
+        short_link: &str,
+    ) -> Result<Option<ShortUrl>, RepositoryError> {
+        (**self).get_url_from_short_link(short_link).await
+    }
+    async fn store_short_url(&self, short_url: ShortUrl) -> Result<ShortUrl, RepositoryError> {
+        (**self).store_short_url(short_url).await
+    }
+    async fn store_short_urls(
+        &self,
+        short_urls: Vec<ShortUrl>,
+    ) -> Result<Vec<ShortUrl>, RepositoryError> {
+        (**self).store_short_urls(short_urls).await
+    }
     async fn add_details_to_short_url(
         &self,
         short_link: String,
+        original_link: &str,
         url_details: UrlDetails,
-    ) -> Result<(), String>;
-    async fn increment_clicks(&self, short_link: &str, n: u64) -> Result<(), String>;
-    async fn list_urls(
+    ) -> Result<(), RepositoryError> {
+        (**self)
+            .add_details_to_short_url(short_link, original_link, url_details)
+            .await
+    }
+    async fn increment_clicks(&self, short_link: &str, n: u64) -> Result<(), RepositoryError> {
+        (**self).increment_clicks(short_link, n).await
+    }
+    async fn increment_target_clicks(
         &self,
-        last_evaluated_id: Option<String>,
-    ) -> Result<(Vec<ShortUrl>, Option<String>), String>;
+        short_link: &str,
+        variant: usize,
+        n: u64,
+    ) -> Result<(), RepositoryError> {
+        (**self)
+            .increment_target_clicks(short_link, variant, n)
+            .await
+    }
+    async fn update_destination(
+        &self,
+        short_link: &str,
+        owner_id: Option<String>,
+        original_link: String,
+        expected_version: u64,
+    ) -> Result<ShortUrl, RepositoryError> {
+        (**self)
+            .update_destination(short_link, owner_id, original_link, expected_version)
+            .await
+    }
+    async fn record_password_failure(
+        &self,
+        short_link: &str,
+        window: u64,
+    ) -> Result<u32, RepositoryError> {
+        (**self).record_password_failure(short_link, window).await
+    }
+    async fn set_link_status(
+        &self,
+        short_link: &str,
+        owner_id: Option<String>,
+        status: LinkStatus,
+    ) -> Result<ShortUrl, RepositoryError> {
+        (**self).set_link_status(short_link, owner_id, status).await
+    }
+    async fn add_tags(
+        &self,
+        short_link: &str,
+        owner_id: Option<String>,
+        tags: Vec<String>,
+    ) -> Result<ShortUrl, RepositoryError> {
+        (**self).add_tags(short_link, owner_id, tags).await
+    }
+    async fn remove_tags(
+        &self,
+        short_link: &str,
+        owner_id: Option<String>,
+        tags: Vec<String>,
+    ) -> Result<ShortUrl, RepositoryError> {
+        (**self).remove_tags(short_link, owner_id, tags).await
+    }
+    async fn find_urls_by_hash(
+        &self,
+        owner_id: &str,
+        url_hash: &str,
+    ) -> Result<Vec<ShortUrl>, RepositoryError> {
+        (**self).find_urls_by_hash(owner_id, url_hash).await
+    }
+    async fn search_urls(
+        &self,
+        owner_id: &str,
+        query: &str,
+        options: ListOptions,
+    ) -> Result<Page, RepositoryError> {
+        (**self).search_urls(owner_id, query, options).await
+    }
+    async fn list_urls(&self, options: ListOptions) -> Result<Page, RepositoryError> {
+        (**self).list_urls(options).await
+    }
+    async fn list_urls_for_owner(
+        &self,
+        owner_id: &str,
+        options: ListOptions,
+    ) -> Result<Page, RepositoryError> {
+        (**self).list_urls_for_owner(owner_id, options).await
+    }
 }
 
 #[cfg_attr(any(test, feature = "mocks"), automock)]
 #[async_trait]
 pub trait UrlInfo: Debug {
-    async fn fetch_details(&self, url: &str) -> Result<UrlDetails, String>;
+    async fn fetch_details(&self, url: &str) -> Result<UrlDetails, RepositoryError>;
 }
 
 #[cfg_attr(any(test, feature = "mocks"), automock)]
@@ -43,6 +275,12 @@ pub struct CuidGenerator {
     gen: CuidConstructor,
 }
 
+impl Default for CuidGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl CuidGenerator {
     pub fn new() -> Self {
         Self {
@@ -57,14 +295,236 @@ impl IdGenerator for CuidGenerator {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkStatus {
+    #[default]
+    Active,
+    Disabled,
+    Deleted,
+}
+
+impl LinkStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LinkStatus::Active => "active",
+            LinkStatus::Disabled => "disabled",
+            LinkStatus::Deleted => "deleted",
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        *self == LinkStatus::Active
+    }
+}
+
+impl std::str::FromStr for LinkStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "active" => Ok(LinkStatus::Active),
+            "disabled" => Ok(LinkStatus::Disabled),
+            "deleted" => Ok(LinkStatus::Deleted),
+            _ => Err(format!("Unknown link status '{}'", s)),
+        }
+    }
+}
+
+/// The status code a link redirects with, serialised as the code itself.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "u16", into = "u16")]
+pub enum RedirectType {
+    MovedPermanently,
+    #[default]
+    Found,
+    TemporaryRedirect,
+    PermanentRedirect,
+}
+
+impl RedirectType {
+    pub fn status_code(&self) -> u16 {
+        match self {
+            RedirectType::MovedPermanently => 301,
+            RedirectType::Found => 302,
+            RedirectType::TemporaryRedirect => 307,
+            RedirectType::PermanentRedirect => 308,
+        }
+    }
+
+    /// Permanent redirects may be cached by browsers and proxies.
+    pub fn is_permanent(&self) -> bool {
+        matches!(
+            self,
+            RedirectType::MovedPermanently | RedirectType::PermanentRedirect
+        )
+    }
+
+    /// 307 and 308 make the client repeat the request method on the destination.
+    pub fn preserves_method(&self) -> bool {
+        matches!(
+            self,
+            RedirectType::TemporaryRedirect | RedirectType::PermanentRedirect
+        )
+    }
+
+    pub fn is_found(&self) -> bool {
+        *self == RedirectType::Found
+    }
+}
+
+impl TryFrom<u16> for RedirectType {
+    type Error = String;
+
+    fn try_from(status_code: u16) -> Result<Self, Self::Error> {
+        match status_code {
+            301 => Ok(RedirectType::MovedPermanently),
+            302 => Ok(RedirectType::Found),
+            307 => Ok(RedirectType::TemporaryRedirect),
+            308 => Ok(RedirectType::PermanentRedirect),
+            _ => Err(format!(
+                "Unsupported redirect status {}, expected 301, 302, 307 or 308",
+                status_code
+            )),
+        }
+    }
+}
+
+impl From<RedirectType> for u16 {
+    fn from(redirect_type: RedirectType) -> Self {
+        redirect_type.status_code()
+    }
+}
+
+/// What happens to the query string a visitor adds to the short link.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QueryMode {
+    #[default]
+    Ignore,
+    /// The visitor's parameters are added to the destination's.
+    Merge,
+}
+
+impl QueryMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QueryMode::Ignore => "ignore",
+            QueryMode::Merge => "merge",
+        }
+    }
+
+    pub fn is_ignore(&self) -> bool {
+        *self == QueryMode::Ignore
+    }
+}
+
+impl std::str::FromStr for QueryMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ignore" => Ok(QueryMode::Ignore),
+            "merge" => Ok(QueryMode::Merge),
+            _ => Err(format!("Unknown query mode '{}'", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct ShortUrl {
     pub link_id: String,
     pub original_link: String,
+    /// The authenticated caller that created the link.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner_id: Option<String>,
+    /// Hash of the normalised `original_link`, see `normalise::url_hash`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url_hash: Option<String>,
     pub clicks: u32,
     pub title: Option<String>,
     pub description: Option<String>,
     pub content_type: Option<String>,
+    /// Seconds since the Unix epoch after which the link stops redirecting.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_clicks: Option<u32>,
+    #[serde(default, skip_serializing_if = "LinkStatus::is_active")]
+    pub status: LinkStatus,
+    /// Bumped on every destination change, used as an optimistic lock.
+    #[serde(default)]
+    pub version: u64,
+    /// Previous destinations, oldest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<DestinationChange>,
+    /// Sorted and without duplicates, like the string set it is stored as.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Set on protected links. Never serialised, so it stays out of responses and events.
+    #[serde(skip)]
+    pub password: Option<LinkPassword>,
+    /// Destinations that split the traffic instead of `original_link`, see `targets::choose_target`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub targets: Vec<Target>,
+    /// Checked in order before anything else, see `rules::first_match`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<RedirectRule>,
+    #[serde(default, skip_serializing_if = "QueryMode::is_ignore")]
+    pub query_mode: QueryMode,
+    /// Whether added parameters replace the ones of the same name already in
+    /// the destination, instead of being dropped.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub override_query: bool,
+    /// UTM parameters added on every redirect, see `query::redirect_location`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub utm: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "RedirectType::is_found")]
+    pub redirect_type: RedirectType,
+    /// Always show the preview page before redirecting, for untrusted destinations.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub interstitial: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct DestinationChange {
+    pub original_link: String,
+    /// Seconds since the Unix epoch when this destination was replaced.
+    pub replaced_at: u64,
+}
+
+/// One variant of an A/B split, with its share of the traffic.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Target {
+    pub url: String,
+    pub weight: u32,
+    /// Visits sent to this variant, counted by `increment_target_clicks`.
+    #[serde(default)]
+    pub clicks: u64,
+}
+
+/// Sends visitors matching every condition it sets to its own destination.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct RedirectRule {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<Device>,
+    /// ISO 3166-1 alpha-2 country codes.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub countries: Vec<String>,
+    /// Language tags, matched against the visitor's preferred language.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub languages: Vec<String>,
+    pub destination: String,
+}
+
+/// The payload of a LinkClicked event: the link and, for A/B links, the index
+/// of the target the visitor was sent to.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LinkClicked {
+    #[serde(flatten)]
+    pub short_url: ShortUrl,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variant: Option<usize>,
 }
 
 impl ShortUrl {
@@ -72,10 +532,7 @@ impl ShortUrl {
         Self {
             link_id,
             original_link,
-            clicks: 0,
-            title: None,
-            description: None,
-            content_type: None,
+            ..Default::default()
         }
     }
     pub fn with_details(
@@ -93,12 +550,94 @@ impl ShortUrl {
             title,
             description,
             content_type,
+            ..Default::default()
+        }
+    }
+
+    /// Whether the link belongs to `owner_id`, always true for `None`, see `Caller::owner_filter`.
+    pub fn belongs_to(&self, owner_id: Option<&str>) -> bool {
+        owner_id.is_none_or(|owner_id| self.owner_id.as_deref() == Some(owner_id))
+    }
+
+    /// Whether anything besides `original_link` decides where visitors end
+    /// up or what they go through on the way.
+    pub fn has_options(&self) -> bool {
+        self.password.is_some()
+            || !self.targets.is_empty()
+            || !self.rules.is_empty()
+            || !self.query_mode.is_ignore()
+            || self.override_query
+            || !self.utm.is_empty()
+            || !self.redirect_type.is_found()
+            || self.interstitial
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|expires_at| now >= expires_at)
+    }
+
+    /// Clicks are counted asynchronously, so a link can overshoot its
+    /// `max_clicks` by the visits that are still in flight.
+    pub fn is_exhausted(&self) -> bool {
+        self.max_clicks
+            .is_some_and(|max_clicks| self.clicks >= max_clicks)
+    }
+}
+
+/// Current time in seconds since the Unix epoch, the format DynamoDB TTL expects.
+pub fn epoch_seconds() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+pub const MAX_PAGE_SIZE: usize = 100;
+
+/// Where a listing stopped: the full `LastEvaluatedKey` of the table or index
+/// that was read, including index keys. Every key attribute is a string.
+pub type PageKey = BTreeMap<String, String>;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Ascending,
+    Descending,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListOptions {
+    pub start_key: Option<PageKey>,
+    pub limit: usize,
+    pub include_inactive: bool,
+    pub sort: SortOrder,
+    /// Only list links carrying this tag.
+    pub tag: Option<String>,
+}
+
+impl Default for ListOptions {
+    fn default() -> Self {
+        Self {
+            start_key: None,
+            limit: DEFAULT_PAGE_SIZE,
+            include_inactive: false,
+            sort: SortOrder::default(),
+            tag: None,
         }
     }
 }
 
+#[derive(Debug, Clone, Default)]
+pub struct Page {
+    pub short_urls: Vec<ShortUrl>,
+    /// Set when the listing may continue after this page.
+    pub next_key: Option<PageKey>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct ListShortUrlsResponse {
-    short_urls: Vec<ShortUrl>,
-    last_evaluated_id: Option<String>,
+    pub short_urls: Vec<ShortUrl>,
+    pub next_cursor: Option<String>,
 }
@@ -0,0 +1,186 @@
+use crate::core::{PageKey, SortOrder};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use thiserror::Error;
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum CursorError {
+    #[error("cursor is malformed")]
+    Malformed,
+    #[error("cursor signature is invalid")]
+    InvalidSignature,
+    #[error("cursor belongs to another query")]
+    OtherQuery,
+}
+
+/// The query a cursor pages through. A key of one listing means nothing to
+/// another, so a cursor is only accepted back with the query it came from.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CursorQuery {
+    /// The owner whose links are listed, `None` when all links are.
+    pub owner_id: Option<String>,
+    pub sort: SortOrder,
+    pub tag: Option<String>,
+    /// The search query, for search results.
+    pub search: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Payload {
+    key: PageKey,
+    query: CursorQuery,
+}
+
+/// Turns a `PageKey` into an opaque pagination cursor and back.
+///
+/// A cursor is `<payload>.<signature>`, both base64url encoded: the payload is
+/// the key and the query as JSON and the signature an HMAC-SHA256 of the
+/// payload, so clients can neither read internal keys easily nor forge a
+/// position, nor resume one query with another's cursor.
+#[derive(Clone)]
+pub struct CursorSigner {
+    key: Vec<u8>,
+}
+
+impl CursorSigner {
+    pub fn new(key: impl Into<Vec<u8>>) -> Self {
+        Self { key: key.into() }
+    }
+
+    pub fn encode(&self, page_key: &PageKey, query: &CursorQuery) -> String {
+        let payload = Payload {
+            key: page_key.clone(),
+            query: query.clone(),
+        };
+        // Serializing maps and options of strings cannot fail
+        let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&payload).unwrap_or_default());
+        let signature = URL_SAFE_NO_PAD.encode(self.mac(&payload).finalize().into_bytes());
+        format!("{}.{}", payload, signature)
+    }
+
+    pub fn decode(&self, cursor: &str, query: &CursorQuery) -> Result<PageKey, CursorError> {
+        let (payload, signature) = cursor.split_once('.').ok_or(CursorError::Malformed)?;
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| CursorError::Malformed)?;
+        self.mac(payload)
+            .verify_slice(&signature)
+            .map_err(|_| CursorError::InvalidSignature)?;
+        let payload = URL_SAFE_NO_PAD
+            .decode(payload)
+            .map_err(|_| CursorError::Malformed)?;
+        let payload: Payload =
+            serde_json::from_slice(&payload).map_err(|_| CursorError::Malformed)?;
+        if payload.query != *query {
+            return Err(CursorError::OtherQuery);
+        }
+        Ok(payload.key)
+    }
+
+    fn mac(&self, payload: &str) -> HmacSha256 {
+        let mut mac =
+            HmacSha256::new_from_slice(&self.key).expect("HMAC can take a key of any size");
+        mac.update(payload.as_bytes());
+        mac
+    }
+}
+
+impl std::fmt::Debug for CursorSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CursorSigner").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CursorError, CursorQuery, CursorSigner};
+    use crate::core::{PageKey, SortOrder};
+
+    fn page_key() -> PageKey {
+        PageKey::from([
+            ("LinkId".to_string(), "abc123".to_string()),
+            ("OwnerId".to_string(), "user-1".to_string()),
+        ])
+    }
+
+    fn query() -> CursorQuery {
+        CursorQuery {
+            owner_id: Some("user-1".into()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn when_cursor_is_round_tripped_should_keep_the_full_key() {
+        let signer = CursorSigner::new("secret");
+
+        let cursor = signer.encode(&page_key(), &query());
+
+        assert!(!cursor.contains("abc123"));
+        assert_eq!(signer.decode(&cursor, &query()), Ok(page_key()));
+    }
+
+    #[test]
+    fn when_cursor_is_tampered_with_should_be_rejected() {
+        let signer = CursorSigner::new("secret");
+        let cursor = signer.encode(&page_key(), &query());
+        let (_, signature) = cursor.split_once('.').unwrap();
+        let forged_key = PageKey::from([("LinkId".to_string(), "zzz".to_string())]);
+        let forged_payload = CursorSigner::new("other").encode(&forged_key, &query());
+        let (forged_payload, _) = forged_payload.split_once('.').unwrap();
+
+        assert_eq!(
+            signer.decode(&format!("{}.{}", forged_payload, signature), &query()),
+            Err(CursorError::InvalidSignature)
+        );
+        assert_eq!(
+            CursorSigner::new("other").decode(&cursor, &query()),
+            Err(CursorError::InvalidSignature)
+        );
+        assert_eq!(
+            signer.decode("not-a-cursor", &query()),
+            Err(CursorError::Malformed)
+        );
+    }
+
+    #[test]
+    fn when_cursor_is_used_with_another_query_should_be_rejected() {
+        let signer = CursorSigner::new("secret");
+        let cursor = signer.encode(&page_key(), &query());
+
+        for other in [
+            CursorQuery {
+                owner_id: Some("user-2".into()),
+                ..query()
+            },
+            CursorQuery {
+                owner_id: None,
+                ..query()
+            },
+            CursorQuery {
+                sort: SortOrder::Descending,
+                ..query()
+            },
+            CursorQuery {
+                tag: Some("spring".into()),
+                ..query()
+            },
+            CursorQuery {
+                search: Some("spring sale".into()),
+                ..query()
+            },
+        ] {
+            assert_eq!(
+                signer.decode(&cursor, &other),
+                Err(CursorError::OtherQuery),
+                "{:?}",
+                other
+            );
+        }
+    }
+}
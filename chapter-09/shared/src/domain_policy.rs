@@ -0,0 +1,234 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+use url::{Host, Url};
+
+#[derive(Debug, Error, PartialEq)]
+pub enum DomainPolicyError {
+    #[error("'{0}' is not a domain or a *.domain wildcard")]
+    InvalidPattern(String),
+    #[error("'{0}' would match every site under a public suffix")]
+    PublicSuffix(String),
+    #[error("domain '{0}' is blocked")]
+    Blocked(String),
+    #[error("domain '{0}' is not on the allow list")]
+    NotAllowed(String),
+}
+
+/// An entry of the allow or deny list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DomainPattern {
+    /// `example.com`, that host only.
+    Exact(String),
+    /// `*.example.com`, any host below example.com, but not example.com itself.
+    Subdomains(String),
+}
+
+impl DomainPattern {
+    /// `host` is expected lower case and without a trailing dot, as `Url` gives it.
+    pub fn matches(&self, host: &str) -> bool {
+        match self {
+            DomainPattern::Exact(domain) => host == domain,
+            DomainPattern::Subdomains(domain) => host
+                .strip_suffix(domain.as_str())
+                .is_some_and(|rest| rest.ends_with('.')),
+        }
+    }
+}
+
+impl FromStr for DomainPattern {
+    type Err = DomainPolicyError;
+
+    /// Patterns are checked against the public suffix list: `*.co.uk` or
+    /// `*.github.io` would match sites of unrelated owners, so they are refused
+    /// rather than blocking or allowing all of them. Unknown top-level domains
+    /// count as public suffixes too.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let pattern = s.trim().trim_end_matches('.').to_ascii_lowercase();
+        let (wildcard, domain) = match pattern.strip_prefix("*.") {
+            Some(domain) => (true, domain),
+            None => (false, pattern.as_str()),
+        };
+        // Host parsing turns internationalised names into the punycode URLs use
+        let domain = match Host::parse(domain) {
+            Ok(Host::Domain(domain)) if !domain.contains('*') => domain,
+            _ => return Err(DomainPolicyError::InvalidPattern(s.to_string())),
+        };
+        if psl::suffix_str(&domain) == Some(domain.as_str()) {
+            return Err(DomainPolicyError::PublicSuffix(s.to_string()));
+        }
+        Ok(if wildcard {
+            DomainPattern::Subdomains(domain)
+        } else {
+            DomainPattern::Exact(domain)
+        })
+    }
+}
+
+impl fmt::Display for DomainPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DomainPattern::Exact(domain) => write!(f, "{}", domain),
+            DomainPattern::Subdomains(domain) => write!(f, "*.{}", domain),
+        }
+    }
+}
+
+/// How the lists are written in the configuration.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DomainLists {
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+}
+
+/// Which destination domains links may point to. Deny entries win over allow
+/// entries, and an empty allow list allows every domain that is not denied.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(from = "DomainLists", into = "DomainLists")]
+pub struct DomainPolicy {
+    pub allow: Vec<DomainPattern>,
+    pub deny: Vec<DomainPattern>,
+}
+
+/// Invalid entries are logged and skipped, so a typo in one entry does not
+/// throw away the rest of the configuration.
+impl From<DomainLists> for DomainPolicy {
+    fn from(lists: DomainLists) -> Self {
+        let parse = |entries: Vec<String>| {
+            entries
+                .iter()
+                .filter_map(|entry| {
+                    entry
+                        .parse()
+                        .inspect_err(|e| tracing::warn!("Ignoring domain pattern: {}", e))
+                        .ok()
+                })
+                .collect()
+        };
+        Self {
+            allow: parse(lists.allow),
+            deny: parse(lists.deny),
+        }
+    }
+}
+
+impl From<DomainPolicy> for DomainLists {
+    fn from(policy: DomainPolicy) -> Self {
+        let print = |patterns: Vec<DomainPattern>| patterns.iter().map(|p| p.to_string()).collect();
+        Self {
+            allow: print(policy.allow),
+            deny: print(policy.deny),
+        }
+    }
+}
+
+impl DomainPolicy {
+    /// Refuses a URL whose host is denied, or missing from a non-empty allow
+    /// list. URLs without a host have nothing to match and pass, the URL policy
+    /// is what refuses them.
+    pub fn check(&self, url: &str) -> Result<(), DomainPolicyError> {
+        let Some(host) = Url::parse(url.trim()).ok().and_then(|url| {
+            url.host_str()
+                .map(|host| host.trim_end_matches('.').to_string())
+        }) else {
+            return Ok(());
+        };
+        if self.deny.iter().any(|pattern| pattern.matches(&host)) {
+            return Err(DomainPolicyError::Blocked(host));
+        }
+        if !self.allow.is_empty() && !self.allow.iter().any(|pattern| pattern.matches(&host)) {
+            return Err(DomainPolicyError::NotAllowed(host));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DomainPattern, DomainPolicy, DomainPolicyError};
+
+    #[test]
+    fn when_patterns_are_parsed_should_refuse_public_suffixes() {
+        let cases = [
+            (
+                "Example.COM.",
+                Ok(DomainPattern::Exact("example.com".into())),
+            ),
+            (
+                "*.example.co.uk",
+                Ok(DomainPattern::Subdomains("example.co.uk".into())),
+            ),
+            (
+                "*.bücher.de",
+                Ok(DomainPattern::Subdomains("xn--bcher-kva.de".into())),
+            ),
+            (
+                "*.co.uk",
+                Err(DomainPolicyError::PublicSuffix("*.co.uk".into())),
+            ),
+            (
+                "*.github.io",
+                Err(DomainPolicyError::PublicSuffix("*.github.io".into())),
+            ),
+            ("com", Err(DomainPolicyError::PublicSuffix("com".into()))),
+            (
+                "ex*ample.com",
+                Err(DomainPolicyError::InvalidPattern("ex*ample.com".into())),
+            ),
+            (
+                "10.0.0.1",
+                Err(DomainPolicyError::InvalidPattern("10.0.0.1".into())),
+            ),
+        ];
+
+        for (pattern, expected) in cases {
+            assert_eq!(pattern.parse::<DomainPattern>(), expected, "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn when_url_is_checked_should_apply_deny_before_allow() {
+        let policy: DomainPolicy = serde_json::from_value(serde_json::json!({
+            "allow": ["example.com", "*.example.com", "*.github.io"],
+            "deny": ["*.phish.example.com"]
+        }))
+        .unwrap();
+        // The public suffix wildcard was skipped
+        assert_eq!(policy.allow.len(), 2);
+
+        let cases = [
+            ("https://example.com/a", Ok(())),
+            ("https://www.example.com./a", Ok(())),
+            (
+                "https://login.phish.example.com",
+                Err(DomainPolicyError::Blocked("login.phish.example.com".into())),
+            ),
+            (
+                "https://notexample.com",
+                Err(DomainPolicyError::NotAllowed("notexample.com".into())),
+            ),
+            (
+                "https://someone.github.io",
+                Err(DomainPolicyError::NotAllowed("someone.github.io".into())),
+            ),
+        ];
+        for (url, expected) in cases {
+            assert_eq!(policy.check(url), expected, "{}", url);
+        }
+    }
+
+    #[test]
+    fn when_there_is_no_allow_list_should_only_refuse_denied_domains() {
+        let policy = DomainPolicy {
+            allow: vec![],
+            deny: vec!["phish.example".parse().unwrap()],
+        };
+
+        assert!(policy.check("https://anything.example.org").is_ok());
+        assert!(policy.check("https://phish.example/login").is_err());
+        // A subdomain needs its own entry or a wildcard
+        assert!(policy.check("https://www.phish.example/login").is_ok());
+    }
+}
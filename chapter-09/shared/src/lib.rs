@@ -1,7 +1,22 @@
 pub mod adapters;
+pub mod api_key;
+pub mod auth;
 pub mod configuration;
 pub mod core;
+pub mod cursor;
+pub mod domain_policy;
+pub mod jwt;
+pub mod normalise;
+pub mod password;
+pub mod query;
+pub mod rate_limit;
+pub mod rules;
+pub mod search;
+pub mod slug;
+pub mod tags;
+pub mod targets;
 pub mod url_info;
+pub mod url_policy;
 pub mod utils;
 pub use reqwest::Client;
-pub mod observability;
\ No newline at end of file
+pub mod observability;
@@ -0,0 +1,70 @@
+use sha2::{Digest, Sha256};
+use url::{ParseError, Url};
+
+/// Rewrites a destination URL into a canonical form, so that URLs that only
+/// differ cosmetically shorten to the same link.
+///
+/// The scheme and host are lowercased and default ports are dropped (both done
+/// by the URL parser), the fragment is removed and query parameters are sorted.
+/// Parameters keep their original encoding, only their order changes.
+pub fn normalise_url(url: &str) -> Result<String, ParseError> {
+    let mut url = Url::parse(url.trim())?;
+    url.set_fragment(None);
+
+    if let Some(query) = url.query() {
+        let mut params: Vec<&str> = query.split('&').filter(|param| !param.is_empty()).collect();
+        params.sort_unstable();
+        let query = params.join("&");
+        url.set_query((!query.is_empty()).then_some(query.as_str()));
+    }
+
+    Ok(url.to_string())
+}
+
+/// Hex encoded SHA-256 of the normalised URL, the `UrlHash` links are looked up by.
+pub fn url_hash(url: &str) -> Result<String, ParseError> {
+    let normalised = normalise_url(url)?;
+    Ok(format!("{:x}", Sha256::digest(normalised.as_bytes())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalise_url, url_hash};
+
+    #[test]
+    fn when_urls_differ_cosmetically_should_normalise_to_the_same_url() {
+        let cases = [
+            ("HTTPS://Example.COM/Path", "https://example.com/Path"),
+            ("https://example.com:443/", "https://example.com/"),
+            ("http://example.com:80/a", "http://example.com/a"),
+            ("http://example.com:8080/a", "http://example.com:8080/a"),
+            ("https://example.com/a#section", "https://example.com/a"),
+            (
+                "https://example.com/?b=2&a=1&a=0",
+                "https://example.com/?a=0&a=1&b=2",
+            ),
+            ("https://example.com/?", "https://example.com/"),
+            (
+                "https://example.com/?q=a%20b&p=1",
+                "https://example.com/?p=1&q=a%20b",
+            ),
+        ];
+
+        for (url, expected) in cases {
+            assert_eq!(normalise_url(url).unwrap(), expected, "{}", url);
+        }
+    }
+
+    #[test]
+    fn when_urls_normalise_the_same_should_hash_the_same() {
+        assert_eq!(
+            url_hash("https://EXAMPLE.com:443/?b=2&a=1#top").unwrap(),
+            url_hash("https://example.com/?a=1&b=2").unwrap()
+        );
+        assert_ne!(
+            url_hash("https://example.com/a").unwrap(),
+            url_hash("https://example.com/b").unwrap()
+        );
+        assert!(url_hash("not a url").is_err());
+    }
+}
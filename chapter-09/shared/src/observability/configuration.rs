@@ -1,9 +1,6 @@
 use anyhow::Result;
 
-use opentelemetry::{
-    global,
-    trace::TracerProvider,
-};
+use opentelemetry::{global, trace::TracerProvider};
 use opentelemetry_appender_tracing::layer;
 use opentelemetry_aws::detector::LambdaResourceDetector;
 use opentelemetry_otlp::{LogExporter, MetricExporter, SpanExporter};
@@ -37,7 +34,7 @@ fn init_tracer() -> SdkTracerProvider {
 
     let lambda_detector = LambdaResourceDetector {};
 
-    let tracer_provider = SdkTracerProvider::builder()
+    SdkTracerProvider::builder()
         .with_resource(OsResourceDetector.detect())
         .with_resource(ProcessResourceDetector.detect())
         .with_resource(SdkProvidedResourceDetector.detect())
@@ -46,9 +43,7 @@ fn init_tracer() -> SdkTracerProvider {
         .with_resource(lambda_detector.detect())
         .with_id_generator(RandomIdGenerator::default())
         .with_batch_exporter(exporter)
-        .build();
-
-    tracer_provider
+        .build()
 }
 
 // A Meter Provider is a factory for Meters
@@ -90,7 +85,7 @@ fn init_logger_provider() -> SdkLoggerProvider {
 
     let lambda_detector = LambdaResourceDetector {};
 
-    let logger_provider = SdkLoggerProvider::builder()
+    SdkLoggerProvider::builder()
         .with_resource(
             Resource::builder()
                 .with_service_name(
@@ -105,9 +100,7 @@ fn init_logger_provider() -> SdkLoggerProvider {
         .with_resource(TelemetryResourceDetector.detect())
         .with_resource(lambda_detector.detect())
         .with_simple_exporter(otlp_exporter)
-        .build();
-
-    logger_provider
+        .build()
 }
 
 pub fn init_otel() -> Result<OtelGuard> {
@@ -122,7 +115,7 @@ pub fn init_otel() -> Result<OtelGuard> {
         .add_directive("opentelemetry=off".parse().unwrap())
         .add_directive("tonic=off".parse().unwrap())
         .add_directive("h2=off".parse().unwrap());
-    
+
     let otel_layer = layer::OpenTelemetryTracingBridge::new(&logger).with_filter(filter_otel);
 
     tracing_subscriber::registry()
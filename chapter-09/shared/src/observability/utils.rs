@@ -1,6 +1,7 @@
 use cloudevents::Event;
 use opentelemetry::{
-    Context, SpanId, TraceFlags, TraceId, trace::{SpanContext, TraceState}
+    trace::{SpanContext, TraceState},
+    Context, SpanId, TraceFlags, TraceId,
 };
 use tracing_opentelemetry::OpenTelemetrySpanExt;
 
@@ -72,7 +73,8 @@ pub fn add_parent_context_from(span: &tracing::Span, cloud_event: &Event) {
 
     match remote_context {
         Some(remote_span_context) => {
-            let _ = span.set_parent(Context::new().with_remote_span_context(remote_span_context.clone()));
+            let _ = span
+                .set_parent(Context::new().with_remote_span_context(remote_span_context.clone()));
         }
         None => {
             tracing::warn!(
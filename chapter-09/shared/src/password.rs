@@ -0,0 +1,191 @@
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use thiserror::Error;
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+/// Hashing is deliberately slow, so very long passwords are refused up front.
+pub const MAX_PASSWORD_LENGTH: usize = 128;
+/// Wrong passwords allowed per link within one window.
+pub const MAX_PASSWORD_FAILURES: u32 = 5;
+pub const PASSWORD_FAILURE_WINDOW_SECONDS: u64 = 300;
+/// How long a correct password lets the visitor skip the form.
+pub const ACCESS_COOKIE_SECONDS: u64 = 3600;
+pub const ACCESS_COOKIE_NAME: &str = "link_access";
+
+/// Access cookies are sent for every path, so each link's is named after it
+/// and unlocking one link does not replace the cookie of another.
+pub fn access_cookie_name(link_id: &str) -> String {
+    format!("{}_{}", ACCESS_COOKIE_NAME, link_id)
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum PasswordError {
+    #[error(
+        "password must be between {MIN_PASSWORD_LENGTH} and {MAX_PASSWORD_LENGTH} characters long"
+    )]
+    InvalidLength,
+    #[error("password could not be hashed: {0}")]
+    Hashing(String),
+}
+
+/// The password of a protected link, as stored with it.
+#[derive(Clone, Default)]
+pub struct LinkPassword {
+    /// Argon2 hash in PHC string format.
+    pub hash: String,
+    /// Wrong attempts counted in `failure_window`.
+    pub failures: u32,
+    /// Window of the last wrong attempt, see `failure_window`.
+    pub failure_window: u64,
+}
+
+impl LinkPassword {
+    /// Whether wrong attempts in the window of `now` used up the allowance.
+    pub fn is_locked(&self, now: u64) -> bool {
+        self.failure_window == failure_window(now) && self.failures >= MAX_PASSWORD_FAILURES
+    }
+
+    pub fn verify(&self, password: &str) -> bool {
+        PasswordHash::new(&self.hash).is_ok_and(|hash| {
+            Argon2::default()
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok()
+        })
+    }
+}
+
+impl std::fmt::Debug for LinkPassword {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LinkPassword")
+            .field("failures", &self.failures)
+            .field("failure_window", &self.failure_window)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Wrong attempts are counted in fixed windows of `PASSWORD_FAILURE_WINDOW_SECONDS`.
+pub fn failure_window(now: u64) -> u64 {
+    now / PASSWORD_FAILURE_WINDOW_SECONDS
+}
+
+/// Seconds until the window of `now` ends and attempts are allowed again.
+pub fn seconds_until_unlocked(now: u64) -> u64 {
+    (failure_window(now) + 1) * PASSWORD_FAILURE_WINDOW_SECONDS - now
+}
+
+/// Validates a new password and hashes it with Argon2 and a random salt.
+pub fn hash_password(password: &str) -> Result<String, PasswordError> {
+    let length = password.chars().count();
+    if !(MIN_PASSWORD_LENGTH..=MAX_PASSWORD_LENGTH).contains(&length) {
+        return Err(PasswordError::InvalidLength);
+    }
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| PasswordError::Hashing(e.to_string()))
+}
+
+/// Signs the cookie that lets a visitor back into a protected link.
+///
+/// The value is `<expires_at>.<signature>`, the signature an HMAC-SHA256 of the
+/// link id and expiry, so a cookie only opens the link it was issued for.
+#[derive(Clone)]
+pub struct AccessSigner {
+    key: Vec<u8>,
+}
+
+impl AccessSigner {
+    pub fn new(key: impl Into<Vec<u8>>) -> Self {
+        Self { key: key.into() }
+    }
+
+    pub fn issue(&self, link_id: &str, expires_at: u64) -> String {
+        let signature =
+            URL_SAFE_NO_PAD.encode(self.mac(link_id, expires_at).finalize().into_bytes());
+        format!("{}.{}", expires_at, signature)
+    }
+
+    pub fn verify(&self, token: &str, link_id: &str, now: u64) -> bool {
+        let Some((expires_at, signature)) = token.split_once('.') else {
+            return false;
+        };
+        let (Ok(expires_at), Ok(signature)) =
+            (expires_at.parse::<u64>(), URL_SAFE_NO_PAD.decode(signature))
+        else {
+            return false;
+        };
+        expires_at > now
+            && self
+                .mac(link_id, expires_at)
+                .verify_slice(&signature)
+                .is_ok()
+    }
+
+    fn mac(&self, link_id: &str, expires_at: u64) -> HmacSha256 {
+        let mut mac =
+            HmacSha256::new_from_slice(&self.key).expect("HMAC can take a key of any size");
+        mac.update(format!("{}.{}", link_id, expires_at).as_bytes());
+        mac
+    }
+}
+
+impl std::fmt::Debug for AccessSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AccessSigner").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        failure_window, hash_password, AccessSigner, LinkPassword, PasswordError,
+        MAX_PASSWORD_FAILURES,
+    };
+
+    #[test]
+    fn when_password_is_hashed_should_only_verify_the_same_password() {
+        let password = LinkPassword {
+            hash: hash_password("correct horse").unwrap(),
+            ..Default::default()
+        };
+
+        assert!(password.hash.starts_with("$argon2"));
+        assert!(!password.hash.contains("correct horse"));
+        assert!(password.verify("correct horse"));
+        assert!(!password.verify("battery staple"));
+        assert_eq!(hash_password("short"), Err(PasswordError::InvalidLength));
+    }
+
+    #[test]
+    fn when_failures_reach_the_limit_should_lock_until_the_window_ends() {
+        let now = 1_700_000_000;
+        let password = LinkPassword {
+            failures: MAX_PASSWORD_FAILURES,
+            failure_window: failure_window(now),
+            ..Default::default()
+        };
+
+        assert!(password.is_locked(now));
+        assert!(!password.is_locked(now + super::PASSWORD_FAILURE_WINDOW_SECONDS));
+    }
+
+    #[test]
+    fn when_access_cookie_is_checked_should_match_link_and_expiry() {
+        let signer = AccessSigner::new("test-secret");
+        let token = signer.issue("abc123", 2_000);
+
+        assert!(signer.verify(&token, "abc123", 1_000));
+        assert!(!signer.verify(&token, "other", 1_000));
+        assert!(!signer.verify(&token, "abc123", 2_000));
+        assert!(!signer.verify(&token.replace("2000", "9000"), "abc123", 1_000));
+        assert!(!AccessSigner::new("other-secret").verify(&token, "abc123", 1_000));
+    }
+}
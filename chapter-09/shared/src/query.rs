@@ -0,0 +1,183 @@
+use crate::core::{QueryMode, ShortUrl};
+use std::collections::BTreeMap;
+use thiserror::Error;
+use url::form_urlencoded;
+use url::Url;
+
+/// The UTM parameters a link can be configured with.
+pub const UTM_PARAMETERS: [&str; 6] = [
+    "utm_source",
+    "utm_medium",
+    "utm_campaign",
+    "utm_term",
+    "utm_content",
+    "utm_id",
+];
+pub const MAX_UTM_VALUE_LENGTH: usize = 200;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum QueryError {
+    #[error("'{0}' is not a UTM parameter")]
+    UnknownUtm(String),
+    #[error("{0} must be between 1 and {MAX_UTM_VALUE_LENGTH} characters long")]
+    InvalidUtmValue(String),
+}
+
+pub fn validate_utm(utm: &BTreeMap<String, String>) -> Result<(), QueryError> {
+    for (name, value) in utm {
+        if !UTM_PARAMETERS.contains(&name.as_str()) {
+            return Err(QueryError::UnknownUtm(name.clone()));
+        }
+        if !(1..=MAX_UTM_VALUE_LENGTH).contains(&value.chars().count()) {
+            return Err(QueryError::InvalidUtmValue(name.clone()));
+        }
+    }
+    Ok(())
+}
+
+/// Where to send a visitor of `short_url` bound for `destination`, given the
+/// query string they added to the short link.
+///
+/// The link's UTM parameters are added first and the visitor cannot replace
+/// them, then the visitor's own parameters when the link merges them.
+/// Parameters the destination already has are kept as they are unless the link
+/// overrides them. The destination's own query is never re-encoded.
+pub fn redirect_location(
+    destination: &str,
+    short_url: &ShortUrl,
+    incoming: Option<&str>,
+) -> String {
+    let mut added: Vec<(String, String)> = short_url
+        .utm
+        .iter()
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    if short_url.query_mode == QueryMode::Merge {
+        added.extend(
+            form_urlencoded::parse(incoming.unwrap_or_default().as_bytes())
+                .into_owned()
+                .filter(|(name, _)| !name.is_empty() && !short_url.utm.contains_key(name)),
+        );
+    }
+    let Ok(mut url) = Url::parse(destination) else {
+        return destination.to_string();
+    };
+
+    let is_added = |segment: &&str| {
+        form_urlencoded::parse(segment.as_bytes())
+            .next()
+            .is_some_and(|(name, _)| added.iter().any(|(added, _)| *added == name))
+    };
+    let existing = url.query().unwrap_or_default().to_string();
+    let mut kept: Vec<&str> = existing.split('&').filter(|s| !s.is_empty()).collect();
+    if short_url.override_query {
+        kept.retain(|segment| !is_added(segment));
+    } else {
+        let present: Vec<String> = form_urlencoded::parse(existing.as_bytes())
+            .map(|(name, _)| name.into_owned())
+            .collect();
+        added.retain(|(name, _)| !present.contains(name));
+    }
+    if added.is_empty() {
+        return destination.to_string();
+    }
+
+    let added = form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(&added)
+        .finish();
+    kept.push(&added);
+    url.set_query(Some(&kept.join("&")));
+    url.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{redirect_location, validate_utm, QueryError};
+    use crate::core::{QueryMode, ShortUrl};
+    use std::collections::BTreeMap;
+
+    fn link(query_mode: QueryMode, override_query: bool, utm: &[(&str, &str)]) -> ShortUrl {
+        ShortUrl {
+            query_mode,
+            override_query,
+            utm: utm
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            ..ShortUrl::new("abc123".into(), "https://example.com".into())
+        }
+    }
+
+    #[test]
+    fn when_building_the_location_should_add_parameters_without_touching_the_destination() {
+        let cases = [
+            (
+                link(QueryMode::Ignore, false, &[]),
+                "https://example.com/a?x=1",
+                Some("ref=tw"),
+                "https://example.com/a?x=1",
+            ),
+            (
+                link(QueryMode::Merge, false, &[]),
+                "https://example.com/a?x=1",
+                Some("ref=tw&x=2"),
+                "https://example.com/a?x=1&ref=tw",
+            ),
+            (
+                link(QueryMode::Merge, true, &[]),
+                "https://example.com/a?x=1&y=a%20b",
+                Some("x=2"),
+                "https://example.com/a?y=a%20b&x=2",
+            ),
+            (
+                link(QueryMode::Merge, false, &[]),
+                "https://example.com/a#top",
+                Some("q=caf%C3%A9+%26+co"),
+                "https://example.com/a?q=caf%C3%A9+%26+co#top",
+            ),
+            (
+                link(QueryMode::Ignore, false, &[("utm_source", "news letter")]),
+                "https://example.com/a",
+                Some("utm_source=spoofed"),
+                "https://example.com/a?utm_source=news+letter",
+            ),
+            (
+                link(QueryMode::Merge, false, &[("utm_source", "newsletter")]),
+                "https://example.com/a?utm_source=site",
+                Some("utm_source=spoofed&utm_medium=email"),
+                "https://example.com/a?utm_source=site&utm_medium=email",
+            ),
+            (
+                link(QueryMode::Ignore, true, &[("utm_source", "newsletter")]),
+                "https://example.com/a?utm_source=site",
+                None,
+                "https://example.com/a?utm_source=newsletter",
+            ),
+        ];
+
+        for (short_url, destination, incoming, expected) in cases {
+            assert_eq!(
+                redirect_location(destination, &short_url, incoming),
+                expected,
+                "{:?} {:?}",
+                destination,
+                incoming
+            );
+        }
+    }
+
+    #[test]
+    fn when_utm_is_validated_should_only_accept_known_parameters() {
+        let utm = |name: &str, value: &str| BTreeMap::from([(name.to_string(), value.to_string())]);
+
+        assert_eq!(validate_utm(&utm("utm_campaign", "spring")), Ok(()));
+        assert_eq!(
+            validate_utm(&utm("ref", "spring")),
+            Err(QueryError::UnknownUtm("ref".into()))
+        );
+        assert_eq!(
+            validate_utm(&utm("utm_term", "")),
+            Err(QueryError::InvalidUtmValue("utm_term".into()))
+        );
+    }
+}
@@ -0,0 +1,289 @@
+use crate::adapters::map_sdk_error;
+use crate::core::RepositoryError;
+use async_trait::async_trait;
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::Mutex;
+
+#[cfg(any(test, feature = "mocks"))]
+use mockall::automock;
+
+/// Attempts at a conditional update before a busy bucket counts as empty.
+const MAX_UPDATE_ATTEMPTS: usize = 3;
+
+/// A token bucket: `capacity` requests at once, then `refill_per_second`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RateLimit {
+    pub capacity: u32,
+    pub refill_per_second: f64,
+}
+
+/// The outcome of taking a token, with what the `RateLimit-*` headers report.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimitDecision {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    /// Seconds until the bucket is full again.
+    pub reset_seconds: u64,
+    /// Seconds until the next token, 0 when one was taken.
+    pub retry_after_seconds: u64,
+}
+
+/// What is stored per key: the tokens left when it was last updated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bucket {
+    pub tokens: f64,
+    pub updated_at: u64,
+}
+
+impl RateLimit {
+    /// Takes a token from `bucket`, or from a full one when there is none yet.
+    /// Returns the bucket to store when a token was taken.
+    pub fn take(&self, bucket: Option<Bucket>, now: u64) -> (Option<Bucket>, RateLimitDecision) {
+        let capacity = f64::from(self.capacity);
+        let available = bucket.map_or(capacity, |bucket| {
+            let elapsed = now.saturating_sub(bucket.updated_at) as f64;
+            (bucket.tokens + elapsed * self.refill_per_second).min(capacity)
+        });
+        let seconds_for = |tokens: f64| (tokens / self.refill_per_second).ceil().max(0.0) as u64;
+
+        if available < 1.0 {
+            return (
+                None,
+                RateLimitDecision {
+                    allowed: false,
+                    limit: self.capacity,
+                    remaining: 0,
+                    reset_seconds: seconds_for(capacity - available),
+                    retry_after_seconds: seconds_for(1.0 - available).max(1),
+                },
+            );
+        }
+        let tokens = available - 1.0;
+        (
+            Some(Bucket {
+                tokens,
+                updated_at: now,
+            }),
+            RateLimitDecision {
+                allowed: true,
+                limit: self.capacity,
+                remaining: tokens.floor() as u32,
+                reset_seconds: seconds_for(capacity - tokens),
+                retry_after_seconds: 0,
+            },
+        )
+    }
+
+    /// The answer when the bucket could not be updated, because too many
+    /// requests raced for it.
+    fn contended(&self) -> RateLimitDecision {
+        RateLimitDecision {
+            allowed: false,
+            limit: self.capacity,
+            remaining: 0,
+            reset_seconds: 1,
+            retry_after_seconds: 1,
+        }
+    }
+}
+
+#[cfg_attr(any(test, feature = "mocks"), automock)]
+#[async_trait]
+pub trait RateLimiter: Debug {
+    /// Takes a token from the bucket of `key` at `now`, in epoch seconds.
+    async fn acquire(
+        &self,
+        key: &str,
+        limit: &RateLimit,
+        now: u64,
+    ) -> Result<RateLimitDecision, RepositoryError>;
+}
+
+/// Lets a function pick its limiter at start up, like `UrlRepository`.
+#[async_trait]
+impl<L: RateLimiter + Send + Sync + ?Sized> RateLimiter for Box<L> {
+    async fn acquire(
+        &self,
+        key: &str,
+        limit: &RateLimit,
+        now: u64,
+    ) -> Result<RateLimitDecision, RepositoryError> {
+        (**self).acquire(key, limit, now).await
+    }
+}
+
+/// Keeps one item per bucket. A token is taken with an update conditioned on
+/// the bucket being as it was read, so concurrent requests cannot both spend
+/// the same token. Items expire through the table's TTL once their bucket has
+/// refilled.
+#[derive(Debug)]
+pub struct DynamoDbRateLimiter {
+    table_name: String,
+    dynamodb_client: Client,
+}
+
+impl DynamoDbRateLimiter {
+    pub fn new(table_name: String, dynamodb_client: Client) -> Self {
+        Self {
+            table_name,
+            dynamodb_client,
+        }
+    }
+}
+
+#[async_trait]
+impl RateLimiter for DynamoDbRateLimiter {
+    #[tracing::instrument(skip(self, limit))]
+    async fn acquire(
+        &self,
+        key: &str,
+        limit: &RateLimit,
+        now: u64,
+    ) -> Result<RateLimitDecision, RepositoryError> {
+        for _ in 0..MAX_UPDATE_ATTEMPTS {
+            let item = self
+                .dynamodb_client
+                .get_item()
+                .table_name(&self.table_name)
+                .key("BucketKey", AttributeValue::S(key.to_string()))
+                .consistent_read(true)
+                .send()
+                .await
+                .map_err(|e| map_sdk_error("Error reading rate limit", e, RepositoryError::Fatal))?
+                .item;
+            // The condition compares the attributes exactly as they were read
+            let read = item.as_ref().and_then(|item| {
+                let tokens = item.get("Tokens")?;
+                let updated_at = item.get("UpdatedAt")?;
+                let bucket = Bucket {
+                    tokens: tokens.as_n().ok()?.parse().ok()?,
+                    updated_at: updated_at.as_n().ok()?.parse().ok()?,
+                };
+                Some((bucket, tokens.clone(), updated_at.clone()))
+            });
+
+            let (taken, decision) = limit.take(read.as_ref().map(|(bucket, ..)| *bucket), now);
+            let Some(taken) = taken else {
+                return Ok(decision);
+            };
+
+            let update = self
+                .dynamodb_client
+                .update_item()
+                .table_name(&self.table_name)
+                .key("BucketKey", AttributeValue::S(key.to_string()))
+                .update_expression(
+                    "SET Tokens = :tokens, UpdatedAt = :now, ExpiresAt = :expires_at",
+                )
+                .expression_attribute_values(":tokens", AttributeValue::N(taken.tokens.to_string()))
+                .expression_attribute_values(":now", AttributeValue::N(now.to_string()))
+                .expression_attribute_values(
+                    ":expires_at",
+                    AttributeValue::N((now + decision.reset_seconds).to_string()),
+                );
+            let update = match read {
+                Some((_, tokens, updated_at)) => update
+                    .condition_expression("Tokens = :read_tokens AND UpdatedAt = :read_at")
+                    .expression_attribute_values(":read_tokens", tokens)
+                    .expression_attribute_values(":read_at", updated_at),
+                None => update.condition_expression("attribute_not_exists(BucketKey)"),
+            };
+            match update.send().await.map_err(|e| {
+                map_sdk_error(
+                    "Error taking rate limit token",
+                    e,
+                    RepositoryError::Conflict,
+                )
+            }) {
+                Ok(_) => return Ok(decision),
+                // Another request took a token since, read the bucket again
+                Err(RepositoryError::Conflict(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(limit.contended())
+    }
+}
+
+/// A `RateLimiter` that keeps its buckets in memory, useful for local runs and tests.
+#[derive(Debug, Default)]
+pub struct InMemoryRateLimiter {
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl InMemoryRateLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl RateLimiter for InMemoryRateLimiter {
+    async fn acquire(
+        &self,
+        key: &str,
+        limit: &RateLimit,
+        now: u64,
+    ) -> Result<RateLimitDecision, RepositoryError> {
+        let mut buckets = self
+            .buckets
+            .lock()
+            .map_err(|e| RepositoryError::Fatal(format!("Error taking rate limit token: {}", e)))?;
+        let (taken, decision) = limit.take(buckets.get(key).copied(), now);
+        if let Some(taken) = taken {
+            buckets.insert(key.to_string(), taken);
+        }
+        Ok(decision)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{InMemoryRateLimiter, RateLimit, RateLimiter};
+
+    #[tokio::test]
+    async fn when_bucket_is_empty_should_refuse_until_it_refills() {
+        let limiter = InMemoryRateLimiter::new();
+        let limit = RateLimit {
+            capacity: 3,
+            refill_per_second: 0.5,
+        };
+
+        for remaining in [2, 1, 0] {
+            let decision = limiter.acquire("ip#1", &limit, 100).await.unwrap();
+            assert!(decision.allowed);
+            assert_eq!(decision.remaining, remaining);
+        }
+
+        let decision = limiter.acquire("ip#1", &limit, 100).await.unwrap();
+        assert!(!decision.allowed);
+        assert_eq!(decision.retry_after_seconds, 2);
+        assert_eq!(decision.reset_seconds, 6);
+        // Other keys have buckets of their own
+        assert!(limiter.acquire("ip#2", &limit, 100).await.unwrap().allowed);
+
+        let decision = limiter.acquire("ip#1", &limit, 102).await.unwrap();
+        assert!(decision.allowed);
+        assert_eq!(decision.remaining, 0);
+    }
+
+    #[test]
+    fn when_bucket_refills_should_not_go_over_capacity() {
+        let limit = RateLimit {
+            capacity: 5,
+            refill_per_second: 1.0,
+        };
+        let (bucket, _) = limit.take(None, 0);
+
+        let (bucket, decision) = limit.take(bucket, 3600);
+
+        assert_eq!(bucket.unwrap().tokens, 4.0);
+        assert_eq!(decision.remaining, 4);
+        assert_eq!(decision.reset_seconds, 1);
+    }
+}
@@ -0,0 +1,287 @@
+use crate::core::RedirectRule;
+use crate::normalise::url_hash;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use thiserror::Error;
+
+/// The most redirect rules a single link can have.
+pub const MAX_RULES: usize = 20;
+const MAX_LANGUAGE_LENGTH: usize = 35;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Device {
+    Ios,
+    Android,
+    Desktop,
+}
+
+impl Device {
+    /// Anything that is neither iOS nor Android, including a missing user agent,
+    /// counts as desktop.
+    pub fn from_user_agent(user_agent: &str) -> Self {
+        if ["iPhone", "iPad", "iPod"]
+            .iter()
+            .any(|device| user_agent.contains(device))
+        {
+            Device::Ios
+        } else if user_agent.contains("Android") {
+            Device::Android
+        } else {
+            Device::Desktop
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Device::Ios => "ios",
+            Device::Android => "android",
+            Device::Desktop => "desktop",
+        }
+    }
+}
+
+impl FromStr for Device {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ios" => Ok(Device::Ios),
+            "android" => Ok(Device::Android),
+            "desktop" => Ok(Device::Desktop),
+            other => Err(format!("unknown device '{}'", other)),
+        }
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum RuleError {
+    #[error("a link can have at most {MAX_RULES} rules")]
+    TooMany,
+    #[error("rule destination '{0}' must be an absolute URL")]
+    InvalidDestination(String),
+    #[error("rule {0} has no condition, the link's own destination is the fallback")]
+    NoCondition(usize),
+    #[error("'{0}' is not a two-letter country code")]
+    InvalidCountry(String),
+    #[error("'{0}' is not a language tag")]
+    InvalidLanguage(String),
+}
+
+/// What a request tells about the visitor, for matching rules.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Visitor {
+    pub device: Device,
+    /// Upper case ISO 3166-1 alpha-2 code, when a CDN told us.
+    pub country: Option<String>,
+    /// The most preferred language tag, lower case.
+    pub language: Option<String>,
+}
+
+impl RedirectRule {
+    /// A rule matches when every condition it sets does, conditions it leaves
+    /// empty match anyone.
+    pub fn matches(&self, visitor: &Visitor) -> bool {
+        let device = self.device.is_none_or(|device| device == visitor.device);
+        let country = self.countries.is_empty()
+            || visitor
+                .country
+                .as_ref()
+                .is_some_and(|country| self.countries.contains(country));
+        // "fr" matches "fr-ca", "fr-ca" only matches itself
+        let language = self.languages.is_empty()
+            || visitor.language.as_deref().is_some_and(|tag| {
+                self.languages.iter().any(|language| {
+                    tag == language
+                        || tag
+                            .strip_prefix(language.as_str())
+                            .is_some_and(|rest| rest.starts_with('-'))
+                })
+            });
+        device && country && language
+    }
+}
+
+/// The destination of the first rule matching the visitor, in order.
+pub fn first_match<'a>(rules: &'a [RedirectRule], visitor: &Visitor) -> Option<&'a str> {
+    rules
+        .iter()
+        .find(|rule| rule.matches(visitor))
+        .map(|rule| rule.destination.as_str())
+}
+
+/// Validates rules and normalises the case of their countries and languages,
+/// the way `Visitor` reports them.
+pub fn normalise_rules(rules: Vec<RedirectRule>) -> Result<Vec<RedirectRule>, RuleError> {
+    if rules.len() > MAX_RULES {
+        return Err(RuleError::TooMany);
+    }
+    rules
+        .into_iter()
+        .enumerate()
+        .map(|(index, rule)| {
+            if url_hash(&rule.destination).is_err() {
+                return Err(RuleError::InvalidDestination(rule.destination));
+            }
+            if rule.device.is_none() && rule.countries.is_empty() && rule.languages.is_empty() {
+                return Err(RuleError::NoCondition(index));
+            }
+            let countries = rule
+                .countries
+                .into_iter()
+                .map(|country| {
+                    if country.len() == 2 && country.chars().all(|c| c.is_ascii_alphabetic()) {
+                        Ok(country.to_ascii_uppercase())
+                    } else {
+                        Err(RuleError::InvalidCountry(country))
+                    }
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let languages = rule
+                .languages
+                .into_iter()
+                .map(|language| {
+                    if (1..=MAX_LANGUAGE_LENGTH).contains(&language.len())
+                        && language
+                            .chars()
+                            .all(|c| c.is_ascii_alphanumeric() || c == '-')
+                    {
+                        Ok(language.to_ascii_lowercase())
+                    } else {
+                        Err(RuleError::InvalidLanguage(language))
+                    }
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(RedirectRule {
+                countries,
+                languages,
+                ..rule
+            })
+        })
+        .collect()
+}
+
+/// The most preferred language of an `Accept-Language` header, lower case.
+/// Wildcards and languages with a weight of 0 are skipped.
+pub fn preferred_language(accept_language: &str) -> Option<String> {
+    accept_language
+        .split(',')
+        .filter_map(|entry| {
+            let mut parts = entry.split(';').map(str::trim);
+            let tag = parts.next().filter(|tag| !tag.is_empty() && *tag != "*")?;
+            let weight = parts
+                .find_map(|param| param.strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.parse::<f32>().ok())?;
+            (weight > 0.0).then(|| (tag.to_ascii_lowercase(), weight))
+        })
+        // The first of equally weighted languages wins, as the header lists them
+        .fold(
+            None,
+            |best: Option<(String, f32)>, (tag, weight)| match best {
+                Some((_, best_weight)) if best_weight >= weight => best,
+                _ => Some((tag, weight)),
+            },
+        )
+        .map(|(tag, _)| tag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalise_rules, preferred_language, Device, RuleError};
+    use crate::core::RedirectRule;
+
+    #[test]
+    fn when_user_agent_is_parsed_should_detect_the_platform() {
+        let cases = [
+            (
+                "Mozilla/5.0 (iPhone; CPU iPhone OS 17_0 like Mac OS X)",
+                Device::Ios,
+            ),
+            ("Mozilla/5.0 (iPad; CPU OS 16_6 like Mac OS X)", Device::Ios),
+            ("Mozilla/5.0 (Linux; Android 14; Pixel 8)", Device::Android),
+            (
+                "Mozilla/5.0 (Windows NT 10.0; Win64; x64) Chrome/120",
+                Device::Desktop,
+            ),
+            ("", Device::Desktop),
+        ];
+
+        for (user_agent, device) in cases {
+            assert_eq!(
+                Device::from_user_agent(user_agent),
+                device,
+                "{}",
+                user_agent
+            );
+        }
+    }
+
+    #[test]
+    fn when_accept_language_is_parsed_should_pick_the_highest_weight() {
+        let cases = [
+            ("fr-CH, fr;q=0.9, en;q=0.8", Some("fr-ch")),
+            ("en;q=0.5, de", Some("de")),
+            ("*;q=1, es;q=0.1", Some("es")),
+            ("nl;q=0", None),
+            ("", None),
+        ];
+
+        for (header, language) in cases {
+            assert_eq!(
+                preferred_language(header).as_deref(),
+                language,
+                "{}",
+                header
+            );
+        }
+    }
+
+    #[test]
+    fn when_rules_are_normalised_should_check_and_case_their_conditions() {
+        let rule = RedirectRule {
+            device: None,
+            countries: vec!["be".into()],
+            languages: vec!["FR".into()],
+            destination: "https://example.com/fr".into(),
+        };
+
+        let normalised = normalise_rules(vec![rule.clone()]).unwrap();
+        assert_eq!(normalised[0].countries, ["BE"]);
+        assert_eq!(normalised[0].languages, ["fr"]);
+
+        let cases = [
+            (
+                RedirectRule {
+                    countries: vec!["BEL".into()],
+                    ..rule.clone()
+                },
+                RuleError::InvalidCountry("BEL".into()),
+            ),
+            (
+                RedirectRule {
+                    languages: vec!["fr_FR".into()],
+                    ..rule.clone()
+                },
+                RuleError::InvalidLanguage("fr_FR".into()),
+            ),
+            (
+                RedirectRule {
+                    destination: "example.com".into(),
+                    ..rule.clone()
+                },
+                RuleError::InvalidDestination("example.com".into()),
+            ),
+            (
+                RedirectRule {
+                    countries: vec![],
+                    languages: vec![],
+                    ..rule
+                },
+                RuleError::NoCondition(0),
+            ),
+        ];
+        for (rule, error) in cases {
+            assert_eq!(normalise_rules(vec![rule]), Err(error));
+        }
+    }
+}
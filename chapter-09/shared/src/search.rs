@@ -0,0 +1,145 @@
+use crate::core::PageKey;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// A term in the title counts as much as this many in the description.
+pub const TITLE_WEIGHT: u32 = 3;
+/// Longer queries are cut, each term costs a query on the index.
+pub const MAX_QUERY_TERMS: usize = 8;
+
+/// Search results are ranked on every request, so their pages are offsets.
+const OFFSET_KEY: &str = "Offset";
+const MIN_TERM_LENGTH: usize = 2;
+const STOP_WORDS: [&str; 24] = [
+    "a", "an", "and", "are", "as", "at", "be", "by", "for", "from", "in", "is", "it", "of", "on",
+    "or", "that", "the", "this", "to", "was", "with", "you", "your",
+];
+
+/// Splits text into lowercase alphanumeric terms, without stop words.
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .map(str::to_lowercase)
+        .filter(|term| term.chars().count() >= MIN_TERM_LENGTH)
+        .filter(|term| !STOP_WORDS.contains(&term.as_str()))
+        .collect()
+}
+
+/// The terms a link is indexed under, with how much each one weighs for it.
+pub fn index_terms(title: Option<&str>, description: Option<&str>) -> BTreeMap<String, u32> {
+    let mut terms = BTreeMap::new();
+    for term in title.map(tokenize).unwrap_or_default() {
+        *terms.entry(term).or_insert(0) += TITLE_WEIGHT;
+    }
+    for term in description.map(tokenize).unwrap_or_default() {
+        *terms.entry(term).or_insert(0) += 1;
+    }
+    terms
+}
+
+/// The distinct terms of a search query, at most `MAX_QUERY_TERMS` of them.
+pub fn query_terms(query: &str) -> Vec<String> {
+    let mut seen = BTreeSet::new();
+    tokenize(query)
+        .into_iter()
+        .filter(|term| seen.insert(term.clone()))
+        .take(MAX_QUERY_TERMS)
+        .collect()
+}
+
+/// Partition key of the search index: one partition per owner and term.
+pub fn term_key(owner_id: &str, term: &str) -> String {
+    format!("{}#{}", owner_id, term)
+}
+
+/// Ranks links from the postings of each query term, as `(link_id, weight)` pairs.
+///
+/// A link scores the sum of its term weights, each divided by how common the
+/// term is, so links matching more and rarer terms come first. Ties are broken
+/// by link id to keep pages stable.
+pub fn rank(postings: &HashMap<String, Vec<(String, u32)>>) -> Vec<String> {
+    let mut scores: HashMap<&str, f64> = HashMap::new();
+    for links in postings.values() {
+        let rarity = 1.0 / (1.0 + (links.len() as f64).ln());
+        for (link_id, weight) in links {
+            *scores.entry(link_id.as_str()).or_insert(0.0) += f64::from(*weight) * rarity;
+        }
+    }
+
+    let mut ranked: Vec<(&str, f64)> = scores.into_iter().collect();
+    ranked.sort_by(|(a_id, a_score), (b_id, b_score)| {
+        b_score.total_cmp(a_score).then_with(|| a_id.cmp(b_id))
+    });
+    ranked
+        .into_iter()
+        .map(|(link_id, _)| link_id.to_string())
+        .collect()
+}
+
+/// Cuts a page out of ranked link ids, returning the key of the next page if any.
+pub fn page_of(
+    ranked: Vec<String>,
+    start_key: Option<&PageKey>,
+    limit: usize,
+) -> (Vec<String>, Option<PageKey>) {
+    let offset = start_key
+        .and_then(|key| key.get(OFFSET_KEY))
+        .and_then(|offset| offset.parse::<usize>().ok())
+        .unwrap_or_default();
+    let end = offset.saturating_add(limit);
+    let next_key =
+        (end < ranked.len()).then(|| PageKey::from([(OFFSET_KEY.to_string(), end.to_string())]));
+    let page = ranked.into_iter().skip(offset).take(limit).collect();
+    (page, next_key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{index_terms, page_of, query_terms, rank, tokenize, TITLE_WEIGHT};
+    use std::collections::HashMap;
+
+    #[test]
+    fn when_text_is_tokenized_should_drop_stop_words_and_punctuation() {
+        assert_eq!(
+            tokenize("Running Rust on AWS Lambda: the fast way!"),
+            ["running", "rust", "aws", "lambda", "fast", "way"]
+        );
+        assert_eq!(query_terms("rust RUST lambdas"), ["rust", "lambdas"]);
+    }
+
+    #[test]
+    fn when_terms_are_in_the_title_should_weigh_more() {
+        let terms = index_terms(Some("Rust lambdas"), Some("Deploying Rust to AWS"));
+
+        assert_eq!(terms["rust"], TITLE_WEIGHT + 1);
+        assert_eq!(terms["lambdas"], TITLE_WEIGHT);
+        assert_eq!(terms["aws"], 1);
+    }
+
+    #[test]
+    fn when_ranking_should_favour_more_and_rarer_matching_terms() {
+        let postings = HashMap::from([
+            (
+                "rust".to_string(),
+                vec![
+                    ("a".to_string(), 3),
+                    ("b".to_string(), 3),
+                    ("c".to_string(), 3),
+                ],
+            ),
+            ("lambdas".to_string(), vec![("b".to_string(), 1)]),
+        ]);
+
+        assert_eq!(rank(&postings), ["b", "a", "c"]);
+    }
+
+    #[test]
+    fn when_paging_ranked_results_should_continue_from_the_offset() {
+        let ranked: Vec<String> = ["a", "b", "c"].map(String::from).to_vec();
+
+        let (first, next_key) = page_of(ranked.clone(), None, 2);
+        let (second, last_key) = page_of(ranked, next_key.as_ref(), 2);
+
+        assert_eq!(first, ["a", "b"]);
+        assert_eq!(second, ["c"]);
+        assert!(last_key.is_none());
+    }
+}
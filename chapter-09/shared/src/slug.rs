@@ -0,0 +1,95 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum SlugError {
+    #[error("slug must be between {min} and {max} characters long")]
+    InvalidLength { min: usize, max: usize },
+    #[error("slug contains the invalid character '{0}'")]
+    InvalidCharacter(char),
+    #[error("slug '{0}' is reserved")]
+    Reserved(String),
+}
+
+/// Rules a custom slug has to follow before it can be used as a link id.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SlugPolicy {
+    pub allowed_characters: String,
+    pub min_length: usize,
+    pub max_length: usize,
+    pub reserved: Vec<String>,
+}
+
+impl Default for SlugPolicy {
+    fn default() -> Self {
+        Self {
+            allowed_characters: "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789-_"
+                .to_string(),
+            min_length: 3,
+            max_length: 64,
+            reserved: vec!["links".to_string()],
+        }
+    }
+}
+
+impl SlugPolicy {
+    pub fn validate(&self, slug: &str) -> Result<(), SlugError> {
+        let length = slug.chars().count();
+        if length < self.min_length || length > self.max_length {
+            return Err(SlugError::InvalidLength {
+                min: self.min_length,
+                max: self.max_length,
+            });
+        }
+        if let Some(c) = slug.chars().find(|c| !self.allowed_characters.contains(*c)) {
+            return Err(SlugError::InvalidCharacter(c));
+        }
+        if self
+            .reserved
+            .iter()
+            .any(|reserved| reserved.eq_ignore_ascii_case(slug))
+        {
+            return Err(SlugError::Reserved(slug.to_string()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SlugError, SlugPolicy};
+
+    #[test]
+    fn when_slug_follows_policy_should_be_valid() {
+        let policy = SlugPolicy::default();
+
+        assert_eq!(policy.validate("summer-sale_2024"), Ok(()));
+    }
+
+    #[test]
+    fn when_slug_breaks_policy_should_be_rejected() {
+        let policy = SlugPolicy {
+            max_length: 8,
+            reserved: vec!["links".to_string(), "admin".to_string()],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            policy.validate("ab"),
+            Err(SlugError::InvalidLength { min: 3, max: 8 })
+        );
+        assert_eq!(
+            policy.validate("too-long-slug"),
+            Err(SlugError::InvalidLength { min: 3, max: 8 })
+        );
+        assert_eq!(
+            policy.validate("a/b/c"),
+            Err(SlugError::InvalidCharacter('/'))
+        );
+        assert_eq!(
+            policy.validate("Admin"),
+            Err(SlugError::Reserved("Admin".to_string()))
+        );
+    }
+}
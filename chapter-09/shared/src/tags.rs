@@ -0,0 +1,53 @@
+use thiserror::Error;
+
+/// The most tags a single link can carry.
+pub const MAX_TAGS: usize = 20;
+pub const MAX_TAG_LENGTH: usize = 32;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum TagError {
+    #[error("tags must be between 1 and {MAX_TAG_LENGTH} characters long")]
+    InvalidLength,
+    #[error("tag contains the invalid character '{0}'")]
+    InvalidCharacter(char),
+    #[error("a link can have at most {MAX_TAGS} tags")]
+    TooMany,
+}
+
+/// Tags are letters, digits, `-` and `_`, so they can be joined with the owner
+/// into the tag index key without escaping.
+pub fn validate_tag(tag: &str) -> Result<(), TagError> {
+    let length = tag.chars().count();
+    if length == 0 || length > MAX_TAG_LENGTH {
+        return Err(TagError::InvalidLength);
+    }
+    if let Some(c) = tag
+        .chars()
+        .find(|c| !c.is_ascii_alphanumeric() && *c != '-' && *c != '_')
+    {
+        return Err(TagError::InvalidCharacter(c));
+    }
+    Ok(())
+}
+
+/// Partition key of the tag index: one partition per owner and tag.
+pub fn tag_key(owner_id: &str, tag: &str) -> String {
+    format!("{}#{}", owner_id, tag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{validate_tag, TagError};
+
+    #[test]
+    fn when_tag_is_validated_should_accept_only_short_plain_tags() {
+        assert_eq!(validate_tag("spring-sale_2025"), Ok(()));
+        assert_eq!(validate_tag(""), Err(TagError::InvalidLength));
+        assert_eq!(validate_tag(&"a".repeat(33)), Err(TagError::InvalidLength));
+        assert_eq!(validate_tag("a#b"), Err(TagError::InvalidCharacter('#')));
+        assert_eq!(
+            validate_tag("two words"),
+            Err(TagError::InvalidCharacter(' '))
+        );
+    }
+}
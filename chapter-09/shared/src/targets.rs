@@ -0,0 +1,123 @@
+use crate::core::Target;
+use crate::normalise::url_hash;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+/// The most variants a single link can split its traffic between.
+pub const MAX_TARGETS: usize = 10;
+pub const MAX_TARGET_WEIGHT: u32 = 1000;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum TargetError {
+    #[error("a link can have at most {MAX_TARGETS} targets")]
+    TooMany,
+    #[error("target '{0}' must be an absolute URL")]
+    InvalidUrl(String),
+    #[error("target weights must be at most {MAX_TARGET_WEIGHT}")]
+    InvalidWeight,
+    #[error("at least one target must have a weight above 0")]
+    NoWeight,
+}
+
+/// Targets need absolute URLs and some weight between them. A weight of 0
+/// keeps a variant on the link without sending it any traffic.
+pub fn validate_targets(targets: &[Target]) -> Result<(), TargetError> {
+    if targets.len() > MAX_TARGETS {
+        return Err(TargetError::TooMany);
+    }
+    if let Some(target) = targets.iter().find(|target| url_hash(&target.url).is_err()) {
+        return Err(TargetError::InvalidUrl(target.url.clone()));
+    }
+    if targets
+        .iter()
+        .any(|target| target.weight > MAX_TARGET_WEIGHT)
+    {
+        return Err(TargetError::InvalidWeight);
+    }
+    if !targets.is_empty() && targets.iter().all(|target| target.weight == 0) {
+        return Err(TargetError::NoWeight);
+    }
+    Ok(())
+}
+
+/// Picks the index of a target in proportion to the weights.
+///
+/// The choice is a hash of `key` rather than a random draw, so the same visitor
+/// keeps landing on the same variant and the split is reproducible in tests.
+pub fn choose_target(targets: &[Target], key: &str) -> Option<usize> {
+    let total: u64 = targets.iter().map(|target| u64::from(target.weight)).sum();
+    if total == 0 {
+        return None;
+    }
+    let digest = Sha256::digest(key.as_bytes());
+    let mut point = u64::from_be_bytes(digest[..8].try_into().ok()?) % total;
+    for (index, target) in targets.iter().enumerate() {
+        let weight = u64::from(target.weight);
+        if point < weight {
+            return Some(index);
+        }
+        point -= weight;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{choose_target, validate_targets, TargetError, MAX_TARGETS};
+    use crate::core::Target;
+
+    fn target(url: &str, weight: u32) -> Target {
+        Target {
+            url: url.to_string(),
+            weight,
+            clicks: 0,
+        }
+    }
+
+    #[test]
+    fn when_choosing_should_follow_the_weights_and_repeat_for_the_same_key() {
+        let targets = [
+            target("https://example.com/a", 3),
+            target("https://example.com/b", 1),
+            target("https://example.com/paused", 0),
+        ];
+
+        let mut chosen = [0; 3];
+        for visitor in 0..4000 {
+            chosen[choose_target(&targets, &format!("visitor-{}", visitor)).unwrap()] += 1;
+        }
+
+        assert!((2800..3200).contains(&chosen[0]), "{:?}", chosen);
+        assert_eq!(chosen[0] + chosen[1], 4000);
+        assert_eq!(chosen[2], 0);
+        assert_eq!(
+            choose_target(&targets, "visitor-1"),
+            choose_target(&targets, "visitor-1")
+        );
+        assert_eq!(choose_target(&[], "visitor-1"), None);
+    }
+
+    #[test]
+    fn when_targets_are_validated_should_reject_bad_urls_and_weights() {
+        assert_eq!(
+            validate_targets(&[target("https://example.com", 1)]),
+            Ok(())
+        );
+        assert_eq!(
+            validate_targets(&[target("not a url", 1)]),
+            Err(TargetError::InvalidUrl("not a url".into()))
+        );
+        assert_eq!(
+            validate_targets(&[target("https://example.com", 1001)]),
+            Err(TargetError::InvalidWeight)
+        );
+        assert_eq!(
+            validate_targets(&[target("https://example.com", 0)]),
+            Err(TargetError::NoWeight)
+        );
+        assert_eq!(
+            validate_targets(&vec![target("https://example.com", 1); MAX_TARGETS + 1]),
+            Err(TargetError::TooMany)
+        );
+    }
+}
@@ -1,11 +1,15 @@
-use crate::core::UrlInfo;
+use crate::core::{RepositoryError, UrlInfo};
+use crate::url_policy::{UrlPolicy, UrlPolicyError};
 use async_trait::async_trait;
 use reqwest::Client;
 use scraper::{selector::Selector, Html};
+use std::time::Duration;
 
+/// Fetches links from inside our network, so only what `url_policy` allows.
 #[derive(Debug)]
 pub struct HttpUrlInfo {
     pub http_client: Client,
+    pub url_policy: UrlPolicy,
 }
 
 #[derive(Debug, Default)]
@@ -16,21 +20,51 @@ pub struct UrlDetails {
 }
 
 impl HttpUrlInfo {
-    pub fn new(http_client: Client) -> Self {
-        Self { http_client }
+    /// The client resolves hosts and follows redirects under `url_policy`.
+    pub fn new(url_policy: UrlPolicy, timeout: Duration) -> Result<Self, reqwest::Error> {
+        let http_client = url_policy.client_builder().timeout(timeout).build()?;
+        Ok(Self {
+            http_client,
+            url_policy,
+        })
     }
 }
 
+/// Whether the URL policy refused a connection or a redirect hop somewhere
+/// down the error's causes.
+fn refused_by_policy(error: &reqwest::Error) -> bool {
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(error);
+    while let Some(error) = source {
+        if error.is::<UrlPolicyError>() {
+            return true;
+        }
+        source = error.source();
+    }
+    false
+}
+
 #[async_trait]
 impl UrlInfo for HttpUrlInfo {
     #[tracing::instrument(skip(self, url))]
-    async fn fetch_details(&self, url: &str) -> Result<UrlDetails, String> {
-        let response = self
-            .http_client
-            .get(url)
-            .send()
-            .await
-            .map_err(|e| format!("Cannot scrape '{}': {}", url, e))?;
+    async fn fetch_details(&self, url: &str) -> Result<UrlDetails, RepositoryError> {
+        // The resolver never sees IP address hosts, they are checked here
+        if let Err(e) = self.url_policy.check(url) {
+            return Err(RepositoryError::Validation(format!(
+                "Cannot scrape '{}': {}",
+                url, e
+            )));
+        }
+        let response = self.http_client.get(url).send().await.map_err(|e| {
+            let message = format!("Cannot scrape '{}': {}", url, e);
+            // Retrying would be refused again
+            if e.is_builder() || refused_by_policy(&e) {
+                RepositoryError::Validation(message)
+            } else if e.is_timeout() || e.is_connect() || e.is_request() {
+                RepositoryError::Transient(message)
+            } else {
+                RepositoryError::Fatal(message)
+            }
+        })?;
 
         let content_type = response
             .headers()
@@ -75,3 +109,33 @@ impl UrlInfo for HttpUrlInfo {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::HttpUrlInfo;
+    use crate::core::{RepositoryError, UrlInfo};
+    use crate::url_policy::UrlPolicy;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn when_url_points_inside_our_network_should_refuse_to_fetch_it() {
+        let policy = UrlPolicy::default()
+            .with_resolved("metadata.example", &["169.254.169.254".parse().unwrap()]);
+        let url_info = HttpUrlInfo::new(policy, Duration::from_secs(1)).unwrap();
+
+        for url in [
+            "http://169.254.169.254/latest/meta-data/",
+            "http://metadata.example/latest/meta-data/",
+            "ftp://example.com/file",
+        ] {
+            let error = url_info.fetch_details(url).await.unwrap_err();
+
+            assert!(
+                matches!(error, RepositoryError::Validation(_)),
+                "{}: {:?}",
+                url,
+                error
+            );
+        }
+    }
+}
@@ -0,0 +1,318 @@
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::redirect;
+use reqwest::ClientBuilder;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use thiserror::Error;
+use url::{Host, Url};
+
+/// Redirects followed when fetching a URL, each hop checked like the first.
+pub const MAX_REDIRECTS: usize = 5;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum UrlPolicyError {
+    #[error("URL must be at most {0} characters long")]
+    TooLong(usize),
+    #[error("URL is not valid: {0}")]
+    Invalid(String),
+    #[error("scheme '{0}' is not allowed, only http and https are")]
+    Scheme(String),
+    #[error("URL has no host")]
+    NoHost,
+    #[error("host '{0}' could not be resolved")]
+    Unresolvable(String),
+    #[error("host '{host}' points to the non-public address {address}")]
+    NonPublicAddress { host: String, address: IpAddr },
+    #[error("more than {MAX_REDIRECTS} redirects")]
+    TooManyRedirects,
+}
+
+/// What a URL has to be before we store it or fetch it from inside our network.
+///
+/// `check` looks at the URL alone, `validate` also resolves its host. A client
+/// from `client_builder` applies the same rules to every connection and
+/// redirect hop, so a host that resolves differently later, or a redirect to
+/// an internal address, is refused too.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UrlPolicy {
+    pub max_length: usize,
+    /// Hosts resolved to fixed addresses instead of through DNS.
+    #[serde(skip)]
+    pub resolved: HashMap<String, Vec<IpAddr>>,
+}
+
+impl Default for UrlPolicy {
+    fn default() -> Self {
+        Self {
+            max_length: 2048,
+            resolved: HashMap::new(),
+        }
+    }
+}
+
+impl UrlPolicy {
+    pub fn with_resolved(mut self, host: &str, addresses: &[IpAddr]) -> Self {
+        self.resolved
+            .insert(host.to_ascii_lowercase(), addresses.to_vec());
+        self
+    }
+
+    /// Checks the length, the scheme and, when the host is an IP address, the address.
+    pub fn check(&self, url: &str) -> Result<Url, UrlPolicyError> {
+        if url.len() > self.max_length {
+            return Err(UrlPolicyError::TooLong(self.max_length));
+        }
+        let url = Url::parse(url.trim()).map_err(|e| UrlPolicyError::Invalid(e.to_string()))?;
+        self.check_parsed(&url)?;
+        Ok(url)
+    }
+
+    fn check_parsed(&self, url: &Url) -> Result<(), UrlPolicyError> {
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(UrlPolicyError::Scheme(url.scheme().to_string()));
+        }
+        let address = match url.host() {
+            None => return Err(UrlPolicyError::NoHost),
+            Some(Host::Domain(_)) => return Ok(()),
+            Some(Host::Ipv4(address)) => IpAddr::V4(address),
+            Some(Host::Ipv6(address)) => IpAddr::V6(address),
+        };
+        if !is_public(address) {
+            return Err(UrlPolicyError::NonPublicAddress {
+                host: address.to_string(),
+                address,
+            });
+        }
+        Ok(())
+    }
+
+    /// `check`, then requires every address the host resolves to to be public.
+    pub async fn validate(&self, url: &str) -> Result<Url, UrlPolicyError> {
+        let url = self.check(url)?;
+        if let Some(Host::Domain(host)) = url.host() {
+            self.resolve_public(host).await?;
+        }
+        Ok(url)
+    }
+
+    /// Resolves a host, refusing it if any of its addresses is not public,
+    /// since the connection could use any of them.
+    async fn resolve_public(&self, host: &str) -> Result<Vec<IpAddr>, UrlPolicyError> {
+        let host = host.to_ascii_lowercase();
+        let addresses = match self.resolved.get(&host) {
+            Some(addresses) => addresses.clone(),
+            None => tokio::net::lookup_host((host.as_str(), 0))
+                .await
+                .map_err(|_| UrlPolicyError::Unresolvable(host.clone()))?
+                .map(|address| address.ip())
+                .collect(),
+        };
+        if addresses.is_empty() {
+            return Err(UrlPolicyError::Unresolvable(host));
+        }
+        if let Some(address) = addresses.iter().find(|address| !is_public(**address)) {
+            return Err(UrlPolicyError::NonPublicAddress {
+                host,
+                address: *address,
+            });
+        }
+        Ok(addresses)
+    }
+
+    /// Stops at `MAX_REDIRECTS` and at any hop `check` refuses. Hosts of the
+    /// hops are resolved by the client's resolver, which checks them as well.
+    pub fn redirect_policy(&self) -> redirect::Policy {
+        let policy = self.clone();
+        redirect::Policy::custom(move |attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                return attempt.error(UrlPolicyError::TooManyRedirects);
+            }
+            match policy.check_parsed(attempt.url()) {
+                Ok(()) => attempt.follow(),
+                Err(e) => attempt.error(e),
+            }
+        })
+    }
+
+    /// A client builder resolving and redirecting under this policy. Proxies
+    /// are turned off, they would resolve hosts where we cannot check them.
+    pub fn client_builder(&self) -> ClientBuilder {
+        reqwest::Client::builder()
+            .dns_resolver(self.clone())
+            .redirect(self.redirect_policy())
+            .no_proxy()
+    }
+}
+
+impl Resolve for UrlPolicy {
+    fn resolve(&self, name: Name) -> Resolving {
+        let policy = self.clone();
+        Box::pin(async move {
+            let addresses = policy.resolve_public(name.as_str()).await?;
+            // The connector fills in the port of the URL
+            let addrs: Addrs = Box::new(
+                addresses
+                    .into_iter()
+                    .map(|address| SocketAddr::new(address, 0)),
+            );
+            Ok(addrs)
+        })
+    }
+}
+
+/// Whether an address is reachable on the public internet, as opposed to
+/// private, loopback, link-local (cloud metadata lives there), shared,
+/// documentation, multicast and other special-purpose ranges.
+pub fn is_public(address: IpAddr) -> bool {
+    match address {
+        IpAddr::V4(address) => is_public_v4(address),
+        IpAddr::V6(address) => is_public_v6(address),
+    }
+}
+
+fn is_public_v4(address: Ipv4Addr) -> bool {
+    let [a, b, c, _] = address.octets();
+    !(address.is_private()
+        || address.is_loopback()
+        || address.is_link_local()
+        || address.is_unspecified()
+        || address.is_broadcast()
+        || address.is_multicast()
+        || address.is_documentation()
+        || a == 0
+        // Carrier-grade NAT, 100.64.0.0/10
+        || (a == 100 && b & 0xc0 == 64)
+        // IETF protocol assignments, 192.0.0.0/24
+        || (a == 192 && b == 0 && c == 0)
+        // Benchmarking, 198.18.0.0/15
+        || (a == 198 && b & 0xfe == 18)
+        // Reserved, 240.0.0.0/4
+        || a >= 240)
+}
+
+fn is_public_v6(address: Ipv6Addr) -> bool {
+    if let Some(address) = address.to_ipv4_mapped() {
+        return is_public_v4(address);
+    }
+    let segments = address.segments();
+    // NAT64, 64:ff9b::/96, reaches the IPv4 address in its last 32 bits
+    if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+        let [.., high, low] = segments;
+        return is_public_v4(Ipv4Addr::from((u32::from(high) << 16) | u32::from(low)));
+    }
+    !(address.is_loopback()
+        || address.is_unspecified()
+        || address.is_multicast()
+        // Unique local, fc00::/7
+        || segments[0] & 0xfe00 == 0xfc00
+        // Link-local, fe80::/10
+        || segments[0] & 0xffc0 == 0xfe80
+        // Documentation, 2001:db8::/32
+        || (segments[0] == 0x2001 && segments[1] == 0xdb8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_public, UrlPolicy, UrlPolicyError};
+    use std::net::IpAddr;
+
+    #[test]
+    fn when_addresses_are_classified_should_only_accept_public_ones() {
+        let cases = [
+            ("93.184.216.34", true),
+            ("2606:2800:220:1:248:1893:25c8:1946", true),
+            ("169.254.169.254", false),
+            ("127.0.0.1", false),
+            ("10.0.0.1", false),
+            ("172.16.5.4", false),
+            ("192.168.1.1", false),
+            ("100.64.0.1", false),
+            ("0.0.0.0", false),
+            ("::1", false),
+            ("fd00:ec2::254", false),
+            ("fe80::1", false),
+            ("::ffff:169.254.169.254", false),
+            ("64:ff9b::a9fe:a9fe", false),
+        ];
+
+        for (address, public) in cases {
+            assert_eq!(
+                is_public(address.parse::<IpAddr>().unwrap()),
+                public,
+                "{}",
+                address
+            );
+        }
+    }
+
+    #[test]
+    fn when_url_is_checked_should_refuse_other_schemes_long_urls_and_internal_hosts() {
+        let policy = UrlPolicy {
+            max_length: 40,
+            ..Default::default()
+        };
+
+        assert!(policy.check("https://example.com/a").is_ok());
+        let cases = [
+            ("file:///etc/passwd", UrlPolicyError::Scheme("file".into())),
+            (
+                "gopher://example.com",
+                UrlPolicyError::Scheme("gopher".into()),
+            ),
+            (
+                "http://169.254.169.254/latest",
+                UrlPolicyError::NonPublicAddress {
+                    host: "169.254.169.254".into(),
+                    address: "169.254.169.254".parse().unwrap(),
+                },
+            ),
+            // Hex and shortened IPv4 forms are parsed into the address they mean
+            (
+                "http://0x7f.1/",
+                UrlPolicyError::NonPublicAddress {
+                    host: "127.0.0.1".into(),
+                    address: "127.0.0.1".parse().unwrap(),
+                },
+            ),
+            (
+                "https://example.com/a-path-that-is-far-too-long",
+                UrlPolicyError::TooLong(40),
+            ),
+        ];
+        for (url, error) in cases {
+            assert_eq!(policy.check(url).unwrap_err(), error, "{}", url);
+        }
+    }
+
+    #[tokio::test]
+    async fn when_url_is_validated_should_refuse_hosts_resolving_to_internal_addresses() {
+        let policy = UrlPolicy::default()
+            .with_resolved("example.com", &["93.184.216.34".parse().unwrap()])
+            .with_resolved(
+                "rebind.example",
+                &[
+                    "93.184.216.34".parse().unwrap(),
+                    "10.0.0.1".parse().unwrap(),
+                ],
+            )
+            .with_resolved("nowhere.example", &[]);
+
+        assert!(policy.validate("https://example.com").await.is_ok());
+        assert_eq!(
+            policy.validate("https://rebind.example").await.unwrap_err(),
+            UrlPolicyError::NonPublicAddress {
+                host: "rebind.example".into(),
+                address: "10.0.0.1".parse().unwrap(),
+            }
+        );
+        assert_eq!(
+            policy
+                .validate("https://nowhere.example")
+                .await
+                .unwrap_err(),
+            UrlPolicyError::Unresolvable("nowhere.example".into())
+        );
+    }
+}
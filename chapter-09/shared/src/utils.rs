@@ -1,11 +1,24 @@
+use crate::api_key::ApiKeyError;
+use crate::configuration::ConfigurationError;
+use crate::core::RepositoryError;
+use crate::jwt::JwtError;
+use crate::rate_limit::RateLimitDecision;
 use lambda_http::http::StatusCode;
 use lambda_http::{Body, Error, Response};
 use serde::Serialize;
 
-pub fn redirect_response(location: &str) -> Result<Response<Body>, Error> {
+/// Seconds a client is asked to wait before retrying a throttled or transient failure.
+const RETRY_AFTER_SECONDS: u64 = 1;
+
+pub fn redirect_response(
+    status: &StatusCode,
+    location: &str,
+    cache_control: &str,
+) -> Result<Response<Body>, Error> {
     let response = Response::builder()
-        .status(&StatusCode::FOUND)
+        .status(status)
         .header("Location", location)
+        .header("Cache-Control", cache_control)
         .body(Body::Empty)
         .map_err(Box::new)?;
 
@@ -30,3 +43,168 @@ pub fn json_response(status: &StatusCode, body: &impl Serialize) -> Result<Respo
 
     Ok(response)
 }
+
+pub fn html_response(status: &StatusCode, html: String) -> Result<Response<Body>, Error> {
+    let response = Response::builder()
+        .status(status)
+        .header("content-type", "text/html; charset=utf-8")
+        .body(Body::Text(html))
+        .map_err(Box::new)?;
+
+    Ok(response)
+}
+
+/// Escapes text, such as scraped page titles, for HTML content and attributes.
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+pub fn json_error_response(status: &StatusCode, message: &str) -> Result<Response<Body>, Error> {
+    json_response(status, &serde_json::json!({ "error": message }))
+}
+
+pub fn repository_error_response(error: &RepositoryError) -> Result<Response<Body>, Error> {
+    let status = match error {
+        RepositoryError::NotFound(_) => StatusCode::NOT_FOUND,
+        RepositoryError::AlreadyExists(_) | RepositoryError::Conflict(_) => StatusCode::CONFLICT,
+        RepositoryError::Throttled(_) => StatusCode::TOO_MANY_REQUESTS,
+        RepositoryError::Validation(_) => StatusCode::BAD_REQUEST,
+        RepositoryError::Transient(_) => StatusCode::SERVICE_UNAVAILABLE,
+        RepositoryError::Fatal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+
+    let mut response = Response::builder().status(status);
+    if error.is_retryable() {
+        response = response.header("Retry-After", RETRY_AFTER_SECONDS.to_string());
+    }
+
+    let response = response.body(Body::Empty).map_err(Box::new)?;
+
+    Ok(response)
+}
+
+/// A 503: without its configuration a function cannot tell who may do what.
+pub fn configuration_error_response(error: &ConfigurationError) -> Result<Response<Body>, Error> {
+    let response = Response::builder()
+        .status(StatusCode::SERVICE_UNAVAILABLE)
+        .header("Retry-After", RETRY_AFTER_SECONDS.to_string())
+        .header("content-type", "application/json")
+        .body(Body::Text(
+            serde_json::json!({ "error": error.to_string() }).to_string(),
+        ))
+        .map_err(Box::new)?;
+
+    Ok(response)
+}
+
+/// A 401 when no API key was sent, asking for one, and a 403 when it was wrong.
+pub fn api_key_error_response(error: &ApiKeyError) -> Result<Response<Body>, Error> {
+    let response = match error {
+        ApiKeyError::Missing => Response::builder()
+            .status(StatusCode::UNAUTHORIZED)
+            .header("WWW-Authenticate", "Bearer"),
+        ApiKeyError::Invalid => Response::builder().status(StatusCode::FORBIDDEN),
+    };
+    let response = response
+        .header("content-type", "application/json")
+        .body(Body::Text(
+            serde_json::json!({ "error": error.to_string() }).to_string(),
+        ))
+        .map_err(Box::new)?;
+
+    Ok(response)
+}
+
+/// A 401 for a missing or invalid bearer token, or a 503 when the keys to check
+/// it with could not be loaded, which is no fault of the client.
+pub fn jwt_error_response(error: &JwtError) -> Result<Response<Body>, Error> {
+    let response = match error {
+        JwtError::KeysUnavailable(_) => {
+            return json_error_response(
+                &StatusCode::SERVICE_UNAVAILABLE,
+                "the token cannot be checked right now",
+            )
+        }
+        JwtError::Missing => Response::builder()
+            .status(StatusCode::UNAUTHORIZED)
+            .header("WWW-Authenticate", "Bearer"),
+        _ => Response::builder()
+            .status(StatusCode::UNAUTHORIZED)
+            .header("WWW-Authenticate", "Bearer error=\"invalid_token\""),
+    };
+    let response = response
+        .header("content-type", "application/json")
+        .body(Body::Text(
+            serde_json::json!({ "error": error.to_string() }).to_string(),
+        ))
+        .map_err(Box::new)?;
+
+    Ok(response)
+}
+
+/// A 429 telling the client when to retry, with the `RateLimit-*` headers of
+/// the limit it ran into.
+pub fn rate_limited_response(decision: &RateLimitDecision) -> Result<Response<Body>, Error> {
+    let response = Response::builder()
+        .status(StatusCode::TOO_MANY_REQUESTS)
+        .header("content-type", "application/json")
+        .header("Retry-After", decision.retry_after_seconds.to_string())
+        .header("RateLimit-Limit", decision.limit.to_string())
+        .header("RateLimit-Remaining", decision.remaining.to_string())
+        .header("RateLimit-Reset", decision.reset_seconds.to_string())
+        .body(Body::Text(
+            serde_json::json!({ "error": "rate limit exceeded" }).to_string(),
+        ))
+        .map_err(Box::new)?;
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape_html, repository_error_response};
+    use crate::core::RepositoryError;
+
+    #[test]
+    fn repository_errors_should_map_to_status_codes() {
+        let cases = [
+            (RepositoryError::NotFound("x".into()), 404, false),
+            (RepositoryError::AlreadyExists("x".into()), 409, false),
+            (RepositoryError::Conflict("x".into()), 409, false),
+            (RepositoryError::Throttled("x".into()), 429, true),
+            (RepositoryError::Validation("x".into()), 400, false),
+            (RepositoryError::Transient("x".into()), 503, true),
+            (RepositoryError::Fatal("x".into()), 500, false),
+        ];
+
+        for (error, status, has_retry_after) in cases {
+            let response = repository_error_response(&error).unwrap();
+            assert_eq!(response.status(), status, "{:?}", error);
+            assert_eq!(
+                response.headers().contains_key("Retry-After"),
+                has_retry_after,
+                "{:?}",
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn when_text_is_escaped_should_be_inert_in_html() {
+        assert_eq!(
+            escape_html(r#"<script>alert("x & 'y'")</script>"#),
+            "&lt;script&gt;alert(&quot;x &amp; &#39;y&#39;&quot;)&lt;/script&gt;"
+        );
+    }
+}